@@ -0,0 +1,3 @@
+DELETE FROM enabled_modules WHERE config_id = 'console';
+DELETE FROM cmdchars WHERE config_id = 'console';
+DELETE FROM configs WHERE id = 'console';
@@ -0,0 +1,2 @@
+INSERT INTO configs (id) VALUES ('console');
+INSERT INTO cmdchars (config_id, channel, cmdchars) VALUES ('console', '%', '!');
@@ -0,0 +1,5 @@
+DROP TABLE mx_permissions;
+
+DELETE FROM enabled_modules WHERE config_id = 'matrix';
+DELETE FROM cmdchars WHERE config_id = 'matrix';
+DELETE FROM configs WHERE id = 'matrix';
@@ -0,0 +1,10 @@
+INSERT INTO configs (id) VALUES ('matrix');
+INSERT INTO cmdchars (config_id, channel, cmdchars) VALUES ('matrix', '%', '!');
+
+CREATE TABLE mx_permissions (
+	config_id TEXT NOT NULL,
+	user_id TEXT NOT NULL,
+	flags BIGINT NOT NULL,
+	PRIMARY KEY (config_id, user_id),
+	CONSTRAINT fk_config FOREIGN KEY (config_id) REFERENCES configs(id)
+);
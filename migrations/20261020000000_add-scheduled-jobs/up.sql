@@ -0,0 +1,9 @@
+CREATE TABLE scheduled_jobs (
+	id BIGSERIAL PRIMARY KEY,
+	module TEXT NOT NULL,
+	name TEXT NOT NULL,
+	due TIMESTAMPTZ NOT NULL,
+	payload TEXT NOT NULL
+);
+
+CREATE INDEX scheduled_jobs_module ON scheduled_jobs (module);
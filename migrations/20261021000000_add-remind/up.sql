@@ -0,0 +1,13 @@
+CREATE TABLE mod_remind (
+	id BIGSERIAL PRIMARY KEY,
+	config_id TEXT NOT NULL,
+	target TEXT NOT NULL, -- channel string to deliver to
+	user_string TEXT NOT NULL,
+	user_pretty TEXT NOT NULL,
+	due TIMESTAMPTZ NOT NULL,
+	message TEXT NOT NULL,
+	job_id BIGINT, -- NULL until the job is scheduled
+	CONSTRAINT fk_config FOREIGN KEY (config_id) REFERENCES configs(id)
+);
+
+CREATE INDEX mod_remind_user ON mod_remind (config_id, user_string);
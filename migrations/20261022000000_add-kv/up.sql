@@ -0,0 +1,11 @@
+-- Key-value storage for modules; see rustbot/src/lib/kv.rs. The scope columns are empty strings
+-- rather than NULL when a key is not scoped by them, so that they can be part of the primary key.
+CREATE TABLE kv (
+	module TEXT NOT NULL,
+	config_id TEXT NOT NULL,
+	channel TEXT NOT NULL,
+	user_string TEXT NOT NULL,
+	key TEXT NOT NULL,
+	value JSONB NOT NULL,
+	PRIMARY KEY (module, config_id, channel, user_string, key)
+);
@@ -0,0 +1,8 @@
+-- Migrations that modules ship themselves, via Meta::migration
+CREATE TABLE module_migrations (
+	module TEXT NOT NULL,
+	version BIGINT NOT NULL,
+	down TEXT NOT NULL, -- kept so that the schema can be undone once the module is gone
+	applied TIMESTAMPTZ NOT NULL DEFAULT now(),
+	PRIMARY KEY (module, version)
+);
@@ -0,0 +1,15 @@
+CREATE TABLE audit_log (
+	id BIGSERIAL PRIMARY KEY,
+	at TIMESTAMPTZ NOT NULL DEFAULT now(),
+	config_id TEXT NOT NULL, -- no foreign key, so that entries outlive their config
+	user_string TEXT NOT NULL,
+	channel_string TEXT NOT NULL,
+	module TEXT NOT NULL, -- "core" for core commands
+	command TEXT NOT NULL,
+	args TEXT NOT NULL,
+	outcome TEXT NOT NULL -- "ok", "denied", or "failed: <error>"
+);
+
+CREATE INDEX audit_log_at ON audit_log (at);
+CREATE INDEX audit_log_user ON audit_log (user_string, at);
+CREATE INDEX audit_log_command ON audit_log (command, at);
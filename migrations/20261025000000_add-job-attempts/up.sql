@@ -0,0 +1,2 @@
+-- How many times each stored job has been run, so that one that keeps failing is eventually given up on
+ALTER TABLE scheduled_jobs ADD COLUMN attempts INT NOT NULL DEFAULT 0;
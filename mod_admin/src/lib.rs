@@ -4,17 +4,52 @@ mod bash;
 mod db;
 mod raw;
 
+rustbot::manifest!();
+
 #[no_mangle]
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("raw", Command::new(raw::raw).req_perms(Perms::Raw));
-    meta.cmd("join", Command::new(raw::join).req_perms(Perms::Raw));
-    meta.cmd("part", Command::new(raw::part).req_perms(Perms::Raw));
-    meta.cmd("dmsg", Command::new(raw::dmsg).req_perms(Perms::Raw));
-    meta.cmd("imsg", Command::new(raw::imsg).req_perms(Perms::Raw));
+    meta.cmd(
+        "raw",
+        Command::new(raw::raw)
+            .req_perms(Perms::Raw)
+            .description("send a raw IRC line")
+            .usage("<config_id> <line...>"),
+    );
+    meta.cmd(
+        "join",
+        Command::new(raw::join)
+            .req_perms(Perms::Raw)
+            .description("join an IRC channel, and rejoin it on reconnect")
+            .usage("<config_id> <channel>"),
+    );
+    meta.cmd(
+        "part",
+        Command::new(raw::part)
+            .req_perms(Perms::Raw)
+            .description("leave an IRC channel")
+            .usage("<config_id> <channel>"),
+    );
+    meta.cmd(
+        "dmsg",
+        Command::new(raw::dmsg)
+            .req_perms(Perms::Raw)
+            .description("send a message to a Discord channel")
+            .usage(raw::DmsgArgs::describe_expected()),
+    );
+    meta.cmd(
+        "imsg",
+        Command::new(raw::imsg)
+            .req_perms(Perms::Raw)
+            .description("send a message to an IRC channel")
+            .usage("<config_id> <channel> <message...>"),
+    );
 
     meta.cmd("q", Command::new(db::query).req_perms(Perms::Database));
 
-    meta.cmd("whoami", Command::new(whoami));
+    meta.cmd(
+        "whoami",
+        Command::new(whoami).description("show who the bot thinks you are, and your permissions"),
+    );
 
     meta.cmd("bash", Command::new(bash::bash).req_perms(Perms::Eval));
     meta.cmd("bashl", Command::new(bash::bashl).req_perms(Perms::Eval));
@@ -1,12 +1,20 @@
 use rustbot::prelude::*;
 
+#[derive(Arg)]
+pub struct DmsgArgs<'a> {
+    config: Atom<'a>,
+    guild: Atom<'a>,
+    channel: Atom<'a>,
+    message: Rest<'a>,
+}
+
 pub fn dmsg(ctx: &dyn Context, args: &str) -> Result<()> {
-    parse_args! {args,
-        config: Atom,
-        guild: Atom,
-        channel: Atom,
-        message: Rest,
-    }
+    let DmsgArgs {
+        config,
+        guild,
+        channel,
+        message,
+    } = DmsgArgs::parse_full(args)?;
 
     let mut channel: &str = &channel;
     if channel.chars().next().unwrap() == '#' {
@@ -1,68 +1,80 @@
-use lazy_static::lazy_static;
-use regex::Regex;
 use rustbot::prelude::*;
-use std::borrow::Cow;
 
-mod format;
-#[cfg(tests)]
-mod tests;
+rustbot::manifest!();
 
 #[no_mangle]
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("bridge", Command::new(bridge).req_perms(Perms::Admin));
+    meta.cmd(
+        "bridge",
+        CommandGroup::new()
+            .req_perms(Perms::Admin)
+            .description("show, set, or clear (with \"none\") this channel's bridge key")
+            .sub("none", Command::new(clear_bridge))
+            .fallback(Command::new(bridge).usage(BridgeArgs::describe_expected()))
+            .into(),
+    );
 
     meta.handle(HandleType::All, Box::new(do_bridge));
 }
 
-lazy_static! {
-    static ref ANTIPING_RE: Regex = Regex::new(r"\b[a-zA-Z0-9]").unwrap();
+#[derive(Arg)]
+struct BridgeArgs<'a> {
+    #[arg(rest, optional)]
+    key: Option<&'a str>,
 }
 
 fn bridge(ctx: &dyn Context, args: &str) -> Result<()> {
+    let BridgeArgs { key } = BridgeArgs::parse_full(args)?;
+
     let mut db = ctx.bot().sql().lock();
-    if args.is_empty() {
-        let key = db.query(
-            "SELECT bridge_key FROM mod_bridge WHERE config_id = $1 AND channel_id = $2",
-            &[&ctx.config_id(), &ctx.source().channel_string()],
-        )?;
-        if key.is_empty() {
-            return ctx.say("no bridge key found");
+    match key {
+        None => {
+            let key = db.query(
+                "SELECT bridge_key FROM mod_bridge WHERE config_id = $1 AND channel_id = $2",
+                &[&ctx.config_id(), &ctx.source().channel_string()],
+            )?;
+            if key.is_empty() {
+                return ctx.say("no bridge key found");
+            }
+
+            let conf = ctx.config_id();
+            let chan = ctx.source().channel_string();
+
+            let chans = db.query(
+                "SELECT config_id, channel_id FROM mod_bridge WHERE bridge_key = (SELECT bridge_key FROM mod_bridge WHERE config_id = $1 AND channel_id = $2) AND config_id != $1 AND channel_id != $2",
+                &[&conf, &chan],
+            )?;
+            let chans_str = chans
+                .iter()
+                .map(|row| format!("{}:{}", row.get::<_, String>(0), row.get::<_, String>(1)))
+                .collect::<Vec<_>>();
+
+            ctx.say(&format!(
+                "bridge key '{}', bridged channels: {:?}",
+                key.first().unwrap().get::<_, String>(0),
+                chans_str
+            ))
         }
+        Some(key) => {
+            db.execute(
+                "INSERT INTO mod_bridge (config_id, channel_id, bridge_key) VALUES ($1, $2, $3) ON CONFLICT (config_id, channel_id) DO UPDATE SET bridge_key = $3",
+                &[&ctx.config_id(), &ctx.source().channel_string(), &key],
+            )?;
 
-        let conf = ctx.config_id();
-        let chan = ctx.source().channel_string();
-
-        let chans = db.query(
-            "SELECT config_id, channel_id FROM mod_bridge WHERE bridge_key = (SELECT bridge_key FROM mod_bridge WHERE config_id = $1 AND channel_id = $2) AND config_id != $1 AND channel_id != $2",
-            &[&conf, &chan],
-        )?;
-        let chans_str = chans
-            .iter()
-            .map(|row| format!("{}:{}", row.get::<_, String>(0), row.get::<_, String>(1)))
-            .collect::<Vec<_>>();
-
-        ctx.say(&format!(
-            "bridge key '{}', bridged channels: {:?}",
-            key.get(0).unwrap().get::<_, String>(0),
-            chans_str
-        ))
-    } else if args == "none" {
-        let n = db.execute(
-            "DELETE FROM mod_bridge WHERE config_id = $1 AND channel_id = $2",
-            &[&ctx.config_id(), &ctx.source().channel_string()],
-        )?;
-        if n != 1 {
-            ctx.say("there is no bridge key to clear")
-        } else {
-            ctx.say("bridge key cleared")
+            ctx.say(&format!("bridge key set to '{key}'"))
         }
-    } else {
-        db.execute(
-            "INSERT INTO mod_bridge (config_id, channel_id, bridge_key) VALUES ($1, $2, $3) ON CONFLICT (config_id, channel_id) DO UPDATE SET bridge_key = $3",
-            &[&ctx.config_id(), &ctx.source().channel_string(), &args],
-        )?;
+    }
+}
 
-        ctx.say(&format!("bridge key set to '{args}'"))
+fn clear_bridge(ctx: &dyn Context, _args: &str) -> Result<()> {
+    let n = ctx.bot().sql().lock().execute(
+        "DELETE FROM mod_bridge WHERE config_id = $1 AND channel_id = $2",
+        &[&ctx.config_id(), &ctx.source().channel_string()],
+    )?;
+    if n != 1 {
+        ctx.say("there is no bridge key to clear")
+    } else {
+        ctx.say("bridge key cleared")
     }
 }
 
@@ -82,49 +94,26 @@ fn do_bridge(ctx: &dyn Context, _typ: HandleType, msg: &str) -> Result<()> {
         return Ok(());
     }
 
-    let (user, spans): (&dyn Fn(Cow<'_, str>) -> Span<'_>, Vec<Span>) =
-        if let Some((Some(g), _, _)) = ctx.source().get_discord_params() {
-            (
-                &|user| span!(Format::Bold; "<{}>", user),
-                spans! {ctx.bot().dis_unprocess_message(conf, &format!("{g}"), msg)?},
-            )
-        } else if ctx.source().get_irc_params().is_some() {
-            if msg.starts_with(1 as char) && msg.ends_with(1 as char) {
-                let ctcp = &msg[1..msg.len() - 1];
-                let parts = ctcp.splitn(2, ' ').collect::<Vec<_>>();
-                match parts[0] {
-                    "ACTION" => {
-                        let v = format::irc_parse(parts[1]);
-                        (&|user| span!(Format::Bold; "* {}", user), v)
-                    }
-                    _ => {
-                        warn!("unexpected CTCP message {:?} {:?} in do_bridge", parts[0], parts[1]);
-                        return Ok(());
-                    }
-                }
-            } else {
-                let v = format::irc_parse(msg);
-                (&|user| span!(Format::Bold; "<{}>", user), v)
-            }
-        } else {
-            (&|user| span!(Format::Bold; "<{}>", user), spans! {msg})
-        };
+    let (action, spans) = match ctx.source().parse_message(msg)? {
+        Some(MessageText::Plain(spans)) => (false, spans),
+        Some(MessageText::Action(spans)) => (true, spans),
+        None => return Ok(()),
+    };
 
     for row in &chans {
         let tconf = row.get::<_, String>(0);
         let tchan = row.get::<_, String>(1);
 
-        if tchan.starts_with("irc:") {
-            let user_pretty = ctx.source().user_pretty();
-
-            let user_pretty = ANTIPING_RE.replace_all(&user_pretty, "$0\u{feff}");
-
-            let msg = Message::Prefixed(spans! {user(user_pretty), " "}, spans.clone());
-            ctx.bot().send_message(&tconf, &tchan, msg)?;
+        let user_pretty = ctx.source().user_pretty();
+        let user = ctx.bot().quiet_name(&tconf, &tchan, &user_pretty);
+        let user = if action {
+            span!(Format::Bold; "* {}", user)
         } else {
-            let msg = Message::Prefixed(spans! {user(ctx.source().user_pretty()), " "}, spans.clone());
-            ctx.bot().send_message(&tconf, &tchan, msg)?;
-        }
+            span!(Format::Bold; "<{}>", user)
+        };
+
+        let msg = Message::Prefixed(spans! {user, " "}, spans.clone());
+        ctx.bot().send_message(&tconf, &tchan, msg)?;
     }
     Ok(())
 }
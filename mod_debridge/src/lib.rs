@@ -1,39 +1,107 @@
 use lazy_static::lazy_static;
 use regex::Regex;
 use rustbot::prelude::*;
-use std::borrow::Cow;
+
+rustbot::manifest!();
 
 #[no_mangle]
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("isbridge", Command::new(isbridge).req_perms(Perms::Admin));
+    meta.cmd(
+        "isbridge",
+        CommandGroup::new()
+            .req_perms(Perms::Admin)
+            .description("manage the users whose messages are relayed from a bridge")
+            .sub("list", Command::new(list).usage(ListArgs::describe_expected()))
+            .sub("set", Command::new(set).usage(SetArgs::describe_expected()))
+            .sub("clear", Command::new(clear).usage(ClearArgs::describe_expected()))
+            .fallback(Command::new(set_or_clear).usage(SetOrClearArgs::describe_expected()))
+            .into(),
+    );
 
     meta.handle(HandleType::All, Box::new(do_debridge));
 }
 
-fn isbridge(ctx: &dyn Context, args: &str) -> Result<()> {
-    let mut db = ctx.bot().sql().lock();
-
-    let args: Vec<&str> = args.splitn(3, char::is_whitespace).collect();
+#[derive(Arg)]
+struct ListArgs<'a> {
+    config: Atom<'a>,
+}
 
-    let config = args[0];
-    let user = *args.get(1).unwrap_or(&"");
-    let spec = *args.get(2).unwrap_or(&"");
+fn list(ctx: &dyn Context, args: &str) -> Result<()> {
+    let ListArgs { config } = ListArgs::parse_full(args)?;
 
-    if user == "" {
-        return ctx.reply(Message::Simple("todo".to_string()));
+    let rows = ctx.bot().sql().lock().query(
+        "SELECT source_user, spec FROM mod_debridge WHERE config_id = $1 ORDER BY source_user",
+        &[&&*config],
+    )?;
+    if rows.is_empty() {
+        return ctx.say(&format!("no bridge users for {}", &*config));
     }
 
-    if spec == "" {
-        db.execute(
-            "DELETE FROM mod_debridge WHERE config_id = $1 AND source_user = $2",
-            &[&config, &user],
-        )?;
-    } else {
-        db.execute(
-            "INSERT INTO mod_debridge (config_id, source_user, spec) VALUES ($1, $2, $3) ON CONFLICT (config_id, source_user) DO UPDATE SET spec = $3",
-            &[&config, &user, &spec],
-        )?;
+    ctx.reply(Message::List {
+        prefix: format!("bridge users for {}: ", &*config).into(),
+        sep: ", ".into(),
+        items: rows
+            .iter()
+            .map(|row| format!("{} ({})", row.get::<_, String>(0), row.get::<_, String>(1)).into())
+            .collect(),
+    })
+}
+
+#[derive(Arg)]
+struct SetArgs<'a> {
+    config: Atom<'a>,
+    user: Atom<'a>,
+    spec: Rest<'a>,
+}
+
+fn set(ctx: &dyn Context, args: &str) -> Result<()> {
+    let SetArgs { config, user, spec } = SetArgs::parse_full(args)?;
+    set_spec(ctx, &config, &user, &spec)
+}
+
+#[derive(Arg)]
+struct ClearArgs<'a> {
+    config: Atom<'a>,
+    user: Atom<'a>,
+}
+
+fn clear(ctx: &dyn Context, args: &str) -> Result<()> {
+    let ClearArgs { config, user } = ClearArgs::parse_full(args)?;
+    clear_spec(ctx, &config, &user)
+}
+
+// `isbridge <config> <user> [<spec>]`, from before the list, set and clear subcommands: sets the user's
+// spec if one is given, and clears it if not
+#[derive(Arg)]
+struct SetOrClearArgs<'a> {
+    config: Atom<'a>,
+    user: Atom<'a>,
+    #[arg(optional)]
+    spec: Option<Rest<'a>>,
+}
+
+fn set_or_clear(ctx: &dyn Context, args: &str) -> Result<()> {
+    let SetOrClearArgs { config, user, spec } = SetOrClearArgs::parse_full(args)?;
+    match spec {
+        Some(spec) => set_spec(ctx, &config, &user, &spec),
+        None => clear_spec(ctx, &config, &user),
     }
+}
+
+fn set_spec(ctx: &dyn Context, config: &str, user: &str, spec: &str) -> Result<()> {
+    ctx.bot().sql().lock().execute(
+        "INSERT INTO mod_debridge (config_id, source_user, spec) VALUES ($1, $2, $3) ON CONFLICT (config_id, source_user) DO UPDATE SET spec = $3",
+        &[&config, &user, &spec],
+    )?;
+
+    ctx.reply(Message::Simple("done".to_string()))
+}
+
+fn clear_spec(ctx: &dyn Context, config: &str, user: &str) -> Result<()> {
+    ctx.bot().sql().lock().execute(
+        "DELETE FROM mod_debridge WHERE config_id = $1 AND source_user = $2",
+        &[&config, &user],
+    )?;
 
     ctx.reply(Message::Simple("done".to_string()))
 }
@@ -1,15 +1,39 @@
 use rand::thread_rng;
+use std::time::Duration;
 
 mod dice;
 mod swrpg;
 
+#[cfg(test)]
+mod tests;
+
 use rustbot::prelude::*;
 
+rustbot::manifest!();
+
 #[no_mangle]
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("dice", Command::new(cmd_dice));
-    meta.cmd("swrpg", Command::new(cmd_swrpg));
-    meta.cmd("space", Command::new(cmd_space));
+    meta.cmd(
+        "dice",
+        Command::new(cmd_dice)
+            .description("roll dice")
+            .usage("<roll>")
+            .example("2d20H1")
+            .cooldown(CooldownScope::User, 5, Duration::from_secs(10)),
+    );
+    meta.cmd(
+        "swrpg",
+        Command::new(cmd_swrpg)
+            .description("roll Star Wars RPG narrative dice")
+            .usage("<dice>"),
+    );
+    meta.cmd(
+        "space",
+        Command::new(cmd_space)
+            .description("roll a pool of d6s, counting successes, sixes and ones")
+            .usage("<dice> [<description>...]")
+            .example("4 shooting"),
+    );
 }
 
 fn cmd_dice(ctx: &dyn Context, args: &str) -> Result<()> {
@@ -0,0 +1,45 @@
+use rustbot::prelude::*;
+use rustbot::testing::{TestBot, TestContext};
+
+use super::{cmd_dice, cmd_space};
+
+#[test]
+fn test_usage() {
+    let bot = TestBot::new();
+    let ctx = TestContext::new(&bot);
+
+    cmd_dice(&ctx, "  ").unwrap();
+    cmd_space(&ctx, "").unwrap();
+
+    assert_eq!(
+        ctx.replies(),
+        vec![
+            Message::Simple("Usage: dice <roll>; try '1d6', '2d20H1', '2d6>7'".to_string()),
+            Message::Simple("Usage: space <dice> [<description>...]".to_string()),
+        ]
+    );
+    assert_eq!(bot.sent(), vec![]);
+}
+
+#[test]
+fn test_roll_replies_with_spans() {
+    let bot = TestBot::new();
+    let ctx = TestContext::new(&bot);
+
+    cmd_dice(&ctx, "3d1").unwrap();
+
+    match ctx.replies().as_slice() {
+        [Message::Spans(s)] => assert_eq!(spans_to_raw_string(s.clone()), "[1, 1, 1]: 3d1:[1, 1, 1]"),
+        r => panic!("unexpected replies {:?}", r),
+    }
+}
+
+#[test]
+fn test_bad_roll_is_user_error() {
+    let bot = TestBot::new();
+    let ctx = TestContext::new(&bot);
+
+    let err = cmd_dice(&ctx, "1000d1000000000").unwrap_err();
+    assert!(err.downcast_ref::<UserError>().is_some());
+    assert_eq!(ctx.replies(), vec![]);
+}
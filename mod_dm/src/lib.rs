@@ -5,6 +5,8 @@ use std::hash::Hasher;
 use std::io::prelude::*;
 use std::process::Command as ProcessCommand;
 
+rustbot::manifest!();
+
 #[no_mangle]
 pub fn get_meta(meta: &mut dyn Meta) {
     meta.cmd("dm", Command::new(|ctx, args| dm(ctx, args, false, false)));
@@ -3,9 +3,24 @@ use rustbot::prelude::*;
 #[cfg(test)]
 mod tests;
 
+rustbot::manifest!();
+
 #[no_mangle]
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("mpg", Command::new(mpg).req_perms(Perms::Admin));
+    let usage = MpgArgs::describe_expected();
+    meta.cmd(
+        "mpg",
+        CommandGroup::new()
+            .req_perms(Perms::Admin)
+            .description("record a fuel fill-up, and on filling the tank report the cost per mile")
+            .sub(
+                "fill",
+                Command::new(|ctx, args| mpg(ctx, args, false)).usage(usage.clone()),
+            )
+            .sub("full", Command::new(|ctx, args| mpg(ctx, args, true)).usage(usage))
+            .fallback(Command::new(old_mpg).usage(OldMpgArgs::describe_expected()))
+            .into(),
+    );
 }
 
 struct MpgEntry {
@@ -40,24 +55,18 @@ fn load_entries(ctx: &dyn Context) -> Result<Vec<MpgEntry>> {
     Ok(entries)
 }
 
-fn mpg(ctx: &dyn Context, args: &str) -> Result<()> {
-    let args: Vec<&str> = args.splitn(4, ' ').collect();
-    let (mileage, litres, price, full) = match args[..] {
-        [m, l, p] => (m, l, p, false),
-        [m, l, p, "full"] => (m, l, p, true),
-        _ => bail_user!("usage: mpg <mileage> <litres> <price> [\"full\"]"),
-    };
-
-    let mileage = mileage
-        .parse::<i32>()
-        .map_err(|_| UserError::new("mileage must be an int"))?;
-    let litres = litres
-        .parse::<f64>()
-        .map_err(|_| UserError::new("litres must be a float"))?;
-    let price = price
-        .parse::<f64>()
-        .map_err(|_| UserError::new("price must be a float"))?;
+#[derive(Arg)]
+struct MpgArgs {
+    mileage: i32,
+    litres: f64,
+    price: f64,
+}
+
+fn mpg(ctx: &dyn Context, args: &str, full: bool) -> Result<()> {
+    record(ctx, MpgArgs::parse_full(args)?, full)
+}
 
+fn record(ctx: &dyn Context, MpgArgs { mileage, litres, price }: MpgArgs, full: bool) -> Result<()> {
     if !full {
         ctx.bot().sql().lock().query(
             "INSERT INTO mpg (mileage, fill_litres, fill_price, result_price)
@@ -94,6 +103,31 @@ fn mpg(ctx: &dyn Context, args: &str) -> Result<()> {
     return ctx.reply(Message::Simple("Data recorded".into()));
 }
 
+// `mpg <mileage> <litres> <price> [full]`, from before the fill and full subcommands
+#[derive(Arg)]
+struct OldMpgArgs {
+    mileage: i32,
+    litres: f64,
+    price: f64,
+    #[arg(optional)]
+    full: Option<Full>,
+}
+
+#[derive(Arg)]
+enum Full {
+    Full,
+}
+
+fn old_mpg(ctx: &dyn Context, args: &str) -> Result<()> {
+    let OldMpgArgs {
+        mileage,
+        litres,
+        price,
+        full,
+    } = OldMpgArgs::parse_full(args)?;
+    record(ctx, MpgArgs { mileage, litres, price }, full.is_some())
+}
+
 #[derive(Debug, PartialEq)]
 struct MpgStats {
     total_mileage: i32,
@@ -1,4 +1,5 @@
-use super::{calculate_stats, MpgEntry, MpgStats};
+use super::{calculate_stats, MpgEntry, MpgStats, OldMpgArgs};
+use rustbot::prelude::*;
 
 #[test]
 fn test_stats() {
@@ -125,3 +126,14 @@ fn test_stats() {
         }
     );
 }
+
+#[test]
+fn test_old_syntax() {
+    let args = OldMpgArgs::parse_full("200 10 1.5").unwrap();
+    assert_eq!(
+        (args.mileage, args.litres, args.price, args.full.is_some()),
+        (200, 10.0, 1.5, false)
+    );
+    assert!(OldMpgArgs::parse_full("200 10 1.5 full").unwrap().full.is_some());
+    assert!(OldMpgArgs::parse_full("200 10 1.5 empty").is_err());
+}
@@ -1,27 +1,51 @@
 use rustbot::prelude::*;
 
+rustbot::manifest!();
+
 #[no_mangle]
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("8ball", Command::new(|ctx, args| randomlist("eightball", ctx, args)));
-    meta.cmd("kitty", Command::new(|ctx, args| randomlist("kitty", ctx, args)));
-    meta.cmd("fox", Command::new(|ctx, args| randomlist("fox", ctx, args)));
-    meta.cmd("snek", Command::new(|ctx, args| randomlist("snek", ctx, args)));
-    meta.cmd("otter", Command::new(|ctx, args| randomlist("otter", ctx, args)));
-    meta.cmd("doggo", Command::new(|ctx, args| randomlist("doggo", ctx, args)));
-    meta.cmd("possum", Command::new(|ctx, args| randomlist("possum", ctx, args)));
-    meta.cmd("lizard", Command::new(|ctx, args| randomlist("lizard", ctx, args)));
-    meta.cmd("delrand", Command::new(delrand).req_perms(Perms::Admin));
+    meta.cmd("8ball", list_command("eightball"));
+    meta.cmd("kitty", list_command("kitty"));
+    meta.cmd("fox", list_command("fox"));
+    meta.cmd("snek", list_command("snek"));
+    meta.cmd("otter", list_command("otter"));
+    meta.cmd("doggo", list_command("doggo"));
+    meta.cmd("possum", list_command("possum"));
+    meta.cmd("lizard", list_command("lizard"));
+    meta.cmd(
+        "delrand",
+        Command::new(delrand)
+            .req_perms(Perms::Admin)
+            .description("remove a string from a random list")
+            .usage(DelrandArgs::describe_expected()),
+    );
+}
+
+#[derive(Arg)]
+struct DelrandArgs<'a> {
+    category: Atom<'a>,
+    #[arg(rest)]
+    string: &'a str,
+}
+
+// `<name>` gives a random item from the list, and `<name> add <string>` adds one
+fn list_command(what: &'static str) -> Command {
+    CommandGroup::new()
+        .description("get a random item from this list, or add one")
+        .sub(
+            "add",
+            Command::new(move |ctx, args| add(what, ctx, args)).usage(AddArgs::describe_expected()),
+        )
+        .fallback(Command::new(move |ctx, _| randomlist(what, ctx)))
+        .into()
 }
 
 fn delrand(ctx: &dyn Context, args: &str) -> Result<()> {
-    let parts: Vec<&str> = args.splitn(2, ' ').collect();
-    if parts.len() != 2 {
-        return ctx.say("usage: delrand <category> <string>");
-    }
+    let DelrandArgs { category, string } = DelrandArgs::parse_full(args)?;
 
     let n = ctx.bot().sql().lock().execute(
         "DELETE FROM mod_randomlist WHERE category = $1 AND string = $2",
-        &[&parts[0], &parts[1]],
+        &[&&*category, &string],
     )?;
     if n != 1 {
         ctx.say(&format!("{n} rows removed"))
@@ -30,19 +54,25 @@ fn delrand(ctx: &dyn Context, args: &str) -> Result<()> {
     }
 }
 
-fn randomlist(what: &str, ctx: &dyn Context, args: &str) -> Result<()> {
-    let parts: Vec<&str> = args.splitn(2, ' ').collect();
-    if parts.len() == 2 && parts[0] == "add" {
-        let n = ctx.bot().sql().lock().execute(
-            "INSERT INTO mod_randomlist (category, string) VALUES ($1, $2) ON CONFLICT (category, string) DO NOTHING",
-            &[&what, &parts[1]],
-        )?;
-        if n == 0 {
-            return ctx.say("That's already on the list.");
-        }
-        return ctx.say("Added.");
+#[derive(Arg)]
+struct AddArgs<'a> {
+    string: Rest<'a>,
+}
+
+fn add(what: &str, ctx: &dyn Context, args: &str) -> Result<()> {
+    let AddArgs { string } = AddArgs::parse_full(args)?;
+
+    let n = ctx.bot().sql().lock().execute(
+        "INSERT INTO mod_randomlist (category, string) VALUES ($1, $2) ON CONFLICT (category, string) DO NOTHING",
+        &[&what, &&*string],
+    )?;
+    if n == 0 {
+        return ctx.say("That's already on the list.");
     }
+    ctx.say("Added.")
+}
 
+fn randomlist(what: &str, ctx: &dyn Context) -> Result<()> {
     let mut db = ctx.bot().sql().lock();
     let rows = db
             .query("SELECT string FROM mod_randomlist WHERE category = $1 LIMIT 1 OFFSET FLOOR(RANDOM() * GREATEST((SELECT COUNT(*) FROM mod_randomlist WHERE category = $1), 1) )", &[&what])?;
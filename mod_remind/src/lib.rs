@@ -0,0 +1,180 @@
+use rustbot::prelude::*;
+use rustbot::time::{parse_time, parse_tz};
+
+use chrono::{DateTime, Utc};
+use std::time::SystemTime;
+
+#[cfg(test)]
+mod tests;
+
+// Reminders are delivered by a job stored with Bot::schedule_job, so that they survive restarts; this
+// is the module name those jobs are filed under, which must match the name this module is loaded as.
+const MODULE: &str = "remind";
+const JOB: &str = "remind";
+
+rustbot::manifest!();
+
+#[no_mangle]
+pub fn get_meta(meta: &mut dyn Meta) {
+    meta.cmd(
+        "remind",
+        Command::new(remind)
+            .description("remind you of something after a while, or at a time in a timezone")
+            .usage("<duration>|<time> <timezone> <message>")
+            .example("tomorrow 09:00 Europe/London standup"),
+    );
+    meta.cmd(
+        "reminders",
+        Command::new(reminders).description("list your pending reminders"),
+    );
+    meta.cmd(
+        "unremind",
+        Command::new(unremind)
+            .description("cancel one of your pending reminders")
+            .usage("<id>"),
+    );
+    meta.job_handler(JOB, Box::new(deliver));
+}
+
+// Splits the arguments of !remind into when the reminder is due and what it says: either a duration
+// from `now`, such as "2h30m", or a time followed by a timezone, then the message.
+fn parse_when(args: &str, now: DateTime<Utc>) -> Result<(DateTime<Utc>, String)> {
+    let words: Vec<_> = args.split_whitespace().collect();
+
+    let (due, rest) = match words.first().map(|w| parse_duration(w)) {
+        None => bail_user!("expected a duration or a time to remind you at"),
+        Some(Ok(d)) if d.as_secs() == 0 => bail_user!("a reminder must be at least a second away"),
+        Some(Ok(d)) => (now + chrono::Duration::seconds(d.as_secs() as i64), &words[1..]),
+        Some(Err(_)) => match (1..words.len()).find_map(|i| parse_tz(words[i]).ok().map(|tz| (i, tz))) {
+            Some((i, tz)) => (parse_time(&words[..i], tz)?.with_timezone(&Utc), &words[i + 1..]),
+            None => bail_user!("expected a duration such as 2h30m, or a time followed by a timezone"),
+        },
+    };
+
+    if due <= now {
+        bail_user!("{} has already passed", due.format("%Y-%m-%d %H:%M:%S UTC"));
+    }
+    if rest.is_empty() {
+        bail_user!("expected something to remind you of");
+    }
+    Ok((due, rest.join(" ")))
+}
+
+// Where to deliver a reminder set from `source`: the channel it was set in, or for an IRC private message
+// (whose channel is just "query") the user's nick.
+fn target(source: &dyn Source) -> String {
+    match source.get_irc_params() {
+        Some((None, nick)) => format!("irc:{nick}"),
+        _ => source.channel_string().into_owned(),
+    }
+}
+
+fn remind(ctx: &dyn Context, args: &str) -> Result<()> {
+    let (due, message) = parse_when(args, Utc::now())?;
+    let source = ctx.source();
+    let at = SystemTime::from(due);
+
+    let id: i64 = ctx
+        .bot()
+        .sql()
+        .lock()
+        .query_one(
+            "INSERT INTO mod_remind (config_id, target, user_string, user_pretty, due, message)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id",
+            &[
+                &ctx.config_id(),
+                &target(source),
+                &source.user_string(),
+                &source.user_pretty(),
+                &at,
+                &message,
+            ],
+        )?
+        .get(0);
+
+    let job = ctx.bot().schedule_job(MODULE, JOB, at, &id.to_string())?;
+    ctx.bot()
+        .sql()
+        .lock()
+        .execute("UPDATE mod_remind SET job_id = $1 WHERE id = $2", &[&job, &id])?;
+
+    ctx.reply(Message::Simple(format!(
+        "reminder {} set for {}",
+        id,
+        due.format("%Y-%m-%d %H:%M:%S UTC")
+    )))
+}
+
+fn reminders(ctx: &dyn Context, _args: &str) -> Result<()> {
+    let rows = ctx.bot().sql().lock().query(
+        "SELECT id, due, message FROM mod_remind WHERE config_id = $1 AND user_string = $2 ORDER BY due",
+        &[&ctx.config_id(), &ctx.source().user_string()],
+    )?;
+    if rows.is_empty() {
+        return ctx.say("you have no pending reminders");
+    }
+
+    ctx.reply(Message::List {
+        prefix: "your reminders: ".into(),
+        sep: ", ".into(),
+        items: rows
+            .iter()
+            .map(|row| {
+                let due = DateTime::<Utc>::from(row.get::<_, SystemTime>(1));
+                let message: String = row.get(2);
+                format!(
+                    "{} at {}: {}",
+                    row.get::<_, i64>(0),
+                    due.format("%Y-%m-%d %H:%M UTC"),
+                    message
+                )
+                .into()
+            })
+            .collect(),
+    })
+}
+
+fn unremind(ctx: &dyn Context, args: &str) -> Result<()> {
+    parse_args!(args, id: i64,);
+
+    let row = ctx.bot().sql().lock().query_opt(
+        "DELETE FROM mod_remind WHERE id = $1 AND config_id = $2 AND user_string = $3 RETURNING job_id",
+        &[&id, &ctx.config_id(), &ctx.source().user_string()],
+    )?;
+    let job: Option<i64> = match row {
+        Some(row) => row.get(0),
+        None => bail_user!("you have no reminder {}", id),
+    };
+    if let Some(job) = job {
+        ctx.bot().cancel_job(job)?;
+    }
+
+    ctx.say(&format!("reminder {id} cancelled"))
+}
+
+// Delivers the reminder with the id in `payload`, unless it was cancelled meanwhile. The row is
+// only deleted once the message is sent; on failure the error makes the scheduler try again later.
+fn deliver(bot: &dyn Bot, payload: &str) -> Result<()> {
+    let id: i64 = payload.parse()?;
+    let row = bot.sql().lock().query_opt(
+        "SELECT config_id, target, user_pretty, message FROM mod_remind WHERE id = $1",
+        &[&id],
+    )?;
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(()),
+    };
+
+    let (config, target, user, message): (String, String, String, String) =
+        (row.get(0), row.get(1), row.get(2), row.get(3));
+    bot.send_message(
+        &config,
+        &target,
+        Message::Simple(format!("{user}: reminder: {message}")),
+    )?;
+    bot.sql()
+        .lock()
+        .execute("DELETE FROM mod_remind WHERE id = $1", &[&id])?;
+    Ok(())
+}
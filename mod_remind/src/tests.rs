@@ -0,0 +1,54 @@
+use super::{parse_when, target};
+use chrono::{TimeZone, Utc};
+use rustbot::testing::TestSource;
+
+#[test]
+fn test_parse_when() {
+    let now = Utc.with_ymd_and_hms(2030, 1, 1, 12, 0, 0).unwrap();
+
+    assert_eq!(
+        parse_when("2h30m stretch", now).unwrap(),
+        (
+            Utc.with_ymd_and_hms(2030, 1, 1, 14, 30, 0).unwrap(),
+            "stretch".to_string()
+        )
+    );
+    assert_eq!(
+        parse_when("2030-01-02 09:00 Europe/London  daily   standup", now).unwrap(),
+        (
+            Utc.with_ymd_and_hms(2030, 1, 2, 9, 0, 0).unwrap(),
+            "daily standup".to_string()
+        )
+    );
+    assert_eq!(
+        parse_when("2 jan 2030 09:00 america/new_york standup", now).unwrap(),
+        (
+            Utc.with_ymd_and_hms(2030, 1, 2, 14, 0, 0).unwrap(),
+            "standup".to_string()
+        )
+    );
+
+    for (args, err) in [
+        ("", "expected a duration or a time to remind you at"),
+        ("0m stretch", "a reminder must be at least a second away"),
+        ("1h", "expected something to remind you of"),
+        (
+            "soon stretch",
+            "expected a duration such as 2h30m, or a time followed by a timezone",
+        ),
+        (
+            "2029-12-31 09:00 UTC late",
+            "2029-12-31 09:00:00 UTC has already passed",
+        ),
+    ] {
+        assert_eq!(parse_when(args, now).unwrap_err().to_string(), err, "{args:?}");
+    }
+}
+
+#[test]
+fn test_target() {
+    assert_eq!(target(&TestSource::irc("nick", Some("#chan"))), "irc:#chan");
+    assert_eq!(target(&TestSource::irc("nick", None)), "irc:nick");
+    assert_eq!(target(&TestSource::discord("name", Some(1), 2, 3)), "dis:1:2");
+    assert_eq!(target(&TestSource::discord("name", None, 2, 3)), "dis:none:2");
+}
@@ -4,6 +4,8 @@ mod status;
 mod updates;
 mod utils;
 
+rustbot::manifest!();
+
 #[no_mangle]
 pub fn get_meta(meta: &mut dyn Meta) {
     meta.cmd("address", Command::new(status::address));
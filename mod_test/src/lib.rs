@@ -7,6 +7,8 @@ use std::net::SocketAddr;
 use rustbot::prelude::*;
 use rustbot::{span, spans};
 
+rustbot::manifest!();
+
 #[no_mangle]
 pub fn get_meta(meta: &mut dyn Meta) {
     meta.cmd(
@@ -33,7 +35,7 @@ pub fn get_meta(meta: &mut dyn Meta) {
         }),
     );
 
-    meta.cmd("test2", Command::new(test2));
+    meta.cmd("test2", Command::new(test2).usage(Test2Args::describe_expected()));
 
     thread!(meta, async {
         let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
@@ -46,12 +48,15 @@ pub fn get_meta(meta: &mut dyn Meta) {
     })
 }
 
+#[derive(Arg)]
+struct Test2Args<'a> {
+    a: u64,
+    b: Atom<'a>,
+    c: Cow<'a, str>,
+}
+
 fn test2(ctx: &dyn Context, args: &str) -> Result<()> {
-    parse_args! {args,
-        a: u64,
-        b: Atom,
-        c: Cow<str>,
-    }
+    let Test2Args { a, b, c } = Test2Args::parse_full(args)?;
 
     ctx.reply(Message::Simple(format!("You passed {:?}", (a, b, c))))
 }
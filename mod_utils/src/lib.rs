@@ -2,9 +2,17 @@ use rustbot::prelude::*;
 use std::borrow::Cow;
 use std::process::Command as StdCommand;
 
+rustbot::manifest!();
+
 #[no_mangle]
 pub fn get_meta(meta: &mut dyn Meta) {
-    meta.cmd("units", Command::new(units));
+    meta.cmd(
+        "units",
+        Command::new(units)
+            .description("convert between units with GNU units")
+            .usage(UnitsArgs::describe_expected())
+            .example("10 furlongs to m"),
+    );
 }
 
 struct FromUnits<'a>(&'a str);
@@ -26,11 +34,14 @@ impl<'a> Arg<'a> for FromUnits<'a> {
     }
 }
 
+#[derive(Arg)]
+struct UnitsArgs<'a> {
+    from: Option<FromUnits<'a>>,
+    to: Rest<'a>,
+}
+
 fn units(ctx: &dyn Context, args: &str) -> Result<()> {
-    parse_args! {args,
-        from: Option<FromUnits>,
-        to: rustbot::args::Rest,
-    }
+    let UnitsArgs { from, to } = UnitsArgs::parse_full(args)?;
 
     if cfg!(target_os = "windows") {
         bail_user!("unsupported");
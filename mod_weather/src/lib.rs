@@ -5,16 +5,25 @@ use rustbot::spans;
 use chrono::NaiveDateTime;
 use rustbot::prelude::*;
 use serde::Deserialize;
+use std::time::Duration;
 
 #[derive(Deserialize)]
 struct Module {
     appid: String,
 }
 
+rustbot::manifest!();
+
 #[no_mangle]
 pub fn get_meta_conf(meta: &mut dyn Meta, config: toml::Value) -> Result<()> {
     let m: Module = config.try_into()?;
-    meta.cmd("weather", Command::new(move |ctx, args| m.weather(ctx, args)));
+    meta.cmd(
+        "weather",
+        // Each use is a request to the OpenWeatherMap API
+        Command::new(move |ctx, args| m.weather(ctx, args))
+            .description("show the current weather for a place or airport code")
+            .cooldown(CooldownScope::Channel, 3, Duration::from_secs(60)),
+    );
     Ok(())
 }
 
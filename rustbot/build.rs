@@ -0,0 +1,15 @@
+use std::env;
+use std::process::Command;
+
+// Records the compiler version for rustbot::manifest::ABI; cargo rebuilds everything when the compiler
+// changes, so this needn't be rerun for that.
+fn main() {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let out = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .expect("failed to run rustc --version");
+    let version = String::from_utf8(out.stdout).expect("rustc --version printed invalid UTF-8");
+    println!("cargo:rustc-env=RUSTBOT_RUSTC_VERSION={}", version.trim());
+    println!("cargo:rerun-if-changed=build.rs");
+}
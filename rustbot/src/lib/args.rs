@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::time::Duration;
 
 use super::prelude::*;
 
@@ -10,15 +11,30 @@ macro_rules! parse_args {
         let ($($name),*) = match <($($ty),*) as Arg>::parse_full_no_pfx($args) {
             Ok(v) => v,
             Err(e) => return Err(UserError::new(format!("parsing {}: {}",
-                format!("({})", [$(
-                    format!("{}: {}", stringify!($name), <$ty as Arg>::describe_expected())
-                ),*].join(", ")),
+                $crate::describe_args!($($name: $ty,)*),
                 e,
             )).into())
         };
     }
 }
-pub use crate::parse_args;
+
+// The description of an argument list that parse_args! uses in its errors. For a command's usage, put the
+// arguments in a #[derive(Arg)] struct instead, and use its describe_expected() for the usage and its
+// parse_full() in the command, so that the two can't disagree.
+#[macro_export]
+macro_rules! describe_args {
+    ($(
+        $name:ident: $ty:ty,
+    )*) => {
+        {
+            let args: &[String] = &[$(
+                format!("{}: {}", stringify!($name), <$ty as $crate::args::Arg>::describe_expected())
+            ),*];
+            format!("({})", args.join(", "))
+        }
+    }
+}
+pub use crate::{describe_args, parse_args};
 
 pub trait Arg<'a>: Sized {
     fn parse_from<'s: 'a>(input: &'s str) -> Result<(Self, Option<&'s str>)>;
@@ -59,6 +75,25 @@ macro_rules! ws_terminated {
             }
         }
     };
+    ($ty:ty, $desc:literal, |$n:ident| $conv:expr) => {
+        impl<'a> Arg<'a> for $ty {
+            fn parse_from<'s: 'a>(input: &'s str) -> Result<(Self, Option<&'s str>)> {
+                if input == "" {
+                    bail_user!("missing argument")
+                }
+                let ($n, rest) = match input.split_once(char::is_whitespace) {
+                    Some((this, rest)) => (this, Some(rest)),
+                    None => (input, None),
+                };
+
+                Ok(($conv, rest))
+            }
+
+            fn describe_expected() -> Cow<'static, str> {
+                Cow::Borrowed($desc)
+            }
+        }
+    };
 }
 
 macro_rules! impl_ws_with_parse {
@@ -75,9 +110,15 @@ macro_rules! impl_ws_with_parse {
 impl_ws_with_parse! {
     i8, i16, i32, i64, isize,
     u8, u16, u32, u64, usize,
+    f32, f64,
     bool
 }
 
+ws_terminated!(Duration, "duration (like 1h30m)", |this| match parse_duration(this) {
+    Ok(v) => v,
+    Err(e) => bail_user!("failed to parse {:?} as a duration: {}", this, e),
+});
+
 // More complex: strings
 
 // Atom: a single non-quoted segment
@@ -109,36 +150,15 @@ impl<'a> Arg<'a> for Atom<'a> {
     }
 }
 
-// Cow<str>: a possibly-quoted segment
+// Cow<str>: a possibly-quoted segment, as split by next_token
 impl<'a> Arg<'a> for Cow<'a, str> {
     fn parse_from<'s: 'a>(input: &'s str) -> Result<(Self, Option<&'s str>)> {
-        if input.is_empty() {
+        if input.trim_start().is_empty() {
             bail_user!("missing argument")
         }
 
-        let (this, rest) = if &input[0..1] == "\"" {
-            // Quoted string
-            match input[1..].split_once('"') {
-                Some((this, rest)) => {
-                    if rest.is_empty() {
-                        (this, None)
-                    } else if rest.chars().next().unwrap().is_whitespace() {
-                        (this, Some(&rest[1..]))
-                    } else {
-                        bail_user!("bad quoted string");
-                    }
-                }
-                _ => bail_user!("bad quoted string"),
-            }
-        } else {
-            // Non-quoted string; take everything up to the next whitespace
-            match input.split_once(char::is_whitespace) {
-                Some((this, rest)) => (this, Some(rest)),
-                None => (input, None),
-            }
-        };
-
-        Ok((Cow::Borrowed(this), rest))
+        let (this, _, rest) = next_token(input)?;
+        Ok((this, rest))
     }
 
     fn describe_expected() -> Cow<'static, str> {
@@ -146,6 +166,66 @@ impl<'a> Arg<'a> for Cow<'a, str> {
     }
 }
 
+// Splits the first shell-like word off the input, returning the word, the raw input it was parsed
+// from, and the rest of the input after the whitespace following it, or None if nothing but whitespace
+// follows it. Within a word, "double quotes" and 'single quotes' may be used to include whitespace, and a
+// backslash escapes the next character anywhere except inside single quotes.
+pub fn next_token(input: &str) -> Result<(Cow<'_, str>, &str, Option<&str>)> {
+    let input = input.trim_start();
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = input.char_indices();
+    let mut end = input.len();
+
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => {
+                end = i;
+                break;
+            }
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '\\') | (Some('"'), '\\') => match chars.next() {
+                Some((_, c)) => word.push(c),
+                None => bail_user!("unfinished escape sequence at end of input"),
+            },
+            (_, c) => word.push(c),
+        }
+    }
+    if quote.is_some() {
+        bail_user!("unterminated quoted string")
+    }
+
+    let raw = &input[..end];
+    let rest = Some(input[end..].trim_start()).filter(|rest| !rest.is_empty());
+
+    // Borrow rather than allocate where the word is a plain or simply-quoted part of the input
+    let word = if word == raw {
+        Cow::Borrowed(raw)
+    } else if raw.len() >= 2 && word == raw[1..raw.len() - 1] {
+        Cow::Borrowed(&raw[1..raw.len() - 1])
+    } else {
+        Cow::Owned(word)
+    };
+
+    Ok((word, raw, rest))
+}
+
+// Splits the whole input into shell-like words, as next_token.
+pub fn tokenize(input: &str) -> Result<Vec<Cow<'_, str>>> {
+    let mut words = vec![];
+    let mut rest = Some(input.trim());
+    while let Some(input) = rest {
+        if input.is_empty() {
+            break;
+        }
+        let (word, _, r) = next_token(input)?;
+        words.push(word);
+        rest = r;
+    }
+    Ok(words)
+}
+
 // Rest: the rest of the input
 #[derive(Debug, PartialEq, Eq)]
 pub struct Rest<'a>(pub &'a str);
@@ -170,6 +250,293 @@ impl<'a> Arg<'a> for Rest<'a> {
     }
 }
 
+// The named options a command accepts, for Flags:
+//     #[derive(Default)]
+//     struct TimeFlags { tz: Option<String>, count: Option<u32>, utc: bool }
+//     impl FlagSet for TimeFlags {
+//         const FLAGS: &'static [Flag] = &[Flag::value("tz"), Flag::value("count").short('n'), Flag::switch("utc")];
+//         fn set(&mut self, name: &str, value: Option<&str>) -> Result<()> {
+//             match (name, value) {
+//                 ("tz", Some(v)) => self.tz = Some(v.to_string()),
+//                 ("count", Some(v)) => self.count = Some(u32::parse_full(v)?),
+//                 _ => self.utc = true,
+//             }
+//             Ok(())
+//         }
+//     }
+pub trait FlagSet: Default {
+    const FLAGS: &'static [Flag];
+
+    // Called once for each flag given, with its long name; `value` is None exactly when the flag is a switch
+    fn set(&mut self, name: &str, value: Option<&str>) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Flag {
+    pub name: &'static str,
+    pub short: Option<char>,
+    pub takes_value: bool,
+}
+
+impl Flag {
+    // A flag given as `--name value` or `--name=value`
+    pub const fn value(name: &'static str) -> Self {
+        Self {
+            name,
+            short: None,
+            takes_value: true,
+        }
+    }
+
+    // A flag given as just `--name`
+    pub const fn switch(name: &'static str) -> Self {
+        Self {
+            name,
+            short: None,
+            takes_value: false,
+        }
+    }
+
+    // Also accept the flag as `-c`
+    pub const fn short(self, c: char) -> Self {
+        Self { short: Some(c), ..self }
+    }
+}
+
+// Flags<F>: the flags in F, taken from anywhere in the input, followed by the remaining positional
+// arguments. Since removing the flags leaves the positional arguments non-contiguous, Flags takes the
+// whole input and keeps the positional arguments in a new string, to be parsed in turn:
+//     parse_args!(args, flags: Flags<TimeFlags>,);
+//     let args = flags.positional();
+//     parse_args!(args, time: Rest,);
+// `--` ends the flags; anything after it is positional, as is a `-x` that isn't a known short flag, so
+// negative numbers are unaffected.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Flags<F> {
+    pub flags: F,
+    positional: String,
+}
+
+impl<F> Flags<F> {
+    pub fn positional(&self) -> &str {
+        &self.positional
+    }
+}
+
+impl<F> std::ops::Deref for Flags<F> {
+    type Target = F;
+    fn deref(&self) -> &F {
+        &self.flags
+    }
+}
+
+impl<'a, F: FlagSet> Arg<'a> for Flags<F> {
+    fn parse_from<'s: 'a>(input: &'s str) -> Result<(Self, Option<&'s str>)> {
+        let mut flags = F::default();
+        let mut positional = vec![];
+        let mut rest = Some(input.trim());
+
+        while let Some(input) = rest {
+            if input.is_empty() {
+                break;
+            }
+            let (_, raw, r) = next_token(input)?;
+            rest = r;
+
+            if raw == "--" {
+                positional.extend(rest.take());
+                break;
+            }
+
+            let (flag, inline_value) = if let Some(long) = raw.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (long, None),
+                };
+                match F::FLAGS.iter().find(|f| f.name == name) {
+                    Some(f) => (f, value),
+                    None => bail_user!("unknown flag --{}", name),
+                }
+            } else {
+                let mut short = raw.strip_prefix('-').map(str::chars);
+                let flag = match short.as_mut().and_then(|c| Some((c.next()?, c.next()))) {
+                    Some((c, None)) => F::FLAGS.iter().find(|f| f.short == Some(c)),
+                    _ => None,
+                };
+                match flag {
+                    Some(f) => (f, None),
+                    None => {
+                        positional.push(raw);
+                        continue;
+                    }
+                }
+            };
+
+            match (flag.takes_value, inline_value) {
+                (false, None) => flags.set(flag.name, None)?,
+                (false, Some(_)) => bail_user!("flag --{} does not take a value", flag.name),
+                (true, Some(value)) => flags.set(flag.name, Some(&next_token(value)?.0))?,
+                (true, None) => match rest {
+                    Some(input) if !input.is_empty() => {
+                        let (value, _, r) = next_token(input)?;
+                        rest = r;
+                        flags.set(flag.name, Some(&value))?
+                    }
+                    _ => bail_user!("flag --{} needs a value", flag.name),
+                },
+            }
+        }
+
+        Ok((
+            Flags {
+                flags,
+                positional: positional.join(" "),
+            },
+            None,
+        ))
+    }
+
+    fn describe_expected() -> Cow<'static, str> {
+        let flags: Vec<_> = F::FLAGS
+            .iter()
+            .map(|f| {
+                let short = f.short.map_or_else(String::new, |c| format!("-{c}/"));
+                let value = if f.takes_value { " <value>" } else { "" };
+                format!("[{}--{}{}]", short, f.name, value)
+            })
+            .collect();
+        Cow::Owned(format!("flags {} and arguments", flags.join(" ")))
+    }
+}
+
+// Vec<T>: as many Ts as will parse, possibly none
+impl<'a, T: Arg<'a>> Arg<'a> for Vec<T> {
+    fn parse_from<'s: 'a>(input: &'s str) -> Result<(Self, Option<&'s str>)> {
+        let mut v = vec![];
+        let mut rest = Some(input);
+        while let Some(input) = rest {
+            if input.is_empty() {
+                rest = None;
+                break;
+            }
+            match T::parse_from(input) {
+                Ok((this, r)) => {
+                    v.push(this);
+                    rest = r;
+                }
+                Err(_) => break,
+            }
+        }
+        Ok((v, rest))
+    }
+
+    fn describe_expected() -> Cow<'static, str> {
+        Cow::Owned(format!("zero or more {}", T::describe_expected()))
+    }
+}
+
+// A fixed set of keywords, for OneOf:
+//     #[derive(Clone, Copy)]
+//     enum Units { Metric, Imperial }
+//     impl Keyword for Units {
+//         const KEYWORDS: &'static [(&'static str, Self)] = &[("metric", Units::Metric), ("imperial", Units::Imperial)];
+//     }
+pub trait Keyword: Copy + 'static {
+    const KEYWORDS: &'static [(&'static str, Self)];
+}
+
+// OneOf<K>: one of K's keywords, case-insensitively
+#[derive(Debug, PartialEq, Eq)]
+pub struct OneOf<K>(pub K);
+impl<K> std::ops::Deref for OneOf<K> {
+    type Target = K;
+    fn deref(&self) -> &K {
+        &self.0
+    }
+}
+
+impl<'a, K: Keyword> Arg<'a> for OneOf<K> {
+    fn parse_from<'s: 'a>(input: &'s str) -> Result<(Self, Option<&'s str>)> {
+        let (Atom(this), rest) = Atom::parse_from(input)?;
+        match K::KEYWORDS.iter().find(|(kw, _)| kw.eq_ignore_ascii_case(this)) {
+            Some((_, v)) => Ok((OneOf(*v), rest)),
+            None => bail_user!("expected {}, got {:?}", Self::describe_expected(), this),
+        }
+    }
+
+    fn describe_expected() -> Cow<'static, str> {
+        let keywords: Vec<_> = K::KEYWORDS.iter().map(|(kw, _)| *kw).collect();
+        Cow::Owned(format!("one of {}", keywords.join("|")))
+    }
+}
+
+// The numeric ID in a Discord mention like "<@123>", "<@!123>" or "<#123>", given the sigils after '<'.
+fn discord_mention(s: &str, sigils: &[&str]) -> Option<u64> {
+    let inner = s.strip_prefix('<')?.strip_suffix('>')?;
+    sigils
+        .iter()
+        .find_map(|sigil| inner.strip_prefix(sigil))
+        .and_then(|id| id.parse().ok())
+}
+
+// UserRef: a Discord user mention, or an IRC nick
+#[derive(Debug, PartialEq, Eq)]
+pub enum UserRef<'a> {
+    Discord(u64),
+    Nick(&'a str),
+}
+
+fn is_irc_nick(s: &str) -> bool {
+    let special = |c: char| "[]\\`_^{|}".contains(c);
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || special(c) => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || special(c) || c == '-')
+}
+
+impl<'a> Arg<'a> for UserRef<'a> {
+    fn parse_from<'s: 'a>(input: &'s str) -> Result<(Self, Option<&'s str>)> {
+        let (Atom(this), rest) = Atom::parse_from(input)?;
+        if let Some(id) = discord_mention(this, &["@!", "@"]) {
+            Ok((UserRef::Discord(id), rest))
+        } else if is_irc_nick(this) {
+            Ok((UserRef::Nick(this), rest))
+        } else {
+            bail_user!("{:?} is not a nick or user mention", this)
+        }
+    }
+
+    fn describe_expected() -> Cow<'static, str> {
+        Cow::Borrowed("user (nick or @mention)")
+    }
+}
+
+// ChannelRef: a Discord channel mention, or an IRC channel name
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChannelRef<'a> {
+    Discord(u64),
+    Irc(&'a str),
+}
+
+impl<'a> Arg<'a> for ChannelRef<'a> {
+    fn parse_from<'s: 'a>(input: &'s str) -> Result<(Self, Option<&'s str>)> {
+        let (Atom(this), rest) = Atom::parse_from(input)?;
+        if let Some(id) = discord_mention(this, &["#"]) {
+            Ok((ChannelRef::Discord(id), rest))
+        } else if this.len() > 1 && this.starts_with(['#', '&']) && !this.contains(',') {
+            Ok((ChannelRef::Irc(this), rest))
+        } else {
+            bail_user!("{:?} is not a channel name or mention", this)
+        }
+    }
+
+    fn describe_expected() -> Cow<'static, str> {
+        Cow::Borrowed("channel (#name or #mention)")
+    }
+}
+
 // Combining args: tuples
 macro_rules! tuple_impls {
     ( $head:ident, $($tail:ident,)* ) => {
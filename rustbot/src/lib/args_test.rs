@@ -1,6 +1,14 @@
 use crate::args::{Arg, Atom};
 use std::borrow::Cow;
 
+macro_rules! parse_err {
+    ($( $input:literal => $ty:ty => $err:literal; )*) => {
+        $(
+            assert_eq!(<$ty as Arg>::parse_full($input).unwrap_err().to_string(), $err);
+        )*
+    }
+}
+
 #[test]
 fn test_arg_parse_full() {
     macro_rules! parse_ok {
@@ -17,29 +25,230 @@ fn test_arg_parse_full() {
 
         "1 2 3" => (u64, u16, u8) => (1, 2, 3);
         "2 true" => (i32, bool) => (2, true);
-        "1 foo 3" => (u32, String, u32) => (1, "foo".to_string(), 3);
-        "1 \"foo bar\" 3" => (u32, String, u32) => (1, "foo bar".to_string(), 3);
+        "1 foo 3" => (u32, Cow<str>, u32) => (1, Cow::Borrowed("foo"), 3);
+        "1 \"foo bar\" 3" => (u32, Cow<str>, u32) => (1, Cow::Borrowed("foo bar"), 3);
 
-        "1 foo 3" => (u32, Atom, u32) => (1, Atom("foo".to_string()), 3);
+        "1 foo 3" => (u32, Atom, u32) => (1, Atom("foo"), 3);
 
-        "1 foo 3" => (u32, Option<u32>, Atom, u32) => (1, None, Atom("foo".to_string()), 3);
-        "1 2 foo 3" => (u32, Option<u32>, Atom, u32) => (1, Some(2), Atom("foo".to_string()), 3);
+        "1 foo 3" => (u32, Option<u32>, Atom, u32) => (1, None, Atom("foo"), 3);
+        "1 2 foo 3" => (u32, Option<u32>, Atom, u32) => (1, Some(2), Atom("foo"), 3);
     );
 
-    macro_rules! parse_err {
-        ($( $input:literal => $ty:tt => $err:literal; )*) => {
-            $(
-                assert_eq!(<$ty as Arg>::parse_full($input).unwrap_err().to_string(), $err);
-            )*
-        }
-    }
-
     parse_err!(
         "2 tru" => (i32, bool) => "parsing (i32, bool): failed to parse \"tru\" as bool: provided string was not `true` or `false`";
         "foo" => u32 => "parsing u32: failed to parse \"foo\" as u32: invalid digit found in string";
-        "1 foo bar 3" => (u32, String, u32) => "parsing (u32, string, u32): failed to parse \"bar\" as u32: invalid digit found in string";
+        "1 foo bar 3" => (u32, Cow<str>, u32) => "parsing (u32, string, u32): failed to parse \"bar\" as u32: invalid digit found in string";
         "1 2 3" => (u32, u32) => "parsing (u32, u32): extra arguments at end: \"3\"";
         "1 \"foo bar\" 3" => (u32, Atom, u32) => "parsing (u32, atom, u32): failed to parse \"bar\\\"\" as u32: invalid digit found in string";
         "foo bar 2" => (Option<u32>, Atom, u32) => "parsing (optional u32, atom, u32): failed to parse \"bar\" as u32: invalid digit found in string";
     );
 }
+
+#[test]
+fn test_describe_args() {
+    use crate::args::Rest;
+    use crate::describe_args;
+
+    assert_eq!(describe_args!(), "()");
+    assert_eq!(
+        describe_args!(a: u64, b: Option<Atom>, c: Rest,),
+        "(a: u64, b: optional atom, c: rest-of-input)"
+    );
+}
+
+#[test]
+fn test_derive_arg() {
+    use crate::prelude::*;
+
+    #[derive(Arg, Debug, PartialEq)]
+    struct Named<'a> {
+        n: u32,
+        name: Atom<'a>,
+        #[arg(rest)]
+        text: &'a str,
+    }
+
+    #[derive(Arg, Debug, PartialEq)]
+    struct Tuple(u32, #[arg(optional)] Option<u32>);
+
+    #[derive(Arg, Debug, PartialEq)]
+    struct MaybeRest {
+        #[arg(rest, optional)]
+        text: Option<String>,
+    }
+
+    #[derive(Arg, Debug, PartialEq)]
+    enum Sub<'a> {
+        Add(Atom<'a>, #[arg(rest)] &'a str),
+        #[arg(name = "del")]
+        Remove {
+            id: u64,
+        },
+        ListAll,
+    }
+
+    assert_eq!(
+        Named::parse_full("1 foo bar baz").unwrap(),
+        Named {
+            n: 1,
+            name: Atom("foo"),
+            text: "bar baz"
+        }
+    );
+    assert_eq!(
+        Named::parse_full("1 foo").unwrap_err().to_string(),
+        "parsing (n: u32, name: atom, text: rest-of-input): missing argument"
+    );
+
+    assert_eq!(Tuple::parse_full("1 2").unwrap(), Tuple(1, Some(2)));
+    assert_eq!(Tuple::parse_full("1").unwrap(), Tuple(1, None));
+    assert_eq!(Tuple::describe_expected(), "(u32, optional u32)");
+
+    assert_eq!(MaybeRest::parse_full("").unwrap(), MaybeRest { text: None });
+    assert_eq!(
+        MaybeRest::parse_full("a b").unwrap(),
+        MaybeRest {
+            text: Some("a b".to_string())
+        }
+    );
+
+    assert_eq!(Sub::parse_full("add x y z").unwrap(), Sub::Add(Atom("x"), "y z"));
+    assert_eq!(Sub::parse_full("del 3").unwrap(), Sub::Remove { id: 3 });
+    assert_eq!(Sub::parse_full("list-all").unwrap(), Sub::ListAll);
+    assert_eq!(
+        Sub::parse_full("list-all 3").unwrap_err().to_string(),
+        format!("parsing {}: extra arguments at end: \"3\"", Sub::describe_expected())
+    );
+    assert_eq!(
+        Sub::parse_full("frob").unwrap_err().to_string(),
+        "parsing add (atom, rest-of-input) | del (id: u64) | list-all: expected one of add, del, list-all, got \"frob\""
+    );
+}
+
+#[test]
+fn test_more_args() {
+    use crate::args::{ChannelRef, Keyword, OneOf, UserRef};
+    use std::time::Duration;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Units {
+        Metric,
+        Imperial,
+    }
+    impl Keyword for Units {
+        const KEYWORDS: &'static [(&'static str, Self)] = &[("metric", Units::Metric), ("imperial", Units::Imperial)];
+    }
+
+    assert_eq!(<(f64, f32)>::parse_full("1.5 -2").unwrap(), (1.5, -2.0));
+    assert_eq!(Duration::parse_full("1h30m").unwrap(), Duration::from_secs(5400));
+    assert_eq!(
+        <(Vec<u32>, Atom)>::parse_full("1 2 3 foo").unwrap(),
+        (vec![1, 2, 3], Atom("foo"))
+    );
+    assert_eq!(Vec::<u32>::parse_full("").unwrap(), Vec::<u32>::new());
+    assert_eq!(OneOf::<Units>::parse_full("Imperial").unwrap(), OneOf(Units::Imperial));
+    assert_eq!(UserRef::parse_full("<@!1234>").unwrap(), UserRef::Discord(1234));
+    assert_eq!(UserRef::parse_full("[m]nick").unwrap(), UserRef::Nick("[m]nick"));
+    assert_eq!(ChannelRef::parse_full("<#55>").unwrap(), ChannelRef::Discord(55));
+    assert_eq!(ChannelRef::parse_full("#rust").unwrap(), ChannelRef::Irc("#rust"));
+
+    parse_err!(
+        "1x" => Duration => "parsing duration (like 1h30m): failed to parse \"1x\" as a duration: unexpected input at 1x";
+        "feet" => OneOf<Units> => "parsing one of metric|imperial: expected one of metric|imperial, got \"feet\"";
+        "1nick" => UserRef => "parsing user (nick or @mention): \"1nick\" is not a nick or user mention";
+        "rust" => ChannelRef => "parsing channel (#name or #mention): \"rust\" is not a channel name or mention";
+        "1 x" => (Vec<u32>, u32) => "parsing (zero or more u32, u32): failed to parse \"x\" as u32: invalid digit found in string";
+    );
+}
+
+#[test]
+fn test_tokenize() {
+    use crate::args::tokenize;
+
+    assert_eq!(
+        tokenize("  a \"b c\" 'd \"e\"' f\\ g \"h\\\"i\"j ").unwrap(),
+        vec!["a", "b c", "d \"e\"", "f g", "h\"ij"]
+    );
+    assert_eq!(tokenize("'a\\b'").unwrap(), vec!["a\\b"]);
+    assert!(matches!(tokenize("\"foo bar\"").unwrap()[0], Cow::Borrowed("foo bar")));
+    assert_eq!(tokenize("\"abc").unwrap_err().to_string(), "unterminated quoted string");
+    assert_eq!(
+        tokenize("abc\\").unwrap_err().to_string(),
+        "unfinished escape sequence at end of input"
+    );
+
+    assert_eq!(tokenize("a  b").unwrap(), vec!["a", "b"]);
+    assert_eq!(
+        <(Cow<str>, Cow<str>)>::parse_full("a  b").unwrap(),
+        (Cow::Borrowed("a"), Cow::Borrowed("b"))
+    );
+    assert_eq!(
+        <(Cow<str>, Cow<str>)>::parse_full(" a b").unwrap(),
+        (Cow::Borrowed("a"), Cow::Borrowed("b"))
+    );
+    assert_eq!(
+        <(Cow<str>, Cow<str>)>::parse_full("a b ").unwrap(),
+        (Cow::Borrowed("a"), Cow::Borrowed("b"))
+    );
+    assert_eq!(
+        <(Cow<str>, u32)>::parse_full("'it''s' 3").unwrap(),
+        (Cow::Owned("its".to_string()), 3)
+    );
+}
+
+#[test]
+fn test_flags() {
+    use crate::args::{Flag, FlagSet, Flags};
+    use crate::error::Result;
+
+    #[derive(Debug, Default, PartialEq)]
+    struct TestFlags {
+        tz: Option<String>,
+        count: Option<u32>,
+        verbose: bool,
+    }
+    impl FlagSet for TestFlags {
+        const FLAGS: &'static [Flag] = &[
+            Flag::value("tz"),
+            Flag::value("count").short('n'),
+            Flag::switch("verbose").short('v'),
+        ];
+        fn set(&mut self, name: &str, value: Option<&str>) -> Result<()> {
+            match (name, value) {
+                ("tz", Some(v)) => self.tz = Some(v.to_string()),
+                ("count", Some(v)) => self.count = Some(u32::parse_full(v)?),
+                _ => self.verbose = true,
+            }
+            Ok(())
+        }
+    }
+
+    let flags = Flags::<TestFlags>::parse_full("foo --tz 'Europe/London' -1 -n 5 \"bar baz\" -v").unwrap();
+    assert_eq!(
+        flags.flags,
+        TestFlags {
+            tz: Some("Europe/London".to_string()),
+            count: Some(5),
+            verbose: true,
+        }
+    );
+    assert_eq!(flags.positional(), "foo -1 \"bar baz\"");
+    assert_eq!(
+        <(Atom, i32, Cow<str>)>::parse_full(flags.positional()).unwrap(),
+        (Atom("foo"), -1, Cow::Borrowed("bar baz"))
+    );
+
+    let flags = Flags::<TestFlags>::parse_full("--count=2 \"--tz\" -- --verbose x").unwrap();
+    assert_eq!(flags.count, Some(2));
+    assert!(!flags.verbose);
+    assert_eq!(flags.positional(), "\"--tz\" --verbose x");
+
+    assert_eq!(Flags::<TestFlags>::parse_full("").unwrap().positional(), "");
+
+    parse_err!(
+        "--frob" => Flags<TestFlags> => "parsing flags [--tz <value>] [-n/--count <value>] [-v/--verbose] and arguments: unknown flag --frob";
+        "a --tz" => Flags<TestFlags> => "parsing flags [--tz <value>] [-n/--count <value>] [-v/--verbose] and arguments: flag --tz needs a value";
+        "--verbose=yes" => Flags<TestFlags> => "parsing flags [--tz <value>] [-n/--count <value>] [-v/--verbose] and arguments: flag --verbose does not take a value";
+        "-n five" => Flags<TestFlags> => "parsing flags [--tz <value>] [-n/--count <value>] [-v/--verbose] and arguments: parsing u32: failed to parse \"five\" as u32: invalid digit found in string";
+    );
+}
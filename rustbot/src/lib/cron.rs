@@ -0,0 +1,136 @@
+// Cron expressions for Schedule::Cron: five fields, "minute hour day-of-month month day-of-week", each
+// `*`, a number or a range `a-b`, optionally with a step `/n`, or a comma-separated list of those.
+// Day of week is 0-7, where both 0 and 7 are Sunday. As in cron, if both day fields are restricted,
+// a day matching either one matches. Times are in UTC.
+
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, NaiveTime, TimeZone, Timelike, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cron {
+    minutes: u64, // one bit per allowed value
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool, // whether the day-of-month field was `*`
+    any_weekday: bool,
+    source: String,
+}
+
+// Parses one field into a bitset of its values, and whether it was `*`.
+fn field(s: &str, name: &str, min: u32, max: u32) -> Result<(u64, bool)> {
+    let number = |n: &str| -> Result<u32> {
+        match n.parse() {
+            Ok(v) if (min..=max).contains(&v) => Ok(v),
+            _ => bail_user!("invalid {} {:?}, expected {}-{}", name, n, min, max),
+        }
+    };
+
+    let mut bits = 0;
+    for part in s.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse() {
+                Ok(step) if step > 0 => (range, step),
+                _ => bail_user!("invalid step {:?} in {}", step, name),
+            },
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((a, b)) => (number(a)?, number(b)?),
+            // "a/n" means every nth value from a
+            None if step > 1 => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if start > end {
+            bail_user!("invalid {} range {:?}", name, range);
+        }
+        for v in (start..=end).step_by(step) {
+            bits |= 1 << v;
+        }
+    }
+    Ok((bits, s == "*"))
+}
+
+impl Cron {
+    pub fn parse(s: &str) -> Result<Self> {
+        let fields: Vec<_> = s.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail_user!("expected 5 fields in cron expression {:?}, got {}", s, fields.len());
+        };
+
+        let (minutes, _) = field(minute, "minute", 0, 59)?;
+        let (hours, _) = field(hour, "hour", 0, 23)?;
+        let (days, any_day) = field(day, "day of month", 1, 31)?;
+        let (months, _) = field(month, "month", 1, 12)?;
+        let (mut weekdays, any_weekday) = field(weekday, "day of week", 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            minutes,
+            hours,
+            days,
+            months,
+            weekdays,
+            any_day,
+            any_weekday,
+            source: fields.join(" "),
+        })
+    }
+
+    fn matches_day(&self, date: DateTime<Utc>) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+
+    // The first minute after `after` that the expression matches, or None if there isn't one in the
+    // next few years (e.g. for "0 0 31 2 *").
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+        let mut day = Utc.from_utc_datetime(&start.date_naive().and_time(NaiveTime::MIN));
+
+        for _ in 0..(366 * 5) {
+            if self.matches_day(day) {
+                for hour in (0..24).filter(|h| self.hours & (1 << h) != 0) {
+                    for minute in (0..60).filter(|m| self.minutes & (1 << m) != 0) {
+                        let t = day + ChronoDuration::minutes(i64::from(hour * 60 + minute));
+                        if t >= start {
+                            return Some(t);
+                        }
+                    }
+                }
+            }
+            day += ChronoDuration::days(1);
+        }
+        None
+    }
+}
+
+impl FromStr for Cron {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
@@ -0,0 +1,168 @@
+// Key-value storage for modules, so that simple ones don't need a table of their own. Every key belongs
+// to a module, and optionally to a config, a channel and a user as well:
+//
+//     let kv = ctx.bot().kv("quote").config(ctx.config_id()).user(&ctx.source().user_string());
+//     kv.set("last", &quote)?;
+//     let last: Option<Quote> = kv.get("last")?;
+//
+// Values are stored as JSON, so anything serde can (de)serialize will do.
+
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::prelude::*;
+use crate::sql::Sql;
+
+// Which keys a Kv can see. Empty strings mean "not scoped by this", so a key set without a channel is
+// not visible to a Kv that has one, and vice versa.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct KvScope {
+    pub module: String,
+    pub config: String,
+    pub channel: String,
+    pub user: String,
+}
+
+// Where a Kv keeps its values.
+pub trait KvBackend: Send + Sync {
+    fn get(&self, scope: &KvScope, key: &str) -> Result<Option<Value>>;
+    fn set(&self, scope: &KvScope, key: &str, value: Value) -> Result<()>;
+    // Returns whether the key existed.
+    fn delete(&self, scope: &KvScope, key: &str) -> Result<bool>;
+    // Every key in scope that starts with `prefix`, in order of their bytes.
+    fn scan_prefix(&self, scope: &KvScope, prefix: &str) -> Result<Vec<(String, Value)>>;
+}
+
+pub struct Kv<'a> {
+    backend: &'a dyn KvBackend,
+    scope: KvScope,
+}
+
+impl<'a> Kv<'a> {
+    pub fn new(backend: &'a dyn KvBackend, module: &str) -> Self {
+        Self {
+            backend,
+            scope: KvScope {
+                module: module.to_string(),
+                ..KvScope::default()
+            },
+        }
+    }
+
+    #[must_use]
+    pub fn config(mut self, config: &str) -> Self {
+        self.scope.config = config.to_string();
+        self
+    }
+
+    #[must_use]
+    pub fn channel(mut self, channel: &str) -> Self {
+        self.scope.channel = channel.to_string();
+        self
+    }
+
+    #[must_use]
+    pub fn user(mut self, user: &str) -> Self {
+        self.scope.user = user.to_string();
+        self
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match self.backend.get(&self.scope, key)? {
+            Some(v) => Ok(Some(
+                serde_json::from_value(v).with_context(|| format!("invalid value for key {key:?}"))?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<()> {
+        self.backend.set(&self.scope, key, serde_json::to_value(value)?)
+    }
+
+    pub fn delete(&self, key: &str) -> Result<bool> {
+        self.backend.delete(&self.scope, key)
+    }
+
+    pub fn scan_prefix<T: DeserializeOwned>(&self, prefix: &str) -> Result<Vec<(String, T)>> {
+        self.backend
+            .scan_prefix(&self.scope, prefix)?
+            .into_iter()
+            .map(|(k, v)| match serde_json::from_value(v) {
+                Ok(v) => Ok((k, v)),
+                Err(e) => Err(Error::new(e).context(format!("invalid value for key {k:?}"))),
+            })
+            .collect()
+    }
+}
+
+// The kv table, through the bot's database connection.
+impl KvBackend for Sql {
+    fn get(&self, scope: &KvScope, key: &str) -> Result<Option<Value>> {
+        let row = self.lock().query_opt(
+            "SELECT value FROM kv WHERE module = $1 AND config_id = $2 AND channel = $3 AND user_string = $4 AND key = $5",
+            &[&scope.module, &scope.config, &scope.channel, &scope.user, &key],
+        )?;
+        Ok(row.map(|r| r.get(0)))
+    }
+
+    fn set(&self, scope: &KvScope, key: &str, value: Value) -> Result<()> {
+        self.lock().execute(
+            "INSERT INTO kv (module, config_id, channel, user_string, key, value) VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (module, config_id, channel, user_string, key) DO UPDATE SET value = EXCLUDED.value",
+            &[&scope.module, &scope.config, &scope.channel, &scope.user, &key, &value],
+        )?;
+        Ok(())
+    }
+
+    fn delete(&self, scope: &KvScope, key: &str) -> Result<bool> {
+        let n = self.lock().execute(
+            "DELETE FROM kv WHERE module = $1 AND config_id = $2 AND channel = $3 AND user_string = $4 AND key = $5",
+            &[&scope.module, &scope.config, &scope.channel, &scope.user, &key],
+        )?;
+        Ok(n > 0)
+    }
+
+    fn scan_prefix(&self, scope: &KvScope, prefix: &str) -> Result<Vec<(String, Value)>> {
+        let rows = self.lock().query(
+            "SELECT key, value FROM kv
+            WHERE module = $1 AND config_id = $2 AND channel = $3 AND user_string = $4 AND starts_with(key, $5)
+            ORDER BY key COLLATE \"C\"",
+            &[&scope.module, &scope.config, &scope.channel, &scope.user, &prefix],
+        )?;
+        Ok(rows.into_iter().map(|r| (r.get(0), r.get(1))).collect())
+    }
+}
+
+// Values kept in memory, for tests.
+#[derive(Default)]
+pub struct MemoryKv {
+    values: Mutex<BTreeMap<(KvScope, String), Value>>,
+}
+
+impl KvBackend for MemoryKv {
+    fn get(&self, scope: &KvScope, key: &str) -> Result<Option<Value>> {
+        Ok(self.values.lock().get(&(scope.clone(), key.to_string())).cloned())
+    }
+
+    fn set(&self, scope: &KvScope, key: &str, value: Value) -> Result<()> {
+        self.values.lock().insert((scope.clone(), key.to_string()), value);
+        Ok(())
+    }
+
+    fn delete(&self, scope: &KvScope, key: &str) -> Result<bool> {
+        Ok(self.values.lock().remove(&(scope.clone(), key.to_string())).is_some())
+    }
+
+    fn scan_prefix(&self, scope: &KvScope, prefix: &str) -> Result<Vec<(String, Value)>> {
+        Ok(self
+            .values
+            .lock()
+            .range((scope.clone(), prefix.to_string())..)
+            .take_while(|((s, k), _)| s == scope && k.starts_with(prefix))
+            .map(|((_, k), v)| (k.clone(), v.clone()))
+            .collect())
+    }
+}
@@ -0,0 +1,59 @@
+// What a module declares about itself, checked by the bot before it calls anything in the module. Every
+// module crate exports these with the manifest! macro:
+//
+//     rustbot::manifest!();              // no dependencies
+//     rustbot::manifest!("bridge");      // loads the bridge module first
+//
+// RUSTBOT_ABI says which build of librustbot the module was compiled against. Types shared between
+// the bot and a module, like dyn Meta, only agree if both were built by the same compiler from the same
+// librustbot, so a module whose ABI differs from the bot's is refused rather than called.
+
+// Length of RUSTBOT_ABI; the string in it is NUL-padded. It is a plain byte array so that it can be read
+// safely from a module built by any compiler.
+pub const ABI_LEN: usize = 128;
+
+pub const ABI: [u8; ABI_LEN] = pad(concat!(
+    "rustbot ",
+    env!("CARGO_PKG_VERSION"),
+    ", ",
+    env!("RUSTBOT_RUSTC_VERSION")
+));
+
+const fn pad(s: &str) -> [u8; ABI_LEN] {
+    let bytes = s.as_bytes();
+    let mut out = [0; ABI_LEN];
+    let mut i = 0;
+    while i < bytes.len() && i < ABI_LEN - 1 {
+        out[i] = bytes[i];
+        i += 1;
+    }
+    out
+}
+
+// The string in an ABI array.
+pub fn abi_str(abi: &[u8; ABI_LEN]) -> String {
+    let len = abi.iter().position(|&b| b == 0).unwrap_or(ABI_LEN);
+    String::from_utf8_lossy(&abi[..len]).into_owned()
+}
+
+// Only read from a module once its ABI has been checked.
+pub struct Manifest {
+    pub name: &'static str, // the module's crate name, e.g. "mod_remind"
+    pub version: &'static str,
+    pub depends: &'static [&'static str], // modules to load first, by the names they're loaded as
+}
+
+#[macro_export]
+macro_rules! manifest {
+    ($($depends:literal),* $(,)?) => {
+        #[no_mangle]
+        pub static RUSTBOT_ABI: [u8; $crate::manifest::ABI_LEN] = $crate::manifest::ABI;
+
+        #[no_mangle]
+        pub static RUSTBOT_MANIFEST: $crate::manifest::Manifest = $crate::manifest::Manifest {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            depends: &[$($depends),*],
+        };
+    };
+}
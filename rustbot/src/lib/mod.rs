@@ -10,11 +10,20 @@ extern crate toml;
 pub extern crate futures;
 pub extern crate tokio;
 
+// So that code generated by rustbot_derive can refer to ::rustbot from within this crate's tests too
+extern crate self as rustbot;
+
 pub mod args;
+pub mod cron;
 pub mod duration;
 pub mod error;
 pub mod format;
+pub mod kv;
+pub mod manifest;
 pub mod spans;
+pub mod sql;
+pub mod testing;
+pub mod time;
 pub mod types;
 
 #[cfg(test)]
@@ -23,17 +32,22 @@ mod args_test;
 mod test;
 
 pub mod prelude {
-    pub use crate::args::{parse_args, Arg, Atom, Rest};
+    pub use crate::args::{
+        describe_args, parse_args, Arg, Atom, ChannelRef, Flag, FlagSet, Flags, Keyword, OneOf, Rest, UserRef,
+    };
     pub use crate::bail_user;
+    pub use crate::cron::Cron;
     pub use crate::duration::*;
     pub use crate::error::*;
     pub use crate::format::*;
+    pub use crate::kv::Kv;
     pub use crate::spans::*;
     pub use crate::thread;
     pub use crate::types::*;
     pub use anyhow::Context as AnyhowContext; // would conflict with types::Context, but we just need the trait in scope here and don't care about names
     pub use anyhow::{anyhow, bail, Error};
     pub use log::{debug, error, info, trace, warn};
+    pub use rustbot_derive::Arg;
 }
 
 // This is roughly equivalent to anyhow's bail!(), but returns a UserError inside the Error so that the user sees the message.
@@ -0,0 +1,69 @@
+// The database connection that the bot and all of its modules share. It locks like the
+// Mutex<postgres::Client> it wraps, and can report how long each lock was held, which is how long its
+// holder spent on queries:
+//
+//     let rows = bot.sql().lock().query("SELECT ...", &[])?;
+
+use parking_lot::{Mutex, MutexGuard};
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
+
+type ReleaseFn = dyn Fn(Duration) + Send + Sync;
+
+pub struct Sql {
+    client: Mutex<postgres::Client>,
+    on_release: Option<Box<ReleaseFn>>,
+}
+
+impl Sql {
+    pub fn new(client: postgres::Client) -> Self {
+        Self {
+            client: Mutex::new(client),
+            on_release: None,
+        }
+    }
+
+    // Calls `f` with how long the connection was held every time a lock is released.
+    #[must_use]
+    pub fn on_release<F: 'static + Fn(Duration) + Send + Sync>(mut self, f: F) -> Self {
+        self.on_release = Some(Box::new(f));
+        self
+    }
+
+    pub fn lock(&self) -> SqlGuard<'_> {
+        let guard = self.client.lock();
+        SqlGuard {
+            guard,
+            locked: Instant::now(),
+            on_release: self.on_release.as_deref(),
+        }
+    }
+}
+
+pub struct SqlGuard<'a> {
+    guard: MutexGuard<'a, postgres::Client>,
+    locked: Instant,
+    on_release: Option<&'a ReleaseFn>,
+}
+
+impl Deref for SqlGuard<'_> {
+    type Target = postgres::Client;
+
+    fn deref(&self) -> &postgres::Client {
+        &self.guard
+    }
+}
+
+impl DerefMut for SqlGuard<'_> {
+    fn deref_mut(&mut self) -> &mut postgres::Client {
+        &mut self.guard
+    }
+}
+
+impl Drop for SqlGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(f) = self.on_release {
+            f(self.locked.elapsed());
+        }
+    }
+}
@@ -1,4 +1,7 @@
 use super::duration;
+use super::manifest;
+use super::prelude::*;
+use super::testing::{Sent, TestBot, TestContext, TestSource};
 
 #[test]
 fn test_parse_duration() {
@@ -29,3 +32,209 @@ fn test_parse_duration() {
         assert_eq!(duration::parse_duration(case.0).unwrap_err().to_string(), case.1);
     }
 }
+
+#[test]
+fn test_command_req_perms() {
+    let cmd = Command::new(|ctx, args| ctx.say(&format!("ran with {args}"))).req_perms(Perms::Admin);
+
+    let bot = TestBot::new();
+    let ctx = TestContext::new(&bot);
+    cmd.call(&ctx, "foo").unwrap();
+    assert_eq!(ctx.replies(), vec![]);
+
+    let ctx = TestContext::new(&bot).with_perms(Perms::Admin | Perms::Raw);
+    cmd.call(&ctx, "foo").unwrap();
+    assert_eq!(ctx.replies(), vec![Message::Simple("ran with foo".to_string())]);
+}
+
+#[test]
+fn test_command_help_metadata() {
+    #[derive(Arg)]
+    struct HelpArgs {
+        n: u64,
+    }
+
+    let cmd = Command::new(|_, _| Ok(()))
+        .description("does nothing")
+        .usage(HelpArgs::describe_expected())
+        .example("3");
+    let cmd = cmd.req_perms(Perms::Admin);
+
+    assert_eq!(cmd.description.as_deref(), Some("does nothing"));
+    assert_eq!(cmd.usage.as_deref(), Some("(n: u64)"));
+    assert_eq!(HelpArgs::parse_full("3").unwrap().n, 3);
+    assert_eq!(cmd.example.as_deref(), Some("3"));
+}
+
+#[test]
+fn test_command_group() {
+    let group = CommandGroup::new()
+        .description("a group")
+        .sub(
+            "add",
+            Command::new(|ctx, args| ctx.say(&format!("add {args}"))).usage("<thing>"),
+        )
+        .sub("nuke", Command::new(|ctx, _| ctx.say("boom")).req_perms(Perms::Admin));
+    let cmd: Command = group.clone().into();
+
+    assert_eq!(cmd.description.as_deref(), Some("a group"));
+    assert_eq!(cmd.usage.as_deref(), Some("add <thing> | nuke"));
+    assert_eq!(cmd.perms_for("add x"), Perms::None);
+    assert_eq!(cmd.perms_for(" nuke  x"), Perms::Admin);
+    assert_eq!(cmd.perms_for("frob"), Perms::None);
+
+    let bot = TestBot::new();
+    let ctx = TestContext::new(&bot);
+    cmd.call(&ctx, "add  a b").unwrap();
+    assert_eq!(ctx.take_replies(), vec![Message::Simple("add a b".to_string())]);
+
+    let err = |args: &str| cmd.call(&ctx, args).unwrap_err().to_string();
+    assert_eq!(err(""), "missing subcommand; usage: add <thing>");
+    assert_eq!(err("frob"), "unknown subcommand \"frob\"; usage: add <thing>");
+    assert_eq!(err("nuke"), "not permitted to use \"nuke\"");
+
+    let admin = TestContext::new(&bot).with_perms(Perms::Admin);
+    cmd.call(&admin, "nuke").unwrap();
+    assert_eq!(admin.take_replies(), vec![Message::Simple("boom".to_string())]);
+
+    let cmd: Command = group
+        .fallback(Command::new(|ctx, args| ctx.say(&format!("fallback {args:?}"))))
+        .into();
+    assert_eq!(cmd.usage.as_deref(), Some("add <thing> | nuke | ..."));
+    cmd.call(&ctx, "").unwrap();
+    cmd.call(&ctx, "frob x").unwrap();
+    assert_eq!(
+        cmd.call(&ctx, "nuke x").unwrap_err().to_string(),
+        "not permitted to use \"nuke\""
+    );
+    assert_eq!(
+        ctx.take_replies(),
+        vec![
+            Message::Simple("fallback \"\"".to_string()),
+            Message::Simple("fallback \"frob x\"".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_harness_records_sends() {
+    let bot = TestBot::new();
+    let ctx = TestContext::new(&bot).with_source(TestSource::discord("someone", Some(1), 2, 3));
+
+    assert_eq!(ctx.source().channel_string(), "dis:1:2");
+    assert_eq!(ctx.source().user_string(), "Some(1):3");
+
+    let target = ctx.source().channel_string().into_owned();
+    ctx.bot()
+        .send_message(
+            ctx.config_id(),
+            &target,
+            Message::Spans(spans!["a", span!(Format::Bold; "b")]),
+        )
+        .unwrap();
+    ctx.bot().irc_send_privmsg("irc", "#chan", "hello").unwrap();
+
+    assert_eq!(
+        bot.take_sent(),
+        vec![
+            Sent::Message {
+                config: "test".to_string(),
+                target: "dis:1:2".to_string(),
+                message: Message::Spans(spans!["a", span!(Format::Bold; "b")]),
+            },
+            Sent::IrcPrivmsg {
+                config: "irc".to_string(),
+                channel: "#chan".to_string(),
+                message: "hello".to_string(),
+            },
+        ]
+    );
+    assert_eq!(bot.sent(), vec![]);
+}
+
+#[test]
+fn test_cron() {
+    use chrono::{TimeZone, Utc};
+
+    let at = |y, mo, d, h, mi| Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap();
+    let next = |expr: &str, after| Cron::parse(expr).unwrap().next_after(after);
+
+    // 2026-10-18 is a Sunday
+    let now = at(2026, 10, 18, 12, 30);
+    assert_eq!(next("* * * * *", now), Some(at(2026, 10, 18, 12, 31)));
+    assert_eq!(next("*/15 * * * *", now), Some(at(2026, 10, 18, 12, 45)));
+    assert_eq!(next("0 9 * * *", now), Some(at(2026, 10, 19, 9, 0)));
+    assert_eq!(next("0 9-17/4 * * *", now), Some(at(2026, 10, 18, 13, 0)));
+    assert_eq!(next("30 12 * * *", now), Some(at(2026, 10, 19, 12, 30)));
+    assert_eq!(next("0 0 1 1,7 *", now), Some(at(2027, 1, 1, 0, 0)));
+    assert_eq!(next("0 9 * * 1-5", now), Some(at(2026, 10, 19, 9, 0)));
+    assert_eq!(next("0 9 * * 7", now), Some(at(2026, 10, 25, 9, 0)));
+    // either day field may match when both are given
+    assert_eq!(next("0 9 20 * 6", now), Some(at(2026, 10, 20, 9, 0)));
+    assert_eq!(next("0 0 29 2 *", now), Some(at(2028, 2, 29, 0, 0)));
+    assert_eq!(next("0 0 31 2 *", now), None);
+
+    assert_eq!(Cron::parse("0  9 * *  1").unwrap().to_string(), "0 9 * * 1");
+
+    #[rustfmt::skip]
+    let error_cases = &[
+        ("* * * *", "expected 5 fields in cron expression \"* * * *\", got 4"),
+        ("60 * * * *", "invalid minute \"60\", expected 0-59"),
+        ("* * 0 * *", "invalid day of month \"0\", expected 1-31"),
+        ("*/0 * * * *", "invalid step \"0\" in minute"),
+        ("* 5-2 * * *", "invalid hour range \"5-2\""),
+    ];
+
+    for case in error_cases {
+        assert_eq!(Cron::parse(case.0).unwrap_err().to_string(), case.1);
+    }
+}
+
+#[test]
+fn test_kv() {
+    let bot = TestBot::new();
+    let kv = bot.kv("test");
+    let user = bot.kv("test").config("irc").user("nick");
+
+    kv.set("a", &1).unwrap();
+    kv.set("list:b", &vec!["x", "y"]).unwrap();
+    kv.set("list:a", &vec!["z"]).unwrap();
+    user.set("a", "user's").unwrap();
+    bot.kv("other").set("list:c", &[0]).unwrap();
+
+    assert_eq!(kv.get::<i32>("a").unwrap(), Some(1));
+    assert_eq!(user.get::<String>("a").unwrap(), Some("user's".to_string()));
+    assert_eq!(bot.kv("test").config("irc").get::<i32>("a").unwrap(), None);
+    assert_eq!(
+        kv.scan_prefix::<Vec<String>>("list:").unwrap(),
+        vec![
+            ("list:a".to_string(), vec!["z".to_string()]),
+            ("list:b".to_string(), vec!["x".to_string(), "y".to_string()]),
+        ]
+    );
+    assert_eq!(
+        kv.get::<String>("a").unwrap_err().to_string(),
+        "invalid value for key \"a\""
+    );
+
+    kv.set("a", &2).unwrap();
+    assert_eq!(kv.get::<i32>("a").unwrap(), Some(2));
+    assert!(kv.delete("a").unwrap());
+    assert!(!kv.delete("a").unwrap());
+    assert_eq!(kv.get::<i32>("a").unwrap(), None);
+    assert_eq!(user.get::<String>("a").unwrap(), Some("user's".to_string()));
+}
+
+crate::manifest!("bridge", "time");
+
+#[test]
+fn test_manifest() {
+    let abi = manifest::abi_str(&RUSTBOT_ABI);
+    assert_eq!(abi, manifest::abi_str(&manifest::ABI));
+    assert!(abi.starts_with("rustbot 0.1.0, rustc "), "{}", abi);
+    assert_eq!(manifest::abi_str(&[b'x'; manifest::ABI_LEN]).len(), manifest::ABI_LEN);
+
+    assert_eq!(RUSTBOT_MANIFEST.name, "rustbot");
+    assert_eq!(RUSTBOT_MANIFEST.version, "0.1.0");
+    assert_eq!(RUSTBOT_MANIFEST.depends, ["bridge", "time"]);
+}
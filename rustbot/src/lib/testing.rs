@@ -0,0 +1,349 @@
+// In-process stand-ins for the bot, message context and source, so that module crates can call their
+// command and handler functions directly from tests and assert on what they sent.
+//
+//     let bot = TestBot::new();
+//     let ctx = TestContext::new(&bot).with_source(TestSource::irc("nick", Some("#chan")));
+//     my_command(&ctx, "args")?;
+//     assert_eq!(ctx.replies(), vec![Message::Simple("...".to_string())]);
+
+use parking_lot::Mutex;
+use std::borrow::Cow;
+use std::time::SystemTime;
+
+use crate::kv::MemoryKv;
+use crate::prelude::*;
+use crate::sql::Sql;
+
+// Everything a TestBot was asked to send, in the order it was asked.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Sent {
+    IrcPrivmsg {
+        config: String,
+        channel: String,
+        message: String,
+    },
+    IrcRaw {
+        config: String,
+        line: String,
+    },
+    DisMessage {
+        config: String,
+        guild: String,
+        channel: String,
+        message: String,
+        process: bool,
+    },
+    Message {
+        config: String,
+        target: String,
+        message: Message<'static>,
+    },
+    Job {
+        id: i64,
+        module: String,
+        name: String,
+        at: SystemTime,
+        payload: String,
+    },
+    CancelJob {
+        id: i64,
+    },
+}
+
+type UnprocessFn = dyn Fn(&str, &str, &str) -> Result<String> + Send + Sync;
+
+pub struct TestBot {
+    sql: Option<Sql>,
+    sent: Mutex<Vec<Sent>>,
+    unprocess: Box<UnprocessFn>,
+    fail_sends: bool,
+    kv: MemoryKv,
+}
+
+impl Default for TestBot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestBot {
+    pub fn new() -> Self {
+        Self {
+            sql: None,
+            sent: Mutex::new(vec![]),
+            unprocess: Box::new(|_, _, message| Ok(message.to_string())),
+            fail_sends: false,
+            kv: MemoryKv::default(),
+        }
+    }
+
+    // Use a real database connection for Bot::sql(); without one, sql() panics.
+    #[must_use]
+    pub fn with_sql(mut self, client: postgres::Client) -> Self {
+        self.sql = Some(Sql::new(client));
+        self
+    }
+
+    // Replace the default dis_unprocess_message behaviour, which returns the message unchanged.
+    #[must_use]
+    pub fn with_unprocess<F: 'static + Fn(&str, &str, &str) -> Result<String> + Send + Sync>(mut self, f: F) -> Self {
+        self.unprocess = Box::new(f);
+        self
+    }
+
+    // Make every send_* call fail after recording it, to exercise error paths.
+    #[must_use]
+    pub fn with_failing_sends(mut self) -> Self {
+        self.fail_sends = true;
+        self
+    }
+
+    pub fn sent(&self) -> Vec<Sent> {
+        self.sent.lock().clone()
+    }
+
+    pub fn take_sent(&self) -> Vec<Sent> {
+        std::mem::take(&mut *self.sent.lock())
+    }
+
+    fn record(&self, s: Sent) -> Result<()> {
+        self.sent.lock().push(s);
+        if self.fail_sends {
+            bail!("send failed (TestBot configured to fail sends)")
+        }
+        Ok(())
+    }
+}
+
+impl Bot for TestBot {
+    fn sql(&self) -> &Sql {
+        self.sql
+            .as_ref()
+            .expect("TestBot has no database; use TestBot::with_sql to provide one")
+    }
+
+    fn irc_send_privmsg(&self, config: &str, channel: &str, message: &str) -> Result<()> {
+        self.record(Sent::IrcPrivmsg {
+            config: config.to_string(),
+            channel: channel.to_string(),
+            message: message.to_string(),
+        })
+    }
+
+    fn irc_send_raw(&self, config: &str, line: &str) -> Result<()> {
+        self.record(Sent::IrcRaw {
+            config: config.to_string(),
+            line: line.to_string(),
+        })
+    }
+
+    fn dis_unprocess_message(&self, config: &str, guild: &str, message: &str) -> Result<String> {
+        (self.unprocess)(config, guild, message)
+    }
+
+    fn dis_send_message(&self, config: &str, guild: &str, channel: &str, message: &str, process: bool) -> Result<()> {
+        self.record(Sent::DisMessage {
+            config: config.to_string(),
+            guild: guild.to_string(),
+            channel: channel.to_string(),
+            message: message.to_string(),
+            process,
+        })
+    }
+
+    fn send_message(&self, config: &str, target: &str, message: Message) -> Result<()> {
+        self.record(Sent::Message {
+            config: config.to_string(),
+            target: target.to_string(),
+            message: message.into_owned(),
+        })
+    }
+
+    fn quiet_name<'a>(&self, _config: &str, _target: &str, name: &'a str) -> Cow<'a, str> {
+        Cow::Borrowed(name)
+    }
+
+    // Jobs are recorded rather than run; their ids count up from 1.
+    fn schedule_job(&self, module: &str, name: &str, at: SystemTime, payload: &str) -> Result<i64> {
+        let id = 1 + self
+            .sent
+            .lock()
+            .iter()
+            .filter(|s| matches!(s, Sent::Job { .. }))
+            .count() as i64;
+        self.sent.lock().push(Sent::Job {
+            id,
+            module: module.to_string(),
+            name: name.to_string(),
+            at,
+            payload: payload.to_string(),
+        });
+        Ok(id)
+    }
+
+    fn cancel_job(&self, id: i64) -> Result<bool> {
+        let mut sent = self.sent.lock();
+        let exists = sent.iter().any(|s| matches!(s, Sent::Job { id: i, .. } if *i == id));
+        sent.push(Sent::CancelJob { id });
+        Ok(exists)
+    }
+
+    // Kept in memory, unless there is a database from TestBot::with_sql.
+    fn kv(&self, module: &str) -> Kv<'_> {
+        match &self.sql {
+            Some(sql) => Kv::new(sql, module),
+            None => Kv::new(&self.kv, module),
+        }
+    }
+}
+
+// A message source; the constructors produce the same user and channel strings as the real IRC and
+// Discord sources.
+#[derive(Clone, Debug)]
+pub struct TestSource {
+    pub user_string: String,
+    pub user_pretty: String,
+    pub channel_string: String,
+
+    pub discord_params: Option<(Option<u64>, u64, u64)>,
+    pub irc_params: Option<(Option<String>, String)>,
+}
+
+impl TestSource {
+    pub fn irc(nick: &str, channel: Option<&str>) -> Self {
+        Self {
+            user_string: format!("{nick}!{nick}@test.invalid"),
+            user_pretty: nick.to_string(),
+            channel_string: match channel {
+                Some(ch) => format!("irc:{ch}"),
+                None => "irc:query".to_string(),
+            },
+            discord_params: None,
+            irc_params: Some((channel.map(str::to_string), nick.to_string())),
+        }
+    }
+
+    pub fn discord(name: &str, guild: Option<u64>, channel: u64, user: u64) -> Self {
+        Self {
+            user_string: format!("{guild:?}:{user}"),
+            user_pretty: name.to_string(),
+            channel_string: format!(
+                "dis:{}:{}",
+                guild.map_or_else(|| "none".to_string(), |g| format!("{g}")),
+                channel
+            ),
+            discord_params: Some((guild, channel, user)),
+            irc_params: None,
+        }
+    }
+}
+
+impl Source for TestSource {
+    fn user_string(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.user_string)
+    }
+    fn user_pretty(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.user_pretty)
+    }
+    fn channel_string(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.channel_string)
+    }
+
+    fn get_discord_params(&self) -> Option<(Option<u64>, u64, u64)> {
+        self.discord_params
+    }
+    fn get_irc_params(&self) -> Option<(Option<String>, String)> {
+        self.irc_params.clone()
+    }
+
+    fn parse_message(&self, msg: &str) -> Result<Option<MessageText<'static>>> {
+        Ok(Some(MessageText::Plain(spans![msg.to_string()])))
+    }
+}
+
+pub struct TestContext<'a> {
+    bot: &'a TestBot,
+    config: String,
+    source: TestSource,
+    perms: Perms,
+
+    replies: Mutex<Vec<Message<'static>>>,
+    subs: Mutex<Vec<(String, String)>>,
+}
+
+impl<'a> TestContext<'a> {
+    // A context for an IRC user "tester" in #test on config "test", with no permissions.
+    pub fn new(bot: &'a TestBot) -> Self {
+        Self {
+            bot,
+            config: "test".to_string(),
+            source: TestSource::irc("tester", Some("#test")),
+            perms: Perms::None,
+            replies: Mutex::new(vec![]),
+            subs: Mutex::new(vec![]),
+        }
+    }
+
+    #[must_use]
+    pub fn with_config(mut self, config: &str) -> Self {
+        self.config = config.to_string();
+        self
+    }
+
+    #[must_use]
+    pub fn with_source(mut self, source: TestSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    #[must_use]
+    pub fn with_perms(mut self, perms: Perms) -> Self {
+        self.perms = perms;
+        self
+    }
+
+    pub fn replies(&self) -> Vec<Message<'static>> {
+        self.replies.lock().clone()
+    }
+
+    pub fn take_replies(&self) -> Vec<Message<'static>> {
+        std::mem::take(&mut *self.replies.lock())
+    }
+
+    // Every do_sub() call made through this context, as (name, message).
+    pub fn subs(&self) -> Vec<(String, String)> {
+        self.subs.lock().clone()
+    }
+}
+
+impl<'a> Context for TestContext<'a> {
+    fn config_id(&self) -> &str {
+        &self.config
+    }
+
+    fn bot(&self) -> &(dyn Bot + Sync) {
+        self.bot
+    }
+
+    fn say(&self, message: &str) -> Result<()> {
+        self.reply(Message::Simple(message.to_string()))
+    }
+
+    fn reply(&self, message: Message) -> Result<()> {
+        self.replies.lock().push(message.into_owned());
+        Ok(())
+    }
+
+    fn perms(&self) -> Result<Perms> {
+        Ok(self.perms)
+    }
+
+    fn source(&self) -> &dyn Source {
+        &self.source
+    }
+
+    fn do_sub(&self, name: &str, msg: &str) -> Result<()> {
+        self.subs.lock().push((name.to_string(), msg.to_string()));
+        Ok(())
+    }
+}
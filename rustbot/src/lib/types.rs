@@ -1,13 +1,18 @@
 #![allow(non_upper_case_globals)]
 
 use bitflags::bitflags;
-use parking_lot::Mutex;
 use postgres::types::{FromSql, Type};
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
+use super::cron::Cron;
 use super::error::Result;
+use super::kv::Kv;
 use super::spans::Span;
+use super::sql::Sql;
+use crate::bail_user;
 
 bitflags! {
     pub struct Perms: u64 {
@@ -50,6 +55,19 @@ pub type CommandFn = dyn Fn(&dyn Context, &str) -> Result<()> + Send + Sync;
 pub struct Command {
     pub function: Arc<CommandFn>,
     pub req_perms: Perms,
+
+    // Shown by the `help` command
+    pub description: Option<String>,
+    pub usage: Option<String>, // arguments only, without the command name
+    pub example: Option<String>,
+
+    // Enforced by the bot before calling the command, except for users with Perms::Admin
+    pub cooldown: Option<Cooldown>,
+    // How long the bot waits for the command before abandoning it; None means the bot's default
+    pub timeout: Option<Duration>,
+
+    // Set for a CommandGroup, to find the perms of the subcommand that is run
+    group: Option<Arc<CommandGroup>>,
 }
 
 impl Command {
@@ -57,6 +75,12 @@ impl Command {
         Self {
             function: Arc::new(f),
             req_perms: Perms::None,
+            description: None,
+            usage: None,
+            example: None,
+            cooldown: None,
+            timeout: None,
+            group: None,
         }
     }
     #[must_use]
@@ -65,6 +89,53 @@ impl Command {
         s.req_perms.insert(p);
         s
     }
+    #[must_use]
+    pub fn description(&self, d: &str) -> Self {
+        let mut s = self.clone();
+        s.description = Some(d.to_string());
+        s
+    }
+    // Takes a String so that an Arg's describe_expected() can be passed directly
+    #[must_use]
+    pub fn usage<S: Into<String>>(&self, u: S) -> Self {
+        let mut s = self.clone();
+        s.usage = Some(u.into());
+        s
+    }
+    #[must_use]
+    pub fn example(&self, e: &str) -> Self {
+        let mut s = self.clone();
+        s.example = Some(e.to_string());
+        s
+    }
+    // Allow `burst` uses at once, regaining one use every `refill`, counted separately for each `scope`
+    #[must_use]
+    pub fn cooldown(&self, scope: CooldownScope, burst: u32, refill: Duration) -> Self {
+        let mut s = self.clone();
+        s.cooldown = Some(Cooldown { scope, burst, refill });
+        s
+    }
+    // Allow the command to run for longer (or shorter) than the bot's default before it is abandoned
+    #[must_use]
+    pub fn timeout(&self, timeout: Duration) -> Self {
+        let mut s = self.clone();
+        s.timeout = Some(timeout);
+        s
+    }
+    // The perms needed to run the command with `args`: for a CommandGroup, its own and those of the
+    // subcommand (or fallback) that `args` select.
+    pub fn perms_for(&self, args: &str) -> Perms {
+        let mut perms = self.req_perms;
+        if let Some(group) = &self.group {
+            let (word, rest) = split_subcommand(args);
+            match (group.subcommands.get(word), &group.fallback) {
+                (Some(cmd), _) => perms.insert(cmd.perms_for(rest)),
+                (None, Some(cmd)) => perms.insert(cmd.perms_for(args)),
+                (None, None) => {}
+            }
+        }
+        perms
+    }
     pub fn call(&self, ctx: &dyn Context, args: &str) -> Result<()> {
         if !ctx.perms()?.contains(self.req_perms) {
             return Ok(());
@@ -74,6 +145,134 @@ impl Command {
     }
 }
 
+// A command made of subcommands, dispatched on the first word of its arguments; register it with
+// `meta.cmd(name, group.into())`. Each subcommand has its own req_perms on top of the group's, and the
+// group's usage is generated from the subcommands it contains.
+#[derive(Clone)]
+pub struct CommandGroup {
+    subcommands: BTreeMap<String, Command>,
+    fallback: Option<Command>,
+    req_perms: Perms,
+    description: Option<String>,
+}
+
+impl CommandGroup {
+    pub fn new() -> Self {
+        Self {
+            subcommands: BTreeMap::new(),
+            fallback: None,
+            req_perms: Perms::None,
+            description: None,
+        }
+    }
+    #[must_use]
+    pub fn sub(&self, name: &str, cmd: Command) -> Self {
+        let mut s = self.clone();
+        s.subcommands.insert(name.to_string(), cmd);
+        s
+    }
+    // Run with all of the arguments when the first word isn't a subcommand, including when there are
+    // no arguments at all, but not when it is one that the user isn't permitted to use. Without a
+    // fallback, the user is shown the usage instead.
+    #[must_use]
+    pub fn fallback(&self, cmd: Command) -> Self {
+        let mut s = self.clone();
+        s.fallback = Some(cmd);
+        s
+    }
+    #[must_use]
+    pub fn req_perms(&self, p: Perms) -> Self {
+        let mut s = self.clone();
+        s.req_perms.insert(p);
+        s
+    }
+    #[must_use]
+    pub fn description(&self, d: &str) -> Self {
+        let mut s = self.clone();
+        s.description = Some(d.to_string());
+        s
+    }
+
+    // "sub1 <usage> | sub2 | ...", listing only the subcommands that `perms` allows, if given
+    pub fn usage(&self, perms: Option<Perms>) -> String {
+        let allowed = |c: &Command| perms.is_none_or(|p| p.contains(c.req_perms));
+        let mut items: Vec<String> = self
+            .subcommands
+            .iter()
+            .filter(|(_, c)| allowed(c))
+            .map(|(name, c)| match &c.usage {
+                Some(usage) => format!("{name} {usage}"),
+                None => name.clone(),
+            })
+            .collect();
+        if let Some(c) = self.fallback.as_ref().filter(|c| allowed(c)) {
+            items.push(c.usage.clone().unwrap_or_else(|| "...".to_string()));
+        }
+        items.join(" | ")
+    }
+
+    fn dispatch(&self, ctx: &dyn Context, args: &str) -> Result<()> {
+        let args = args.trim_start();
+        let (word, rest) = split_subcommand(args);
+        let perms = ctx.perms()?;
+
+        match self.subcommands.get(word) {
+            Some(cmd) if perms.contains(cmd.req_perms) => (cmd.function)(ctx, rest),
+            Some(_) => bail_user!("not permitted to use {:?}", word),
+            None => match &self.fallback {
+                Some(cmd) => cmd.call(ctx, args),
+                None if word.is_empty() => bail_user!("missing subcommand; usage: {}", self.usage(Some(perms))),
+                None => bail_user!("unknown subcommand {:?}; usage: {}", word, self.usage(Some(perms))),
+            },
+        }
+    }
+}
+
+// The first word of a CommandGroup's arguments, and the rest after it.
+fn split_subcommand(args: &str) -> (&str, &str) {
+    let args = args.trim_start();
+    let (word, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    (word, rest.trim_start())
+}
+
+impl Default for CommandGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<CommandGroup> for Command {
+    fn from(group: CommandGroup) -> Self {
+        let group = Arc::new(group);
+        let dispatched = group.clone();
+        Self {
+            req_perms: group.req_perms,
+            description: group.description.clone(),
+            usage: Some(group.usage(None)),
+            example: None,
+            cooldown: None,
+            timeout: None,
+            function: Arc::new(move |ctx, args| dispatched.dispatch(ctx, args)),
+            group: Some(group),
+        }
+    }
+}
+
+// A token bucket limiting how often a command may be used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cooldown {
+    pub scope: CooldownScope,
+    pub burst: u32,
+    pub refill: Duration,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CooldownScope {
+    User,
+    Channel,
+    Global,
+}
+
 bitflags! {
     pub struct HandleType: u64 {
         const None       = 0x0000_0000;
@@ -97,6 +296,21 @@ pub type MsgHandlerFn = dyn Fn(&dyn Context, HandleType, &str) -> Result<()> + S
 
 pub type ThreadFn = dyn FnOnce() + 'static + Send;
 
+pub type JobFn = dyn Fn(&dyn Bot) -> Result<()> + Send + Sync;
+
+pub type JobHandlerFn = dyn Fn(&dyn Bot, &str) -> Result<()> + Send + Sync;
+
+// When a job added with Meta::schedule runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Schedule {
+    // Repeatedly, this far apart, starting one interval after the module is loaded.
+    Every(Duration),
+    // At every minute that the expression matches.
+    Cron(Cron),
+    // Once, at this time; straight away if it has already passed.
+    At(SystemTime),
+}
+
 pub trait Meta {
     fn cmd(&mut self, name: &str, cmd: Command);
     fn deinit(&mut self, f: Box<DeinitFn>);
@@ -106,10 +320,20 @@ pub trait Meta {
     fn on_unload_channel(&mut self) -> futures::channel::oneshot::Receiver<()>;
 
     fn thread(&mut self, f: Box<ThreadFn>);
+
+    // Runs `f` as `schedule` says, until the module is dropped.
+    fn schedule(&mut self, schedule: Schedule, f: Box<JobFn>);
+    // Runs this module's stored jobs named `name`; see Bot::schedule_job.
+    fn job_handler(&mut self, name: &str, f: Box<JobHandlerFn>);
+
+    // Adds a change to this module's own tables, e.g. with `include_str!`. Unless an earlier load applied
+    // it, it is applied in order of version before the module's threads start or its commands can run.
+    // `down` undoes it, when the core `schema drop` command removes the schema of a dropped module.
+    fn migration(&mut self, version: u32, up: &str, down: &str);
 }
 
 pub trait Bot {
-    fn sql(&self) -> &Mutex<postgres::Client>;
+    fn sql(&self) -> &Sql;
 
     fn irc_send_privmsg(&self, _: &str, _: &str, _: &str) -> Result<()>;
     fn irc_send_raw(&self, _: &str, _: &str) -> Result<()>;
@@ -118,6 +342,23 @@ pub trait Bot {
     fn dis_send_message(&self, _: &str, _: &str, _: &str, _: &str, _: bool) -> Result<()>;
 
     fn send_message(&self, _: &str, _: &str, _: Message) -> Result<()>;
+
+    // Alter a name so that mentioning it in a message to the given channel string won't notify the
+    // user it belongs to.
+    fn quiet_name<'a>(&self, config: &str, target: &str, name: &'a str) -> Cow<'a, str>;
+
+    // Stores a one-shot job in the database, so that it survives restarts: at `at`, the handler that
+    // `module` registered under `name` with Meta::job_handler is called with `payload`. If the module
+    // isn't loaded then, the job runs when it next is. If the handler fails, it is called again later, a
+    // few times over with growing delays, so it should be safe to repeat. Returns the job's id.
+    fn schedule_job(&self, module: &str, name: &str, at: SystemTime, payload: &str) -> Result<i64>;
+    // Removes a job added by schedule_job; false if it has already run, or never existed.
+    fn cancel_job(&self, id: i64) -> Result<bool>;
+
+    // Key-value storage belonging to `module`, kept in the kv table; see kv.rs.
+    fn kv(&self, module: &str) -> Kv<'_> {
+        Kv::new(self.sql(), module)
+    }
 }
 
 pub trait Context {
@@ -138,9 +379,12 @@ pub trait Source {
 
     fn get_discord_params(&self) -> Option<(Option<u64>, u64, u64)>;
     fn get_irc_params(&self) -> Option<(Option<String>, String)>;
+
+    // Decode network formatting in a message from this source; Ok(None) if it isn't chat text.
+    fn parse_message(&self, msg: &str) -> Result<Option<MessageText<'static>>>;
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Message<'a> {
     Simple(String),
     Spans(Vec<Span<'a>>),
@@ -152,3 +396,29 @@ pub enum Message<'a> {
         items: Vec<Cow<'a, str>>,
     },
 }
+
+// The text of an incoming message, as decoded by Source::parse_message.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MessageText<'a> {
+    Plain(Vec<Span<'a>>),
+    Action(Vec<Span<'a>>), // e.g. IRC's /me
+}
+
+impl<'a> Message<'a> {
+    pub fn into_owned(self) -> Message<'static> {
+        match self {
+            Message::Simple(s) => Message::Simple(s),
+            Message::Spans(s) => Message::Spans(s.into_iter().map(Span::into_owned).collect()),
+            Message::Prefixed(p, s) => Message::Prefixed(
+                p.into_iter().map(Span::into_owned).collect(),
+                s.into_iter().map(Span::into_owned).collect(),
+            ),
+            Message::Code(s) => Message::Code(s),
+            Message::List { prefix, sep, items } => Message::List {
+                prefix: Cow::Owned(prefix.into_owned()),
+                sep: Cow::Owned(sep.into_owned()),
+                items: items.into_iter().map(|i| Cow::Owned(i.into_owned())).collect(),
+            },
+        }
+    }
+}
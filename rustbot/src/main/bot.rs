@@ -1,6 +1,3 @@
-use ::irc::client::ext::ClientExt;
-use ::irc::client::prelude as irc;
-use ::irc::client::prelude::Client;
 use flexi_logger::{LogSpecBuilder, Logger, LoggerHandle};
 use futures::channel::oneshot::{self, Receiver, Sender};
 use libloading::Library;
@@ -9,36 +6,53 @@ use parking_lot::{Mutex, RwLock};
 use postgres::types::{FromSql, Type};
 use regex::Regex;
 use serde::Deserialize;
-use serenity::model::channel;
-use serenity::model::guild;
-use serenity::model::id::{ChannelId, GuildId};
-use serenity::prelude as dis;
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::str;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Weak};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
 
 use super::config;
 use super::context;
-use super::context::{Prefix, Source};
+use super::context::Source;
+use super::cooldown::{CooldownKey, Cooldowns};
 use super::core;
+use super::crash::{self, Crash, CrashTracker};
 use super::db;
-use super::message;
+use super::discord::DiscordPlatform;
+use super::flood::Priority;
+use super::irc::IrcPlatform;
+use super::metrics::{self, CountingPaster, Metrics};
+use super::paste;
+use super::platform::{self, Origin, Platform};
+use super::scheduler::{self, JobRef, Scheduler};
+use super::watchdog::{self, Invocation, Outcome, Running, Watchdog};
+use rustbot::manifest::{self, Manifest};
 use rustbot::prelude::{Source as LibSource, *};
+use rustbot::sql::Sql;
 use rustbot::types;
 
 pub struct Rustbot {
-    clients: RwLock<BTreeMap<String, Arc<irc::IrcClient>>>,
-    caches: RwLock<BTreeMap<String, Arc<serenity::CacheAndHttp>>>,
-    db: Mutex<postgres::Client>,
+    this: Weak<Rustbot>, // for handing to platform threads started after startup
+    config: RwLock<config::Config>,
+    paster: Arc<dyn paste::Paster>,
+    platforms: RwLock<BTreeMap<String, Arc<dyn Platform>>>,
+    db: Sql,
     modules: RwLock<BTreeMap<String, Module>>,
-    core_commands: RwLock<BTreeMap<String, (Perms, Box<core::CoreCommand>)>>,
+    core_commands: RwLock<BTreeMap<String, core::CoreCommand>>,
     commands: RwLock<BTreeMap<String, (String, Command)>>,
     logger: Mutex<LogInfo>,
 
     pub(crate) suppress_errors: RwLock<BTreeMap<String, Instant>>,
+    pub(crate) cooldowns: Cooldowns,
+    crashes: CrashTracker,
+    pub(crate) watchdog: Watchdog,
+    pub(crate) metrics: Arc<Metrics>,
+    scheduler: Scheduler,
 }
 
 struct LogInfo {
@@ -46,160 +60,60 @@ struct LogInfo {
     current_level: Level,
 }
 
-fn irc_parse_prefix(prefix: Option<String>) -> Option<context::Prefix> {
-    match prefix {
-        None => None,
-        Some(s) => {
-            if !s.contains('!') {
-                Some(Prefix::Server(s))
-            } else {
-                let ss = s.clone();
-                let nr: Vec<&str> = ss.splitn(2, '!').collect();
-                if !nr[1].contains('@') {
-                    Some(Prefix::Server(s))
-                } else {
-                    let uh: Vec<&str> = nr[1].splitn(2, '@').collect();
-                    Some(Prefix::User {
-                        nick: nr[0].to_string(),
-                        user: uh[0].to_string(),
-                        host: uh[1].to_string(),
-                    })
-                }
-            }
-        }
-    }
-}
-
 impl Rustbot {
-    fn irc_incoming(&self, cfg: String, bot_name: &str, irc_msg: irc::Message) {
-        if let irc::Command::PRIVMSG(channel, message) = irc_msg.command {
-            let mut typ = HandleType::PlainMsg;
-
-            if channel == bot_name {
-                typ |= HandleType::Private;
-            } else {
-                typ |= HandleType::Public;
-            }
-
-            let source = Source::Irc {
-                prefix: irc_parse_prefix(irc_msg.prefix),
-                channel: if channel == bot_name { None } else { Some(channel) },
-            };
-            let ctx = &context::Context {
-                bot: self,
-                config: cfg,
-                source,
-                bot_name: bot_name.to_string(),
-            };
-            self.handle(ctx, typ, message.as_str());
-        }
-    }
-
-    fn dis_incoming(&self, cfg: String, disctx: dis::Context, msg: channel::Message) {
-        if msg.author.id == disctx.cache.read().user.id {
-            return;
-        }
-
-        let mut typ = HandleType::None;
-
-        match msg.channel_id.to_channel(&disctx) {
-            Err(e) => {
-                warn!("failed to determine channel type for incoming message: {}", e);
-                return;
-            }
-            Ok(c) => match c {
-                channel::Channel::Private(_) => typ |= HandleType::Private,
-                channel::Channel::Group(_) => typ |= HandleType::Group,
-                channel::Channel::Guild(_) => typ |= HandleType::Public,
-                _ => return,
-            },
-        }
+    // Entry point for platforms: handle a message that arrived on `platform`.
+    pub(crate) fn incoming(&self, platform: Arc<dyn Platform>, origin: Origin, typ: HandleType, message: &str) {
+        let channel_type = if typ.contains(HandleType::Private) {
+            "private"
+        } else if typ.contains(HandleType::Group) {
+            "group"
+        } else {
+            "public"
+        };
+        self.metrics
+            .messages_received
+            .inc(&[platform.config_id(), channel_type]);
 
         let ctx = &context::Context {
             bot: self,
-            config: cfg,
-            source: Source::Discord {
-                user: msg.author,
-                channel: msg.channel_id,
-                guild: msg.guild_id,
-
-                cache: disctx.cache,
-                http: disctx.http,
-            },
-            bot_name: String::new(),
+            config: platform.config_id().to_string(),
+            source: Source::Platform { platform, origin },
+            invocation: None,
         };
+        self.handle(ctx, typ, message);
+    }
 
-        if !msg.content.is_empty() {
-            self.handle(ctx, HandleType::PlainMsg | typ, msg.content.as_str());
-        }
-        for att in msg.attachments {
-            self.handle(ctx, HandleType::Attachment | typ, &att.proxy_url);
-        }
-        if msg.content.is_empty() {
-            for embed in msg.embeds {
-                if embed.title.is_none() && embed.description.is_none() {
-                    // probably just a link, skip it
-                    continue;
-                }
-
-                let mut data = vec![];
-                if let Some(author) = embed.author {
-                    if let Some(url) = author.url {
-                        data.push(format!("{} <{}>", author.name, url));
-                    } else {
-                        data.push(author.name);
-                    }
-                }
-                if let Some(title) = embed.title {
-                    if let Some(url) = embed.url {
-                        data.push(format!("{title} <{url}>"));
-                    } else {
-                        data.push(title);
-                    }
-                }
-                if let Some(description) = embed.description {
-                    data.append(&mut description.split('\n').map(str::to_string).collect());
-                }
-                for field in embed.fields {
-                    if field.inline {
-                        data.push(format!("{}: {}", field.name, field.value.replace('\n', "\t")));
-                    } else {
-                        data.push(format!("{}:", field.name));
-                        for line in field.value.split('\n') {
-                            data.push(format!("\t{line}"));
-                        }
-                    }
-                }
-
-                if data.is_empty() {
-                    continue;
-                }
-
-                let mut spans = vec![];
-
-                if data.len() == 1 {
-                    spans.push(format!("│ {}", data.remove(0)));
-                } else {
-                    spans.push(format!("╽ {}", data.remove(0)));
-                    let lastline = data.remove(data.len() - 1);
-                    for line in data {
-                        spans.push(format!("┃ {line}"));
-                    }
-                    spans.push(format!("╿ {lastline}"));
-                }
-
-                self.handle(ctx, HandleType::Embed | typ, &spans.join("\n"));
-            }
+    // The platform for a config id, if it is of type T.
+    fn platform<T: 'static>(&self, cfg: &str) -> Result<Arc<dyn Platform>> {
+        match self.platforms.read().get(cfg) {
+            Some(p) if p.as_any().is::<T>() => Ok(p.clone()),
+            _ => bail!("invalid configid"),
         }
     }
 
     fn handle(&self, ctx: &context::Context, typ: HandleType, message: &str) {
         match self.handle_inner(ctx, typ, message) {
             Ok(()) => (),
-            Err(err) => self.handle_err(ctx, err),
+            Err(err) => {
+                let disable = err
+                    .downcast_ref::<Crash>()
+                    .filter(|c| c.disable)
+                    .map(|c| c.module.clone());
+                self.handle_err(ctx, err);
+                if let Some(module) = disable {
+                    self.disable_crashed(&module);
+                }
+            }
         }
     }
     fn handle_err(&self, ctx: &context::Context, err: Error) {
+        if err.downcast_ref::<Crash>().is_some() {
+            // Already logged by guard
+            if let Err(e) = ctx.say("command crashed") {
+                error!("{:?}", e.context("failed to inform user of command crash"));
+            }
+            return;
+        }
         match match err.downcast::<UserError>() {
             Ok(ue) => {
                 // It's a UserError, so try to inform the user
@@ -221,17 +135,7 @@ impl Rustbot {
     }
 
     pub fn handle_inner(&self, ctx: &context::Context, mut typ: HandleType, message: &str) -> Result<()> {
-        let enabled = {
-            let mut db = ctx.bot().sql().lock();
-            let mods: Vec<String> = db.query(
-                    "SELECT name FROM modules JOIN enabled_modules USING (name) WHERE config_id = $1 AND modules.enabled",
-                    &[&ctx.config],
-                )?
-                .iter()
-                .map(|row| row.get(0))
-                .collect();
-            mods
-        };
+        let enabled = self.enabled_modules(&ctx.config)?;
 
         if typ.contains(HandleType::PlainMsg) {
             let cmdchars: Cow<'static, str> = {
@@ -253,16 +157,37 @@ impl Rustbot {
 
                 let (cmd, args) = self.resolve_alias(parts[0], parts.get(1).unwrap_or(&""))?;
 
-                if let Some((p, f)) = self.core_commands.read().get(&cmd) {
-                    if ctx.perms()?.contains(*p) {
-                        f(ctx, &args).with_context(|| format!("failed to run command {cmd:?}"))?;
+                if let Some(c) = self.core_commands.read().get(&cmd) {
+                    if ctx.perms()?.contains(c.req_perms) {
+                        let started = Instant::now();
+                        let res = (c.function)(ctx, &args);
+                        self.record_command("core", &cmd, started, &res);
+                        self.audit(ctx, "core", &cmd, &args, Some(&res));
+                        res.with_context(|| format!("failed to run command {cmd:?}"))?;
+                    } else {
+                        self.audit(ctx, "core", &cmd, &args, None);
                     }
                 } else {
                     let res = self.commands.read().get(&cmd).cloned();
                     if let Some((m, f)) = res {
                         if enabled.contains(&m) {
-                            f.call(ctx, &args)
-                                .with_context(|| format!("failed to run command {cmd:?}"))?;
+                            // Only privileged module commands are audited, including privileged subcommands
+                            // of a CommandGroup that anyone may use
+                            let audited = f.perms_for(&args) != Perms::None;
+                            if !ctx.perms()?.contains(f.req_perms) {
+                                if audited {
+                                    self.audit(ctx, &m, &cmd, &args, None);
+                                }
+                            } else {
+                                self.check_cooldown(ctx, &cmd, &f)?;
+                                let started = Instant::now();
+                                let res = self.run_command(ctx, &m, &cmd, f, args.clone());
+                                self.record_command(&m, &cmd, started, &res);
+                                if audited {
+                                    self.audit(ctx, &m, &cmd, &args, Some(&res));
+                                }
+                                res.with_context(|| format!("failed to run command {cmd:?}"))?;
+                            }
                         }
                     }
                 }
@@ -277,8 +202,10 @@ impl Rustbot {
                 m.with_meta::<Result<_>>(|meta| {
                     for (ty, handler) in &meta.handlers {
                         if ty.contains(typ) {
-                            self.maybe_ignore_err(&name, handler(ctx, typ, message), ())
-                                .with_context(|| format!("failed to run handler for module {name:?}"))?;
+                            self.guard(&name, "message handler", || {
+                                self.maybe_ignore_err(&name, handler(ctx, typ, message), ())
+                            })
+                            .with_context(|| format!("failed to run handler for module {name:?}"))?;
                         }
                     }
                     Ok(())
@@ -289,6 +216,273 @@ impl Rustbot {
         Ok(())
     }
 
+    // Counts a use of `cmd` against its cooldown, failing with a UserError if it has none left. Users
+    // who can't run the command anyway, or who have Perms::Admin, aren't limited.
+    fn check_cooldown(&self, ctx: &context::Context, name: &str, cmd: &Command) -> Result<()> {
+        let cooldown = match cmd.cooldown {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let perms = ctx.perms()?;
+        if !perms.contains(cmd.req_perms) || perms.contains(Perms::Admin) {
+            return Ok(());
+        }
+
+        let key = CooldownKey {
+            config: ctx.config.clone(),
+            command: name.to_string(),
+            scope: cooldown.scope,
+            target: match cooldown.scope {
+                CooldownScope::User => ctx.source.user_string().into_owned(),
+                CooldownScope::Channel => ctx.source.channel_string().into_owned(),
+                CooldownScope::Global => String::new(),
+            },
+        };
+        if let Err(wait) = self.cooldowns.take(key, cooldown, Instant::now()) {
+            bail_user!(
+                "{} is cooling down, please try again in {}s",
+                name,
+                wait.as_secs_f64().ceil()
+            )
+        }
+        Ok(())
+    }
+
+    fn enabled_modules(&self, config: &str) -> Result<Vec<String>> {
+        let mut db = self.sql().lock();
+        let mods: Vec<String> = db
+            .query(
+                "SELECT name FROM modules JOIN enabled_modules USING (name) WHERE config_id = $1 AND modules.enabled",
+                &[&config],
+            )?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        Ok(mods)
+    }
+
+    // The commands that the user behind `ctx` can run: core commands they have permission for, and the
+    // commands of modules enabled for this config that they have permission for.
+    pub(crate) fn available_commands(&self, ctx: &context::Context) -> Result<BTreeMap<String, core::Help>> {
+        let perms = ctx.perms()?;
+        let enabled = self.enabled_modules(&ctx.config)?;
+
+        let mut available = BTreeMap::new();
+        for (name, (module, c)) in self.commands.read().iter() {
+            if enabled.contains(module) && perms.contains(c.req_perms) {
+                available.insert(
+                    name.clone(),
+                    core::Help {
+                        module: Some(module.clone()),
+                        description: c.description.clone(),
+                        usage: c.usage.clone(),
+                        example: c.example.clone(),
+                    },
+                );
+            }
+        }
+        // Core commands shadow module commands in handle_inner, so they do here too
+        for (name, c) in self.core_commands.read().iter() {
+            if perms.contains(c.req_perms) {
+                available.insert(
+                    name.clone(),
+                    core::Help {
+                        module: None,
+                        description: Some(c.description.to_string()),
+                        usage: Some(c.usage.to_string()).filter(|u| !u.is_empty()),
+                        example: None,
+                    },
+                );
+            }
+        }
+        Ok(available)
+    }
+
+    // Records a run of a command in the audit log; `res` is None if the user wasn't allowed to run it. A
+    // failure to record it is logged rather than failing the command, which has already run.
+    fn audit(&self, ctx: &context::Context, module: &str, name: &str, args: &str, res: Option<&Result<()>>) {
+        let outcome = match res {
+            None => "denied".to_string(),
+            Some(Ok(())) => "ok".to_string(),
+            Some(Err(e)) => format!("failed: {e}"),
+        };
+        let res = self.db.lock().execute(
+            "INSERT INTO audit_log (config_id, user_string, channel_string, module, command, args, outcome)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            &[
+                &ctx.config,
+                &ctx.source.user_string(),
+                &ctx.source.channel_string(),
+                &module,
+                &name,
+                &args,
+                &outcome,
+            ],
+        );
+        if let Err(e) = res {
+            error!("failed to audit command {:?}: {:?}", name, e);
+        }
+    }
+
+    // Whether `name` is a core command or the command of a loaded module.
+    pub(crate) fn is_command(&self, name: &str) -> bool {
+        self.core_commands.read().contains_key(name) || self.commands.read().contains_key(name)
+    }
+
+    // Counts a run of a command towards the metrics.
+    fn record_command(&self, module: &str, name: &str, started: Instant, res: &Result<()>) {
+        self.metrics.commands.observe(&[module, name], started.elapsed());
+        if let Err(e) = res {
+            let kind = if e.downcast_ref::<Crash>().is_some() {
+                "crash"
+            } else if e.downcast_ref::<UserError>().is_some() {
+                "user"
+            } else {
+                "backend"
+            };
+            self.metrics.command_errors.inc(&[module, name, kind]);
+        }
+    }
+
+    // Runs a module command on a thread of its own and waits for it, so that a command that hangs only
+    // holds up its caller until its timeout, when it is abandoned.
+    fn run_command(&self, ctx: &context::Context, module: &str, name: &str, cmd: Command, args: String) -> Result<()> {
+        let running = Running {
+            config: ctx.config.clone(),
+            module: module.to_string(),
+            command: name.to_string(),
+            user: ctx.source.user_string().into_owned(),
+            channel: ctx.source.channel_string().into_owned(),
+            timeout: cmd.timeout.unwrap_or(watchdog::DEFAULT_TIMEOUT),
+        };
+        let (config, source) = (ctx.config.clone(), ctx.source.clone());
+        self.run_watched(running, format!("command {name:?}"), |_| {
+            Ok(move |bot: &Rustbot, invocation| {
+                let ctx = context::Context {
+                    bot,
+                    config,
+                    source,
+                    invocation: Some(invocation),
+                };
+                cmd.call(&ctx, &args)
+            })
+        })
+    }
+
+    // Runs a scheduled job of a module the same way as a command, with the default timeout; `get` finds
+    // the job in the module's meta.
+    fn run_job_watched<G>(&self, module: &str, job: String, what: String, get: G) -> Result<()>
+    where
+        G: FnOnce(&Meta) -> Result<JobCall>,
+    {
+        let running = Running {
+            config: String::new(),
+            module: module.to_string(),
+            command: job,
+            user: "scheduler".to_string(),
+            channel: String::new(),
+            timeout: watchdog::DEFAULT_TIMEOUT,
+        };
+        let module = module.to_string();
+        self.run_watched(running, what, |m| {
+            let f = m.with_meta(get)?;
+            Ok(move |bot: &Rustbot, _| bot.maybe_ignore_err(&module, f(bot), ()))
+        })
+    }
+
+    // Runs the module code that `get` finds in `running.module` on a thread of its own that the watchdog
+    // tracks, and waits for it until `running.timeout`. `what` describes it for guard, like
+    // "command \"weather\"".
+    fn run_watched<G, F>(&self, running: Running, what: String, get: G) -> Result<()>
+    where
+        G: FnOnce(&Module) -> Result<F>,
+        F: 'static + FnOnce(&Rustbot, Arc<Invocation>) -> Result<()> + Send,
+    {
+        let bot = self.this.upgrade().context("bot is shutting down")?;
+        let timeout = running.timeout;
+        let (f, invocation, outcome) = {
+            // Holding the modules lock from finding the code until the watchdog tracks it, so that
+            // drop_module either sees this invocation or has already unloaded the module
+            let modules = self.modules.read();
+            let f = match modules.get(&running.module) {
+                Some(m) => get(m)?,
+                None => bail_user!("module {} was unloaded", running.module),
+            };
+            let (invocation, outcome) = self.watchdog.start(running);
+            (f, invocation, outcome)
+        };
+
+        let inv = invocation.clone();
+        let spawned = thread::Builder::new()
+            .name(format!("{}:{}", inv.running.module, inv.running.command))
+            .spawn(move || {
+                let module = &inv.running.module;
+                // `f`, with anything of the module's it holds, is dropped as it returns, before the module may
+                // be unloaded below
+                let res = bot.guard(module, &what, || f(&bot, inv.clone()));
+
+                // Nobody is waiting for the result of an abandoned command, so deal with it here
+                if let Some(Err(e)) = bot.watchdog.finish(&inv, res) {
+                    match e.downcast_ref::<Crash>() {
+                        Some(c) if c.disable => bot.disable_crashed(&c.module),
+                        Some(_) => (), // already logged by guard
+                        None => warn!("abandoned {} of module {:?} failed: {:?}", what, module, e),
+                    }
+                }
+            });
+        if let Err(e) = spawned {
+            self.watchdog.forget(&invocation);
+            return Err(e.into());
+        }
+
+        let name = &invocation.running.command;
+        match outcome.recv_timeout(timeout) {
+            Ok(Outcome::Finished(res)) => res,
+            Ok(Outcome::Abandoned) => bail_user!("{} was abandoned by an admin", name),
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => {
+                if invocation.abandon() {
+                    warn!(
+                        "abandoning command {} ({:?} of module {:?}) after {:?}",
+                        invocation.id, name, invocation.running.module, timeout
+                    );
+                }
+                bail_user!("{} took longer than {}s, and was abandoned", name, timeout.as_secs())
+            }
+        }
+    }
+
+    // Runs module code, turning a panic into a Crash error so that it doesn't unwind through the bot.
+    // Crashes are logged here, and are never suppressed by maybe_ignore_err.
+    fn guard<T>(&self, module: &str, what: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        match crash::catch(f) {
+            Ok(res) => res,
+            Err(message) => {
+                let crash = Crash {
+                    module: module.to_string(),
+                    what: what.to_string(),
+                    message,
+                    disable: self.crashes.record(module, Instant::now()),
+                };
+                error!("{}", crash);
+                Err(crash.into())
+            }
+        }
+    }
+
+    // Unloads a module that guard found to have crashed too often. Must be called without holding any
+    // module's meta, and after dropping any of its commands.
+    fn disable_crashed(&self, module: &str) {
+        error!(
+            "module {:?} panicked {} times within {:?}; unloading it",
+            module,
+            crash::CRASH_LIMIT,
+            crash::CRASH_WINDOW
+        );
+        if let Err(e) = self.drop_module(module) {
+            error!("failed to unload crashed module {:?}: {:?}", module, e);
+        }
+    }
+
     fn maybe_ignore_err<T>(&self, name: &str, res: Result<T>, on_ignore: T) -> Result<T> {
         match self.suppress_errors.read().get(name) {
             None => res,
@@ -308,7 +502,7 @@ impl Rustbot {
         }
     }
 
-    fn resolve_alias(&self, cmd: &str, args: &str) -> Result<(String, String)> {
+    pub(crate) fn resolve_alias(&self, cmd: &str, args: &str) -> Result<(String, String)> {
         let (newcmd, transforms): (String, ArgumentTransforms) = {
             let mut db = self.sql().lock();
             let rows = db.query(
@@ -365,44 +559,24 @@ impl Rustbot {
         Ok((newcmd, args))
     }
 
-    fn dis_get_replacements(
-        guild: impl std::ops::Deref<Target = guild::Guild>,
-        reverse: bool,
-    ) -> Vec<(String, String)> {
-        let mut replacements = vec![];
-        for (id, m) in &guild.members {
-            replacements.push((format!("@{}", m.user.read().name), format!("<@{id}>")));
-            if reverse {
-                replacements.push((format!("@{}", m.user.read().name), format!("<@!{id}>")));
-            }
-        }
-
-        for (id, r) in &guild.roles {
-            replacements.push((format!("@{}", r.name), format!("<@&{id}>")));
-        }
-
-        for (id, c) in &guild.channels {
-            replacements.push((format!("#{}", c.read().name), format!("<#{id}>")));
-        }
-
-        for (id, e) in &guild.emojis {
-            replacements.push((format!(":{}:", e.name), format!("<:{}:{}>", e.name, id)));
-        }
-
-        replacements
-    }
-
-    fn str_max_bytes(s: &str, n: usize) -> &str {
-        if s.len() <= n {
-            return s;
-        }
-
-        let (last_char_inside, _) = s.char_indices().take_while(|(i, _)| *i <= n).last().unwrap();
-        &s[..last_char_inside]
-    }
-
+    // Refuses to unload a module while any of its commands are still running, even abandoned ones, since
+    // their threads are still running its code.
     pub fn drop_module(&self, name: &str) -> Result<()> {
-        if let Some(mut m) = self.modules.write().remove(name) {
+        let mut modules = self.modules.write();
+        let running = self
+            .watchdog
+            .running()
+            .iter()
+            .filter(|i| i.running.module == name)
+            .count();
+        if running != 0 {
+            bail_user!(
+                "module {} has {} commands still running, and can't be unloaded until they return; see ps",
+                name,
+                running
+            );
+        }
+        if let Some(mut m) = modules.remove(name) {
             info!("drop module: {}", name);
             let mut db = self.db.lock();
             db
@@ -410,6 +584,8 @@ impl Rustbot {
                     "INSERT INTO modules (name, enabled) VALUES ($1, false) ON CONFLICT (name) DO UPDATE SET enabled = false",
                     &[&name],
                 )?;
+            self.scheduler.remove_module(name);
+            self.crashes.forget(name);
             m.with_meta_mut::<Result<_>>(|meta| {
                 let mut commands = self.commands.write();
                 for command in &meta.commands {
@@ -433,6 +609,16 @@ impl Rustbot {
     }
 
     pub fn load_module(&self, name: &str) -> Result<()> {
+        self.load_module_after(name, &mut vec![])
+    }
+
+    // Loads a module, first loading any modules it depends on that aren't loaded yet; `waiting` is the
+    // chain of modules that depend on this one and are being loaded, to catch dependency cycles.
+    fn load_module_after(&self, name: &str, waiting: &mut Vec<String>) -> Result<()> {
+        if waiting.iter().any(|m| m == name) {
+            bail!("module dependency cycle: {} -> {}", waiting.join(" -> "), name);
+        }
+
         info!("load module: {}", name);
         let libpath = if cfg!(debug_assertions) {
             format!("libmod_{name}.so")
@@ -440,12 +626,45 @@ impl Rustbot {
             format!("target/release/libmod_{name}.so")
         };
         let lib = Library::new(libpath)?;
+        let depends = check_manifest(name, &lib)?;
+
+        waiting.push(name.to_string());
+        for dep in &depends {
+            if !self.modules.read().contains_key(dep) {
+                self.load_module_after(dep, waiting)
+                    .with_context(|| format!("failed to load {dep}, which {name} depends on"))?;
+            }
+        }
+        waiting.pop();
+
+        // The module is only marked enabled once its migrations are applied, and is marked disabled if they
+        // fail, so that it isn't loaded again (and failed again) on every start
+        let mut m = load_module(name, lib)?;
+        let migrated = m
+            .with_meta(|meta| db::migrate_module(&mut self.db.lock(), name, &meta.migrations))
+            .and_then(|_| {
+                self.db.lock().execute(
+                    "INSERT INTO modules (name, enabled) VALUES ($1, true) ON CONFLICT (name) DO UPDATE SET enabled = true",
+                    &[&name],
+                )?;
+                Ok(())
+            });
+        if let Err(e) = migrated {
+            self.db
+                .lock()
+                .execute("UPDATE modules SET enabled = false WHERE name = $1", &[&name])
+                .unwrap_or_else(|e| {
+                    warn!("module {}: failed to mark it disabled: {}", name, e);
+                    0
+                });
+            // Nothing of the module has run yet but get_meta, which may have set up what deinit undoes
+            if let Some(Err(e)) = m.with_meta_mut(|meta| meta.deinit.as_mut().map(|f| f(self))) {
+                warn!("module {}: deinit failed: {}", name, e);
+            }
+            return Err(e);
+        }
+        m.with_meta_mut(|meta| meta.start_threads());
 
-        self.db.lock().execute(
-            "INSERT INTO modules (name, enabled) VALUES ($1, true) ON CONFLICT (name) DO UPDATE SET enabled = true",
-            &[&name],
-        )?;
-        let m = load_module(name, lib)?;
         let mut commands = self.commands.write();
         m.with_meta::<Result<_>>(|meta| {
             for command in &meta.commands {
@@ -453,10 +672,234 @@ impl Rustbot {
             }
             Ok(())
         })?;
+        let jobs: Vec<_> = m.with_meta(|meta| meta.jobs.iter().map(|(schedule, _)| schedule.clone()).collect());
         self.modules.write().insert(name.to_string(), m);
+
+        let now = SystemTime::now();
+        for (index, schedule) in jobs.into_iter().enumerate() {
+            let job = JobRef::Module {
+                module: name.to_string(),
+                index,
+            };
+            self.scheduler.add(job, schedule, now);
+        }
+        let stored = self.db.lock().query(
+            "SELECT id, name, due, payload FROM scheduled_jobs WHERE module = $1",
+            &[&name],
+        )?;
+        for row in stored {
+            let job = JobRef::Stored {
+                id: row.get(0),
+                module: name.to_string(),
+                name: row.get(1),
+                payload: row.get(3),
+            };
+            self.scheduler.add(job, Schedule::At(row.get(2)), now);
+        }
+        Ok(())
+    }
+
+    // Undoes the migrations of a module that isn't loaded, removing its tables; returns how many there were.
+    pub fn drop_module_schema(&self, name: &str) -> Result<usize> {
+        if self.modules.read().contains_key(name) {
+            bail_user!("module {} is loaded; drop it first", name);
+        }
+        db::unmigrate_module(&mut self.db.lock(), name)
+    }
+
+    // Adds a platform, and connects it in a new thread; it reconnects whenever the connection is lost,
+    // until it is replaced or removed by a rehash.
+    fn start_platform(&self, p: Arc<dyn Platform>) -> Result<()> {
+        let b = match self.this.upgrade() {
+            Some(b) => b,
+            None => bail!("the bot is shutting down"),
+        };
+        self.platforms.write().insert(p.config_id().to_string(), p.clone());
+
+        thread::Builder::new()
+            .name(format!("{}: {}", p.kind(), p.describe()))
+            .spawn(move || {
+                let connected = AtomicBool::new(false);
+                run_with_backoff(&format!("{} connection for {}", p.kind(), p.describe()), &|| {
+                    let current = b
+                        .platforms
+                        .read()
+                        .get(p.config_id())
+                        .is_some_and(|c| Arc::ptr_eq(c, &p));
+                    if !current {
+                        return Ok(());
+                    }
+                    if connected.swap(true, Ordering::SeqCst) {
+                        b.metrics.reconnects.inc(&[p.config_id()]);
+                    }
+                    p.clone().connect(b.clone())
+                });
+            })?;
+        Ok(())
+    }
+
+    // Removes a platform and closes its connection; if it can't be closed, it is left in place.
+    fn stop_platform(&self, id: &str) -> Result<()> {
+        let p = match self.platforms.write().remove(id) {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        if let Err(e) = p.disconnect() {
+            self.platforms.write().insert(id.to_string(), p);
+            return Err(e);
+        }
         Ok(())
     }
 
+    // Re-reads Rustbot.toml and applies what changed: platforms whose entries were added, removed or
+    // changed are connected, disconnected or reconnected, and loaded modules whose [module.x] section
+    // changed are reloaded. Returns a description of each change, in the order they were made.
+    pub fn rehash(&self) -> Result<Vec<String>> {
+        let new = config::load()?;
+        let mut config = self.config.write(); // held throughout, so that rehashes don't overlap
+        let mut changes = vec![];
+
+        if new.postgres != config.postgres {
+            changes.push("[postgres] changed; restart to apply it".to_string());
+        }
+        if new.paste != config.paste {
+            changes.push("[paste] changed; restart to apply it".to_string());
+        }
+        if new.metrics != config.metrics {
+            changes.push("[metrics] changed; restart to apply it".to_string());
+        }
+
+        let (old_platforms, new_platforms) = (config.platforms(), new.platforms());
+        for (id, old) in &old_platforms {
+            let replacement = match new_platforms.get(id) {
+                Some(c) if c == old => continue,
+                c => c,
+            };
+            if let Err(e) = self.stop_platform(id) {
+                changes.push(format!("[[{}]] {} changed, but {}", old.section(), id, e));
+                continue;
+            }
+            match replacement {
+                Some(c) => {
+                    self.start_platform(platform::new(c.clone(), &self.paster))?;
+                    changes.push(format!("reconnected [[{}]] {}", c.section(), id));
+                }
+                None => changes.push(format!("removed [[{}]] {}", old.section(), id)),
+            }
+        }
+        for (id, c) in &new_platforms {
+            if !old_platforms.contains_key(id) {
+                self.start_platform(platform::new(c.clone(), &self.paster))?;
+                changes.push(format!("added [[{}]] {}", c.section(), id));
+            }
+        }
+
+        let modules: BTreeSet<_> = config.module.keys().chain(new.module.keys()).cloned().collect();
+        for m in modules {
+            if config.module.get(&m) == new.module.get(&m) || !self.modules.read().contains_key(&m) {
+                continue;
+            }
+            match self.drop_module(&m).and_then(|()| self.load_module(&m)) {
+                Ok(()) => changes.push(format!("reloaded module {m}")),
+                Err(e) => changes.push(format!("[module.{m}] changed, but reloading {m} failed: {e}")),
+            }
+        }
+
+        *config = new;
+        Ok(changes)
+    }
+
+    // Runs a job that the scheduler found due. It waits for the job, so the scheduler calls it off its own
+    // thread; and the modules lock is only held to look the job up, not while it runs.
+    fn run_job(&self, job: JobRef) {
+        let res = match &job {
+            JobRef::Module { module, index } => self.run_job_watched(
+                module,
+                format!("job {index}"),
+                "scheduled job".to_string(),
+                |meta| match meta.jobs.get(*index) {
+                    Some((_, f)) => {
+                        let f = f.clone();
+                        Ok(Box::new(move |bot: &Rustbot| f(bot)))
+                    }
+                    None => Err(anyhow!("module {:?} has no job {}", module, index)),
+                },
+            ),
+            JobRef::Stored {
+                id,
+                module,
+                name,
+                payload,
+            } => {
+                // Counted before it runs, so that a job that keeps failing, or takes the bot down with it,
+                // isn't retried forever
+                let attempts = self.db.lock().query_opt(
+                    "UPDATE scheduled_jobs SET attempts = attempts + 1 WHERE id = $1 RETURNING attempts",
+                    &[id],
+                );
+                match attempts {
+                    Err(e) => Err(e.into()),
+                    Ok(None) => Ok(()), // cancelled meanwhile
+                    Ok(Some(row)) => {
+                        let what = format!("job handler {name:?}");
+                        let res = self.run_job_watched(module, format!("job {name:?}"), what, |meta| {
+                            match meta.job_handlers.get(name) {
+                                Some(f) => {
+                                    let (f, payload) = (f.clone(), payload.clone());
+                                    Ok(Box::new(move |bot: &Rustbot| f(bot, &payload)))
+                                }
+                                None => Err(anyhow!("module {:?} has no handler for job {:?}", module, name)),
+                            }
+                        });
+                        self.finish_stored_job(&job, *id, module, row.get(0), res)
+                    }
+                }
+            }
+        };
+
+        if let Err(e) = res {
+            match e.downcast_ref::<Crash>() {
+                Some(c) if c.disable => self.disable_crashed(&c.module),
+                Some(_) => (), // already logged by guard
+                None => error!("scheduled job {:?} failed: {:?}", job, e),
+            }
+        }
+    }
+
+    // Deletes a stored job that has run, unless it failed and has attempts left, in which case it is put
+    // back to run again later. A job that timed out may still be running, so handlers should expect to
+    // run more than once.
+    fn finish_stored_job(&self, job: &JobRef, id: i64, module: &str, attempts: i32, res: Result<()>) -> Result<()> {
+        let e = match res {
+            Ok(()) => {
+                self.db
+                    .lock()
+                    .execute("DELETE FROM scheduled_jobs WHERE id = $1", &[&id])?;
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+
+        let delay = match scheduler::retry_delay(attempts) {
+            Some(delay) => delay,
+            None => {
+                self.db
+                    .lock()
+                    .execute("DELETE FROM scheduled_jobs WHERE id = $1", &[&id])?;
+                return Err(e.context(format!("giving up on it after {attempts} attempts")));
+            }
+        };
+        let due = SystemTime::now() + delay;
+        self.db
+            .lock()
+            .execute("UPDATE scheduled_jobs SET due = $1 WHERE id = $2", &[&due, &id])?;
+        // If the module is gone, it picks the job up again when it is next loaded
+        if self.modules.read().contains_key(module) {
+            self.scheduler.add(job.clone(), Schedule::At(due), SystemTime::now());
+        }
+        Err(e.context(format!("retrying it in {}s", delay.as_secs())))
+    }
+
     pub fn set_log_level(&self, level: Level) -> Result<()> {
         self.logger.lock().current_level = level;
         self.update_logger_spec()
@@ -567,193 +1010,89 @@ impl FromSql<'_> for ArgumentTransforms {
 }
 
 impl types::Bot for Rustbot {
-    fn sql(&self) -> &Mutex<postgres::Client> {
+    fn sql(&self) -> &Sql {
         &self.db
     }
 
     fn irc_send_privmsg(&self, cfg: &str, channel: &str, message: &str) -> Result<()> {
-        if let Some(client) = self.clients.read().get(cfg) {
-            let message = Self::str_max_bytes(message, 490);
-            client.send_privmsg(channel, message).map_err(from_irc)?;
-            Ok(())
-        } else {
-            bail!("invalid configid")
-        }
+        self.metrics.messages_sent.inc(&[cfg]);
+        let p = self.platform::<IrcPlatform>(cfg)?;
+        p.as_any()
+            .downcast_ref::<IrcPlatform>()
+            .unwrap()
+            .send_privmsg(channel, message, Priority::Reply)
     }
 
     fn irc_send_raw(&self, cfg: &str, line: &str) -> Result<()> {
-        if let Some(client) = self.clients.read().get(cfg) {
-            let line = Self::str_max_bytes(line, 510);
-            client.send(line).map_err(from_irc)?;
-            Ok(())
-        } else {
-            bail!("invalid configid")
-        }
+        let p = self.platform::<IrcPlatform>(cfg)?;
+        p.as_any().downcast_ref::<IrcPlatform>().unwrap().send_raw(line)
     }
 
     fn dis_unprocess_message(&self, config: &str, guild: &str, message: &str) -> Result<String> {
-        let cache_and_http = match self.caches.read().get(config) {
-            None => bail!("no cache found for config {:?}", config),
-            Some(c) => Arc::clone(c),
-        };
-
-        let cache = cache_and_http.cache.read();
-
-        let mut message = message.to_string();
-
-        let guildobj = {
-            if let Ok(id) = guild.parse() {
-                cache.guilds.get(&GuildId(id))
-            } else {
-                let mut v = None;
-                for g in cache.guilds.values() {
-                    if g.read().name == guild {
-                        v = Some(g);
-                        break;
-                    }
-                }
-                v
-            }
-        }
-        .ok_or_else(|| Error::msg("guild not found"))?
-        .read();
-
-        let mut replacements = Self::dis_get_replacements(guildobj, true);
-
-        replacements.sort_by(|l, r| {
-            if l.1.len() != r.1.len() {
-                return l.1.len().cmp(&r.1.len()).reverse();
-            }
-
-            l.1.cmp(&r.1)
-        });
-
-        for (replace, find) in replacements {
-            message = message.replace(&find, &replace);
-        }
-
-        Ok(message)
+        let p = self.platform::<DiscordPlatform>(config)?;
+        p.as_any()
+            .downcast_ref::<DiscordPlatform>()
+            .unwrap()
+            .unprocess_message(guild, message)
     }
 
     fn dis_send_message(&self, config: &str, guild: &str, channel: &str, message: &str, process: bool) -> Result<()> {
-        let cache_and_http = match self.caches.read().get(config) {
-            None => bail!("no cache found for config {:?}", config),
-            Some(c) => Arc::clone(c),
-        };
+        self.metrics.messages_sent.inc(&[config]);
+        let p = self.platform::<DiscordPlatform>(config)?;
+        p.as_any()
+            .downcast_ref::<DiscordPlatform>()
+            .unwrap()
+            .send_message(guild, channel, message, process)
+    }
 
-        let cache = cache_and_http.cache.read();
+    fn send_message(&self, config: &str, target: &str, msg: Message) -> Result<()> {
+        self.metrics.messages_sent.inc(&[config]);
+        let (kind, channel) = platform::parse_channel_string(target)?;
+        let p = match self.platforms.read().get(config) {
+            Some(p) if p.kind() == kind => p.clone(),
+            Some(p) => bail!("config {:?} is {:?}, not {:?}", config, p.kind(), kind),
+            None => bail!("invalid configid"),
+        };
+        p.send(channel, msg)
+    }
 
-        let guildobj = {
-            if let Ok(id) = guild.parse() {
-                cache.guilds.get(&GuildId(id))
-            } else {
-                let mut v = None;
-                for g in cache.guilds.values() {
-                    if g.read().name == guild {
-                        v = Some(g);
-                        break;
-                    }
-                }
-                v
-            }
+    fn quiet_name<'a>(&self, config: &str, target: &str, name: &'a str) -> Cow<'a, str> {
+        let p = self.platforms.read().get(config).cloned();
+        match (p, platform::parse_channel_string(target)) {
+            (Some(p), Ok((kind, _))) if p.kind() == kind => p.quiet_name(name),
+            _ => name.into(),
         }
-        .ok_or_else(|| Error::msg("guild not found"))?
-        .read();
-
-        let chanid = {
-            if let Ok(id) = channel.parse() {
-                if guildobj.channels.get(&ChannelId(id)).is_some() {
-                    Some(ChannelId(id))
-                } else {
-                    None
-                }
-            } else {
-                let mut v = None;
-                for (id, c) in &guildobj.channels {
-                    if c.read().name == channel {
-                        v = Some(*id);
-                        break;
-                    }
-                }
-                v
-            }
-        }
-        .ok_or_else(|| Error::msg("channel not found"))?;
-
-        if process {
-            let mut message = message.to_string();
-
-            let mut replacements = Self::dis_get_replacements(guildobj, false);
-
-            replacements.sort_by(|l, r| {
-                if l.0.len() != r.0.len() {
-                    return l.0.len().cmp(&r.0.len()).reverse();
-                }
-
-                l.0.cmp(&r.0)
-            });
-
-            {
-                for (find, replace) in replacements {
-                    let mut need_replace = false;
-
-                    let is_replace_before_ok = |c| {
-                        let cat = unic_ucd::GeneralCategory::of(c);
-
-                        cat.is_separator() || cat.is_punctuation()
-                    };
-
-                    // Check whether we actually need to do anything.
-                    // Most of the time, we don't, so we can avoid allocating.
-                    if message.ends_with(&find) {
-                        need_replace = true;
-                    } else {
-                        for part in message.split(&find).skip(1) {
-                            if part.starts_with(is_replace_before_ok) {
-                                need_replace = true;
-                            }
-                        }
-                    }
-
-                    if need_replace {
-                        let mut parts = message.split(&find);
-                        let mut new_parts = vec![parts.next().unwrap()];
-
-                        for part in parts {
-                            if part.is_empty() || part.starts_with(is_replace_before_ok) {
-                                new_parts.push(&replace);
-                            } else {
-                                new_parts.push(&find);
-                            }
-                            new_parts.push(part);
-                        }
-
-                        message = new_parts.join("");
-                    }
-                }
-            }
+    }
 
-            chanid.say(Arc::clone(&cache_and_http.http), message)?;
-        } else {
-            chanid.say(Arc::clone(&cache_and_http.http), message)?;
+    fn schedule_job(&self, module: &str, name: &str, at: SystemTime, payload: &str) -> Result<i64> {
+        let id: i64 = self
+            .db
+            .lock()
+            .query_one(
+                "INSERT INTO scheduled_jobs (module, name, due, payload) VALUES ($1, $2, $3, $4) RETURNING id",
+                &[&module, &name, &at, &payload],
+            )?
+            .get(0);
+
+        if self.modules.read().contains_key(module) {
+            let job = JobRef::Stored {
+                id,
+                module: module.to_string(),
+                name: name.to_string(),
+                payload: payload.to_string(),
+            };
+            self.scheduler.add(job, Schedule::At(at), SystemTime::now());
         }
-
-        Ok(())
+        Ok(id)
     }
 
-    fn send_message(&self, config: &str, source: &str, msg: Message) -> Result<()> {
-        let parts: Vec<_> = source.split(':').collect();
-        if parts[0] == "irc" && parts.len() == 2 {
-            let msg = message::format_irc(msg)?;
-            for line in msg {
-                self.irc_send_privmsg(config, parts[1], &line)?;
-            }
-            Ok(())
-        } else if parts[0] == "dis" && parts.len() == 3 {
-            self.dis_send_message(config, parts[1], parts[2], &message::format_discord(msg)?, true)
-        } else {
-            bail!("invalid source")
-        }
+    fn cancel_job(&self, id: i64) -> Result<bool> {
+        self.scheduler.remove_stored(id);
+        let n = self
+            .db
+            .lock()
+            .execute("DELETE FROM scheduled_jobs WHERE id = $1", &[&id])?;
+        Ok(n > 0)
     }
 }
 
@@ -848,11 +1187,25 @@ pub fn start() -> Result<()> {
 
     // Load the config
     let config = config::load()?;
+    let metrics = Arc::new(Metrics::new());
+    if let Some(c) = &config.metrics {
+        metrics::serve(metrics.clone(), c)?;
+    }
+    let db = {
+        let metrics = metrics.clone();
+        Sql::new(db::open(&config.postgres)?).on_release(move |held| metrics.sql.observe(&[], held))
+    };
+    let paster: Arc<dyn paste::Paster> = Arc::new(CountingPaster {
+        paster: paste::from_config(&config.paste)?,
+        metrics: metrics.clone(),
+    });
 
-    let b = Arc::new(Rustbot {
-        clients: RwLock::new(BTreeMap::new()),
-        caches: RwLock::new(BTreeMap::new()),
-        db: Mutex::new(db::open(&config.postgres)?),
+    let b = Arc::new_cyclic(|this| Rustbot {
+        this: this.clone(),
+        config: RwLock::new(config.clone()),
+        paster,
+        platforms: RwLock::new(BTreeMap::new()),
+        db,
         modules: RwLock::new(BTreeMap::new()),
         core_commands: RwLock::new(core::get_commands()),
         commands: RwLock::new(BTreeMap::new()),
@@ -861,6 +1214,11 @@ pub fn start() -> Result<()> {
             current_level: Level::Info,
         }),
         suppress_errors: RwLock::new(BTreeMap::new()),
+        cooldowns: Cooldowns::new(),
+        crashes: CrashTracker::new(crash::CRASH_LIMIT, crash::CRASH_WINDOW),
+        watchdog: Watchdog::new(),
+        metrics,
+        scheduler: Scheduler::new(),
     });
 
     b.update_logger_spec()?;
@@ -873,80 +1231,76 @@ pub fn start() -> Result<()> {
                 .map(|row| row.get(0))
                 .collect()
         };
+        // A module that fails to load (a stale .so, a dependency that fails, a migration) is marked
+        // disabled rather than keeping the rest of the bot from starting
         for m in modules {
-            b.load_module(m.as_str()).unwrap();
+            if b.modules.read().contains_key(&m) {
+                continue; // Already loaded as a dependency of an earlier one
+            }
+            if let Err(e) = b.load_module(&m) {
+                error!("{:?}", e.context(format!("failed to load module {m}; disabling it")));
+                let disabled =
+                    b.db.lock()
+                        .execute("UPDATE modules SET enabled = false WHERE name = $1", &[&m]);
+                if let Err(e) = disabled {
+                    warn!("module {}: failed to mark it disabled: {}", m, e);
+                }
+            }
         }
     }
 
-    for c in config.irc {
-        let channels: Vec<String> = {
-            let mut db = b.db.lock();
-            let cid = c.id.clone();
-            db.query("SELECT channel FROM irc_channels WHERE config_id = $1", &[&cid])?
-                .iter()
-                .map(|row| row.get(0))
-                .collect()
-        };
-
-        let b = b.clone();
-        thread::Builder::new()
-            .name(format!("IRC: {}", irc_descriptor(&c)))
-            .spawn(move || {
-                run_with_backoff(&format!("IRC connection for {}", c.id), &|| {
-                    let client = Arc::new(
-                        irc::IrcClient::from_config(irc::Config {
-                            nickname: Some(c.nick.clone()),
-                            username: Some(c.user.clone()),
-                            realname: Some(c.real.clone()),
-                            server: Some(c.server.clone()),
-                            port: Some(c.port),
-                            use_ssl: Some(c.ssl),
-                            channels: Some(channels.clone()),
-                            password: c.pass.clone(),
-                            ..Default::default()
-                        })
-                        .map_err(from_irc)?,
-                    );
-                    client.send_cap_req(&[irc::Capability::MultiPrefix]).map_err(from_irc)?;
-                    client.identify().map_err(from_irc)?;
-                    b.clients.write().insert(c.id.clone(), client.clone());
-                    info!("connect: {}", irc_descriptor(&c));
-                    client
-                        .for_each_incoming(|irc_msg| {
-                            let b = b.clone();
-                            let id = c.id.clone();
-                            rayon::spawn(move || {
-                                let client = { b.clients.read().get(&id).unwrap().clone() };
-                                b.irc_incoming(id.clone(), client.current_nickname(), irc_msg);
-                            });
-                        })
-                        .map_err(from_irc)?;
-                    Ok(())
-                });
-            })?;
+    for c in config.platforms().into_values() {
+        b.start_platform(platform::new(c, &b.paster))?;
     }
 
-    for c in config.discord {
+    // Started after the platforms are added, so that jobs that came due while the bot was down have
+    // somewhere to send to; any that run before their platform is connected are retried
+    {
         let b = b.clone();
-        thread::Builder::new()
-            .name(format!("Discord: {}", c.id.clone()))
-            .spawn(move || {
-                run_with_backoff("Discord connection", &|| {
-                    let mut dis = dis::Client::new(
-                        &c.token,
-                        DiscordBot {
-                            id: c.id.clone(),
-                            bot: b.clone(),
-                        },
-                    )?;
-
-                    b.caches.write().insert(c.id.clone(), dis.cache_and_http.clone());
-                    info!("connect: {}", c.id);
-                    dis.start()?;
-                    Ok(())
-                });
-            })?;
+        thread::Builder::new().name("scheduler".to_string()).spawn(move || {
+            b.scheduler.run(|job| {
+                let b = b.clone();
+                rayon::spawn(move || b.run_job(job));
+            })
+        })?;
     }
+
+    rehash_on_sighup(b)
+}
+
+// Rehashes whenever the process gets SIGHUP, in a new thread.
+fn rehash_on_sighup(b: Arc<Rustbot>) -> Result<()> {
+    thread::Builder::new()
+        .name("SIGHUP handler".to_string())
+        .spawn(move || {
+            let rt = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(rt) => rt,
+                Err(e) => {
+                    error!("SIGHUP handler: failed to start runtime: {}", e);
+                    return;
+                }
+            };
+            rt.block_on(async {
+                let mut hangups = match signal(SignalKind::hangup()) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        error!("SIGHUP handler: {}", e);
+                        return;
+                    }
+                };
+                while hangups.recv().await.is_some() {
+                    match b.rehash() {
+                        Ok(changes) if changes.is_empty() => info!("rehash: Rustbot.toml is unchanged"),
+                        Ok(changes) => {
+                            for change in changes {
+                                info!("rehash: {}", change);
+                            }
+                        }
+                        Err(e) => error!("rehash failed: {}", e),
+                    }
+                }
+            });
+        })?;
     Ok(())
 }
 
@@ -973,25 +1327,6 @@ fn run_with_backoff(desc: &str, f: &dyn Fn() -> Result<()>) {
     }
 }
 
-fn irc_descriptor(c: &config::Irc) -> String {
-    format!("{} ({}:{})", c.id, c.server, c.port,)
-}
-
-struct DiscordBot {
-    id: String,
-    bot: Arc<Rustbot>,
-}
-
-impl dis::EventHandler for DiscordBot {
-    fn message(&self, disctx: dis::Context, msg: channel::Message) {
-        let id = self.id.clone();
-        let bot = self.bot.clone();
-        rayon::spawn(move || {
-            bot.dis_incoming(id, disctx, msg);
-        });
-    }
-}
-
 use ouroboros::self_referencing;
 
 #[self_referencing]
@@ -1001,6 +1336,34 @@ pub struct Module {
     meta: Meta,
 }
 
+// Checks that a module was built against this librustbot and is the module it's meant to be, before
+// anything else in it is touched; returns the modules it depends on.
+fn check_manifest(name: &str, lib: &Library) -> Result<Vec<String>> {
+    let abi = match unsafe { lib.get::<*const [u8; manifest::ABI_LEN]>(b"RUSTBOT_ABI") } {
+        Ok(abi) => manifest::abi_str(unsafe { &**abi }),
+        Err(_) => bail!(
+            "module {} exports no RUSTBOT_ABI; add rustbot::manifest!() to it and rebuild it",
+            name
+        ),
+    };
+    let ours = manifest::abi_str(&manifest::ABI);
+    if abi != ours {
+        bail!(
+            "module {} was built for {}, but this is {}; rebuild it",
+            name,
+            abi,
+            ours
+        );
+    }
+
+    let m: &Manifest = unsafe { &**lib.get::<*const Manifest>(b"RUSTBOT_MANIFEST")? };
+    if m.name != format!("mod_{name}") {
+        bail!("module {} calls itself {} in its manifest", name, m.name);
+    }
+    info!("module {} is {} {}", name, m.name, m.version);
+    Ok(m.depends.iter().map(|d| d.to_string()).collect())
+}
+
 fn load_module(name: &str, lib: Library) -> Result<Module> {
     let m = Module::try_new(Box::new(lib), |lib| {
         let get_meta = unsafe { lib.get::<unsafe fn(&mut dyn types::Meta)>(b"get_meta") };
@@ -1036,12 +1399,19 @@ fn load_module(name: &str, lib: Library) -> Result<Module> {
     Ok(m)
 }
 
+// A scheduled job or job handler found in a module's meta, ready to be run on another thread.
+type JobCall = Box<dyn FnOnce(&Rustbot) -> Result<()> + Send>;
+
 pub struct Meta {
     commands: BTreeMap<String, Command>,
     deinit: Option<Box<DeinitFn>>,
     handlers: Vec<(HandleType, Box<MsgHandlerFn>)>,
     unload_channels: Vec<Sender<()>>,
+    pending_threads: Mutex<Vec<Box<ThreadFn>>>, // not started until the module's migrations are applied
     threads: Vec<std::thread::JoinHandle<()>>,
+    jobs: Vec<(Schedule, Arc<JobFn>)>, // Arcs so that run_job can let go of the module's lock while they run
+    job_handlers: BTreeMap<String, Arc<JobHandlerFn>>,
+    migrations: BTreeMap<u32, db::Migration>,
 }
 
 impl Meta {
@@ -1051,7 +1421,17 @@ impl Meta {
             deinit: None,
             handlers: Vec::new(),
             unload_channels: Vec::new(),
+            pending_threads: Mutex::new(Vec::new()),
             threads: Vec::new(),
+            jobs: Vec::new(),
+            job_handlers: BTreeMap::new(),
+            migrations: BTreeMap::new(),
+        }
+    }
+
+    fn start_threads(&mut self) {
+        for f in self.pending_threads.get_mut().drain(..) {
+            self.threads.push(std::thread::spawn(f));
         }
     }
 }
@@ -1073,10 +1453,19 @@ impl types::Meta for Meta {
         recv
     }
     fn thread(&mut self, f: Box<ThreadFn>) {
-        self.threads.push(std::thread::spawn(f));
+        self.pending_threads.get_mut().push(f);
+    }
+    fn schedule(&mut self, schedule: Schedule, f: Box<JobFn>) {
+        self.jobs.push((schedule, Arc::from(f)));
+    }
+    fn job_handler(&mut self, name: &str, f: Box<JobHandlerFn>) {
+        self.job_handlers.insert(name.to_string(), Arc::from(f));
+    }
+    fn migration(&mut self, version: u32, up: &str, down: &str) {
+        let migration = db::Migration {
+            up: up.to_string(),
+            down: down.to_string(),
+        };
+        self.migrations.insert(version, migration);
     }
-}
-
-fn from_irc(e: ::irc::error::IrcError) -> Error {
-    Error::msg(format!("{e}"))
 }
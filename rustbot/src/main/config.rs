@@ -2,9 +2,10 @@ use serde::Deserialize;
 use std::collections::BTreeMap;
 use std::fs;
 
+use crate::message::Limit;
 use rustbot::prelude::*;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct Config {
     pub postgres: Postgres,
 
@@ -12,12 +13,22 @@ pub struct Config {
     pub irc: Vec<Irc>,
     #[serde(default)]
     pub discord: Vec<Discord>,
+    #[serde(default)]
+    pub matrix: Vec<Matrix>,
+    #[serde(default)]
+    pub console: Vec<Console>,
+
+    #[serde(default)]
+    pub paste: Paste,
+
+    // If set, metrics are served over HTTP
+    pub metrics: Option<Metrics>,
 
     #[serde(default)]
     pub module: BTreeMap<String, toml::Value>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct Postgres {
     pub database: String,
     pub user: String,
@@ -27,7 +38,7 @@ pub struct Postgres {
     pub port: u16,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct Irc {
     pub id: String,
 
@@ -41,15 +52,270 @@ pub struct Irc {
     pub pass: Option<String>,
 
     pub ssl: bool,
+
+    // Outgoing flood control: up to `flood_burst` lines at once, then one line every `flood_delay_ms`.
+    // A delay of 0 disables it.
+    #[serde(default = "default_flood_burst")]
+    pub flood_burst: u32,
+    #[serde(default = "default_flood_delay_ms")]
+    pub flood_delay_ms: u64,
+
+    // Long messages are cut to `max_lines` lines, the last of them pointing to the rest as `overflow`
+    // says; lists are wrapped at `list_width` bytes. `channels` overrides these for single channels.
+    #[serde(default = "default_max_lines")]
+    pub max_lines: usize,
+    #[serde(default = "default_list_width")]
+    pub list_width: usize,
+    #[serde(default)]
+    pub overflow: Overflow,
+    #[serde(default)]
+    pub channels: BTreeMap<String, IrcChannel>,
+}
+
+#[derive(Deserialize, Clone, Default, PartialEq)]
+pub struct IrcChannel {
+    pub max_lines: Option<usize>,
+    pub list_width: Option<usize>,
+    pub overflow: Option<Overflow>,
+}
+
+impl Irc {
+    // The line limit and list width for a channel or nick.
+    pub fn limits(&self, channel: &str) -> (Limit, usize) {
+        let ch = self.channels.get(channel);
+        let limit = Limit {
+            max: ch.and_then(|c| c.max_lines).unwrap_or(self.max_lines),
+            overflow: ch.and_then(|c| c.overflow).unwrap_or(self.overflow),
+        };
+        (limit, ch.and_then(|c| c.list_width).unwrap_or(self.list_width))
+    }
+}
+
+fn default_flood_burst() -> u32 {
+    4
+}
+
+fn default_flood_delay_ms() -> u64 {
+    2000
+}
+
+fn default_max_lines() -> usize {
+    3
+}
+
+fn default_list_width() -> usize {
+    300
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, PartialEq)]
 pub struct Discord {
     pub id: String,
 
     pub token: String,
+
+    // Messages longer than Discord's limit are split; beyond this many parts, the rest is pasted or
+    // kept for `more`, as `overflow` says.
+    #[serde(default = "default_max_chunks")]
+    pub max_chunks: usize,
+    #[serde(default)]
+    pub overflow: Overflow,
+    // Overrides of the above for single channels, by channel id.
+    #[serde(default)]
+    pub channels: BTreeMap<String, DiscordChannel>,
+}
+
+#[derive(Deserialize, Clone, Default, PartialEq)]
+pub struct DiscordChannel {
+    pub max_chunks: Option<usize>,
+    pub overflow: Option<Overflow>,
+}
+
+impl Discord {
+    pub fn limit(&self, channel: u64) -> Limit {
+        let ch = self.channels.get(&channel.to_string());
+        Limit {
+            max: ch.and_then(|c| c.max_chunks).unwrap_or(self.max_chunks),
+            overflow: ch.and_then(|c| c.overflow).unwrap_or(self.overflow),
+        }
+    }
+}
+
+fn default_max_chunks() -> usize {
+    3
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct Matrix {
+    pub id: String,
+
+    pub homeserver: String,
+    pub user: String,
+
+    // One of these is required; with a password, the bot logs in on each connect.
+    pub access_token: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct Console {
+    pub id: String,
+
+    // Name to attribute console input to; defaults to $USER.
+    pub user: Option<String>,
+    // Permission flags granted to console users.
+    #[serde(default)]
+    pub perms: u64,
+
+    // If set, listen on this Unix socket instead of reading from stdin. Its directory must be ours and
+    // mode 0700; it's created that way if missing.
+    pub socket: Option<String>,
+}
+
+// What to do with the part of a long message that is over its limit.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Overflow {
+    // Link to a paste of the whole message.
+    #[default]
+    Paste,
+    // Keep the rest for the `more` command to send.
+    More,
+}
+
+// Where long messages are put when they are too long to send.
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum Paste {
+    // Stored in `dir` and served over HTTP on `listen`. Links use `url`, which defaults to
+    // http://<listen> but can be set to the address of a reverse proxy in front of it; with neither
+    // that nor a non-loopback `listen`, long messages are kept for `more` instead. Pastes expire
+    // after `expiry_days`, or never if that is 0.
+    Builtin {
+        #[serde(default = "default_paste_dir")]
+        dir: String,
+        #[serde(default = "default_paste_listen")]
+        listen: String,
+        url: Option<String>,
+        #[serde(default = "default_paste_expiry_days")]
+        expiry_days: u64,
+    },
+    // Piped to `command`, which prints the URL.
+    Script {
+        #[serde(default = "default_paste_command")]
+        command: String,
+    },
+}
+
+impl Default for Paste {
+    fn default() -> Self {
+        Self::Builtin {
+            dir: default_paste_dir(),
+            listen: default_paste_listen(),
+            url: None,
+            expiry_days: default_paste_expiry_days(),
+        }
+    }
+}
+
+fn default_paste_dir() -> String {
+    "pastes".to_string()
+}
+
+// Prometheus metrics, served at http://<listen>/metrics.
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct Metrics {
+    #[serde(default = "default_metrics_listen")]
+    pub listen: String,
+}
+
+fn default_metrics_listen() -> String {
+    "127.0.0.1:9090".to_string()
+}
+
+fn default_paste_listen() -> String {
+    "127.0.0.1:8090".to_string()
+}
+
+fn default_paste_expiry_days() -> u64 {
+    30
+}
+
+fn default_paste_command() -> String {
+    "./external/paste".to_string()
+}
+
+// One [[irc]], [[discord]], [[matrix]] or [[console]] entry.
+#[derive(Clone, PartialEq)]
+pub enum PlatformConfig {
+    Irc(Irc),
+    Discord(Discord),
+    Matrix(Matrix),
+    Console(Console),
+}
+
+impl PlatformConfig {
+    pub fn id(&self) -> &str {
+        match self {
+            PlatformConfig::Irc(c) => &c.id,
+            PlatformConfig::Discord(c) => &c.id,
+            PlatformConfig::Matrix(c) => &c.id,
+            PlatformConfig::Console(c) => &c.id,
+        }
+    }
+
+    // The section the entry is in, for messages about it.
+    pub fn section(&self) -> &'static str {
+        match self {
+            PlatformConfig::Irc(_) => "irc",
+            PlatformConfig::Discord(_) => "discord",
+            PlatformConfig::Matrix(_) => "matrix",
+            PlatformConfig::Console(_) => "console",
+        }
+    }
+}
+
+impl Config {
+    // Every platform entry, by config id.
+    pub fn platforms(&self) -> BTreeMap<String, PlatformConfig> {
+        self.platform_entries()
+            .into_iter()
+            .map(|c| (c.id().to_string(), c))
+            .collect()
+    }
+
+    fn platform_entries(&self) -> Vec<PlatformConfig> {
+        let irc = self.irc.iter().cloned().map(PlatformConfig::Irc);
+        let discord = self.discord.iter().cloned().map(PlatformConfig::Discord);
+        let matrix = self.matrix.iter().cloned().map(PlatformConfig::Matrix);
+        let console = self.console.iter().cloned().map(PlatformConfig::Console);
+        irc.chain(discord).chain(matrix).chain(console).collect()
+    }
+
+    fn validate(&self) -> Result<()> {
+        let mut sections = BTreeMap::new();
+        for c in self.platform_entries() {
+            if c.id().is_empty() {
+                bail!("a [[{}]] entry has an empty id", c.section());
+            }
+            if let Some(other) = sections.insert(c.id().to_string(), c.section()) {
+                bail!(
+                    "config id {:?} is used by both a [[{}]] and a [[{}]] entry",
+                    c.id(),
+                    other,
+                    c.section()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn parse(text: &str) -> Result<Config> {
+    let config: Config = toml::from_str(text)?;
+    config.validate()?;
+    Ok(config)
 }
 
 pub fn load() -> Result<Config> {
-    Ok(toml::from_str(&fs::read_to_string("Rustbot.toml")?)?)
+    parse(&fs::read_to_string("Rustbot.toml")?)
 }
@@ -0,0 +1,193 @@
+use parking_lot::{Mutex, RwLock};
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+use crate::bot::Rustbot;
+use crate::config;
+use crate::message;
+use crate::platform::{Origin, Platform};
+use rustbot::prelude::*;
+
+pub type ConsoleOut = Arc<Mutex<Box<dyn Write + Send>>>;
+
+pub fn console_send(out: &ConsoleOut, message: Message) -> Result<()> {
+    let mut out = out.lock();
+    for line in message::format_console(message) {
+        writeln!(out, "{line}")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+// Makes sure the directory holding the console socket `path` is owned by us and closed to everyone
+// else, creating it mode 0700 if it's missing.
+pub fn check_socket_dir(path: &str) -> Result<()> {
+    let dir = match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .with_context(|| format!("failed to create console socket directory {dir:?}"))?;
+
+    let meta = fs::metadata(dir).with_context(|| format!("failed to stat console socket directory {dir:?}"))?;
+    let uid = unsafe { libc::getuid() };
+    if meta.uid() != uid || meta.mode() & 0o077 != 0 {
+        bail!(
+            "console socket directory {:?} must be ours (uid {}) and mode 0700, not uid {} and mode {:o}",
+            dir,
+            uid,
+            meta.uid(),
+            meta.mode() & 0o777
+        );
+    }
+    Ok(())
+}
+
+// A local console: stdin/stdout, or each connection to a Unix socket, as a separate session. Every
+// session is a private channel named after the session.
+pub struct ConsolePlatform {
+    config: config::Console,
+    sessions: RwLock<BTreeMap<String, ConsoleOut>>,
+}
+
+impl ConsolePlatform {
+    pub fn new(config: config::Console) -> Self {
+        Self {
+            config,
+            sessions: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    // Adds this console's config id to the database if it is new, with `!` as its command character, so
+    // that a console can be used as soon as it is configured under any id.
+    fn ensure_config(&self, bot: &Rustbot) -> Result<()> {
+        let mut db = bot.sql().lock();
+        db.execute(
+            "INSERT INTO configs (id) VALUES ($1) ON CONFLICT (id) DO NOTHING",
+            &[&self.config.id],
+        )?;
+        db.execute(
+            "INSERT INTO cmdchars (config_id, channel, cmdchars)
+            SELECT $1, '%', '!' WHERE NOT EXISTS (SELECT 1 FROM cmdchars WHERE config_id = $1)",
+            &[&self.config.id],
+        )?;
+        Ok(())
+    }
+
+    fn run_session(self: &Arc<Self>, bot: &Rustbot, session: &str, input: impl BufRead, out: ConsoleOut) {
+        let c = &self.config;
+        let user = c
+            .user
+            .clone()
+            .or_else(|| std::env::var("USER").ok())
+            .unwrap_or_else(|| "console".to_string());
+
+        self.sessions.write().insert(session.to_string(), out);
+        info!("console session opened: {}:{}", c.id, session);
+
+        for line in input.lines() {
+            match line {
+                Ok(line) => {
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let origin = Origin {
+                        user: format!("{user}@{session}"),
+                        user_pretty: user.clone(),
+                        channel: session.to_string(),
+                        data: Arc::new(()),
+                    };
+                    bot.incoming(self.clone(), origin, HandleType::PlainMsg | HandleType::Private, line);
+                }
+                Err(e) => {
+                    warn!("console session {}:{} read failed: {}", c.id, session, e);
+                    break;
+                }
+            }
+        }
+
+        self.sessions.write().remove(session);
+        info!("console session closed: {}:{}", c.id, session);
+    }
+}
+
+impl Platform for ConsolePlatform {
+    fn kind(&self) -> &'static str {
+        "con"
+    }
+
+    fn config_id(&self) -> &str {
+        &self.config.id
+    }
+
+    fn describe(&self) -> String {
+        match &self.config.socket {
+            Some(path) => format!("{} ({})", self.config.id, path),
+            None => self.config.id.clone(),
+        }
+    }
+
+    fn connect(self: Arc<Self>, bot: Arc<Rustbot>) -> Result<()> {
+        self.ensure_config(&bot)?;
+
+        let path = match &self.config.socket {
+            None => {
+                let out: ConsoleOut = Arc::new(Mutex::new(Box::new(std::io::stdout())));
+                self.run_session(&bot, "stdin", std::io::stdin().lock(), out);
+                return Ok(());
+            }
+            Some(path) => path,
+        };
+
+        // Anyone who can connect gets the configured perms, so only let our own user connect. The
+        // socket is created with the umask's mode, so the directory is what keeps others out until
+        // the chmod below.
+        check_socket_dir(path)?;
+        // A socket file left behind by a previous run would make bind() fail.
+        let _ = fs::remove_file(path);
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("failed to restrict console socket {path:?}"))?;
+        info!("listen: console {} on {}", self.config.id, path);
+
+        for (n, stream) in listener.incoming().enumerate() {
+            let stream = stream?;
+            let out: ConsoleOut = Arc::new(Mutex::new(Box::new(stream.try_clone()?)));
+            let bot = bot.clone();
+            let this = self.clone();
+            thread::Builder::new()
+                .name(format!("Console: {} #{}", self.config.id, n))
+                .spawn(move || {
+                    this.run_session(&bot, &format!("sock{n}"), BufReader::new(stream), out);
+                })?;
+        }
+        Ok(())
+    }
+
+    fn send(&self, session: &str, msg: Message) -> Result<()> {
+        let out = self.sessions.read().get(session).cloned();
+        match out {
+            Some(out) => console_send(&out, msg),
+            None => bail!("no console session {:?} for config {:?}", session, self.config.id),
+        }
+    }
+
+    fn perms(&self, _bot: &Rustbot, _origin: &Origin) -> Result<Perms> {
+        Ok(Perms::from_bits_truncate(self.config.perms))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
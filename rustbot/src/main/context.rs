@@ -1,8 +1,8 @@
 use crate::bot;
-use crate::message;
+use crate::platform::{channel_string, Origin, Platform};
+use crate::watchdog::Invocation;
 use rustbot::prelude::*;
 use rustbot::types;
-use serenity::model::prelude as ser;
 use std::borrow::Cow;
 use std::sync::Arc;
 
@@ -10,41 +10,7 @@ pub struct Context<'a> {
     pub bot: &'a bot::Rustbot,
     pub config: String,
     pub source: Source,
-    pub bot_name: String,
-}
-
-impl<'a> Context<'a> {
-    fn reply_impl(&self, source: &Source, message: Message) -> Result<()> {
-        match source {
-            Source::Irc { prefix, channel } => {
-                if let Some(Prefix::User { nick, .. }) = prefix {
-                    match channel {
-                        None => {
-                            for msg in message::format_irc(message)? {
-                                self.bot.irc_send_privmsg(&self.config, nick.as_str(), msg.as_str())?;
-                            }
-                        }
-
-                        Some(ch) => {
-                            for msg in message::format_irc(message)? {
-                                self.bot.irc_send_privmsg(
-                                    &self.config,
-                                    ch.as_str(),
-                                    &format!("{}: {}", nick.as_str(), msg.as_str()),
-                                )?;
-                            }
-                        }
-                    }
-                }
-            }
-            Source::Discord { channel, http, .. } => {
-                channel.say(http, message::format_discord(message)?).map(|_| ())?;
-            }
-            Source::Sub { parent, .. } => return self.reply_impl(parent, message),
-        }
-
-        Ok(())
-    }
+    pub invocation: Option<Arc<Invocation>>, // the module command this context was made for, if any
 }
 
 impl<'a> types::Context for Context<'a> {
@@ -65,55 +31,17 @@ impl<'a> types::Context for Context<'a> {
     }
 
     fn reply(&self, message: Message) -> Result<()> {
-        self.reply_impl(&self.source, message)
+        if self.invocation.as_ref().is_some_and(|i| i.is_abandoned()) {
+            bail!("not replying for an abandoned command");
+        }
+        self.bot.metrics.messages_sent.inc(&[&self.config]);
+        let (platform, origin) = self.source.root();
+        platform.reply(origin, message)
     }
 
     fn perms(&self) -> Result<Perms> {
-        // TODO
-        // self.bot.perms(&self.config, &self.source)
         match &self.source {
-            Source::Irc {
-                prefix: Some(Prefix::User { nick, user, host }),
-                ..
-            } => {
-                let perms: Perms = match self.bot.sql().lock().query(
-                    "SELECT flags FROM irc_permissions WHERE config_id = $1 AND nick = $2 AND username = $3 AND host = $4",
-                    &[&self.config, &nick, &user, &host],
-                ) {
-                    Err(e) => {
-                        error!("error fetching perms: {}", e);
-                        Perms::None
-                    }
-                    Ok(v) => {
-                        if v.is_empty() {
-                            Perms::None
-                        } else {
-                            v.get(0).unwrap().get(0)
-                        }
-                    }
-                };
-                Ok(perms)
-            }
-            Source::Irc { .. } => Ok(Perms::None),
-            Source::Discord { user, .. } => {
-                let perms: Perms = match self.bot.sql().lock().query(
-                    "SELECT flags FROM dis_permissions WHERE config_id = $1 AND user_id = $2",
-                    &[&self.config, &(*user.id.as_u64() as i64)],
-                ) {
-                    Err(e) => {
-                        error!("error fetching perms: {}", e);
-                        Perms::None
-                    }
-                    Ok(v) => {
-                        if v.is_empty() {
-                            Perms::None
-                        } else {
-                            v.get(0).unwrap().get(0)
-                        }
-                    }
-                };
-                Ok(perms)
-            }
+            Source::Platform { platform, origin } => platform.perms(self.bot, origin),
             Source::Sub { .. } => Ok(Perms::None), // TODO
         }
     }
@@ -127,7 +55,7 @@ impl<'a> types::Context for Context<'a> {
                     parent: Box::new(self.source.clone()),
                     name: name.to_string(),
                 },
-                bot_name: self.bot_name.clone(),
+                invocation: self.invocation.clone(),
             },
             HandleType::PlainMsg,
             msg,
@@ -137,17 +65,9 @@ impl<'a> types::Context for Context<'a> {
 
 #[derive(Clone)]
 pub enum Source {
-    Irc {
-        prefix: Option<Prefix>,
-        channel: Option<String>,
-    },
-    Discord {
-        user: ser::User,
-        channel: ser::ChannelId,
-        guild: Option<ser::GuildId>,
-
-        cache: serenity::cache::CacheRwLock,
-        http: Arc<serenity::http::Http>,
+    Platform {
+        platform: Arc<dyn Platform>,
+        origin: Origin,
     },
     Sub {
         parent: Box<Source>,
@@ -155,91 +75,60 @@ pub enum Source {
     },
 }
 
+impl Context<'_> {
+    // Sends the next part of a long message that was held back for `more`; false if there was none.
+    pub fn more(&self) -> Result<bool> {
+        let (platform, origin) = self.source.root();
+        platform.more(origin)
+    }
+}
+
+impl Source {
+    // The platform message that this source ultimately derives from.
+    fn root(&self) -> (&dyn Platform, &Origin) {
+        match self {
+            Source::Platform { platform, origin } => (&**platform, origin),
+            Source::Sub { parent, .. } => parent.root(),
+        }
+    }
+}
+
 impl types::Source for Source {
     fn user_string(&self) -> Cow<str> {
         match self {
-            Source::Irc { prefix, .. } => {
-                if let Some(prefix) = prefix {
-                    format!("{prefix}").into()
-                } else {
-                    "none".into()
-                }
-            }
-            Source::Discord { user, guild, .. } => {
-                format!("{:?}:{}", guild.map(|g| *g.as_u64()), user.id.as_u64()).into()
-            }
+            Source::Platform { origin, .. } => (&origin.user).into(),
             Source::Sub { parent, name } => format!("{}@{}", parent.user_string(), name).into(),
         }
     }
 
     fn user_pretty(&self) -> Cow<str> {
         match self {
-            Source::Irc { prefix, .. } => match prefix {
-                Some(Prefix::User { nick, .. }) => nick.into(),
-                Some(Prefix::Server(s)) => s.into(),
-                None => "???".into(),
-            },
-            Source::Discord { user, .. } => (&user.name).into(),
+            Source::Platform { origin, .. } => (&origin.user_pretty).into(),
             Source::Sub { name, .. } => name.into(),
         }
     }
 
     fn channel_string(&self) -> Cow<str> {
-        match self {
-            Source::Irc { channel, .. } => {
-                if let Some(channel) = channel {
-                    format!("irc:{channel}")
-                } else {
-                    "irc:query".to_string()
-                }
-            }
-            Source::Discord { channel, guild, .. } => format!(
-                "dis:{}:{}",
-                guild
-                    .map(|g| format!("{}", *g.as_u64()))
-                    .unwrap_or_else(|| "none".to_string()),
-                channel.as_u64()
-            ),
-            Source::Sub { parent, .. } => parent.channel_string().into_owned(),
-        }
-        .into()
+        let (platform, origin) = self.root();
+        channel_string(platform, &origin.channel).into()
     }
 
     fn get_discord_params(&self) -> Option<(Option<u64>, u64, u64)> {
-        if let Source::Discord {
-            guild, channel, user, ..
-        } = self
-        {
-            Some((guild.map(|g| *g.as_u64()), *channel.as_u64(), *user.id.as_u64()))
-        } else {
-            None
+        match self {
+            Source::Platform { platform, origin } => platform.discord_params(origin),
+            Source::Sub { .. } => None,
         }
     }
 
     fn get_irc_params(&self) -> Option<(Option<String>, String)> {
-        if let Source::Irc { prefix, channel, .. } = self {
-            match prefix {
-                Some(Prefix::User { nick, .. }) => Some((channel.clone(), nick.clone())),
-                Some(Prefix::Server(s)) => Some((channel.clone(), s.clone())),
-                None => None,
-            }
-        } else {
-            None
+        match self {
+            Source::Platform { platform, origin } => platform.irc_params(origin),
+            Source::Sub { .. } => None,
         }
     }
-}
-
-#[derive(Debug, Clone)]
-pub enum Prefix {
-    Server(String),
-    User { nick: String, user: String, host: String },
-}
 
-impl std::fmt::Display for Prefix {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        match self {
-            Self::Server(s) => write!(f, "{s}"),
-            Self::User { nick, user, host } => write!(f, "{nick}!{user}@{host}"),
-        }
+    fn parse_message(&self, msg: &str) -> Result<Option<MessageText<'static>>> {
+        let (platform, origin) = self.root();
+        platform.parse_message(origin, msg)
     }
 }
@@ -0,0 +1,119 @@
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use rustbot::prelude::*;
+
+// The bucket a use of a command counts against.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CooldownKey {
+    pub config: String,
+    pub command: String,
+    pub scope: CooldownScope,
+    pub target: String, // the user or channel string; empty for CooldownScope::Global
+}
+
+impl std::fmt::Display for CooldownKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
+        match self.scope {
+            CooldownScope::User => write!(f, "{} (user {} on {})", self.command, self.target, self.config),
+            CooldownScope::Channel => write!(f, "{} (channel {} on {})", self.command, self.target, self.config),
+            CooldownScope::Global => write!(f, "{} (global on {})", self.command, self.config),
+        }
+    }
+}
+
+// Allows `burst` uses at once, regaining one use every `refill`; a zero `refill` never runs out.
+pub struct TokenBucket {
+    burst: u32,
+    refill: Duration,
+    uses: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(burst: u32, refill: Duration, now: Instant) -> Self {
+        let burst = burst.max(1);
+        Self {
+            burst,
+            refill,
+            uses: f64::from(burst),
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let refill = self.refill.as_secs_f64();
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.uses = if refill > 0.0 {
+            (self.uses + elapsed / refill).min(f64::from(self.burst))
+        } else {
+            f64::from(self.burst)
+        };
+        self.updated = self.updated.max(now); // `now` can be behind a use taken on another thread
+    }
+
+    fn is_full(&self) -> bool {
+        self.uses >= f64::from(self.burst)
+    }
+
+    // Takes one use, or returns how long it will be until one is available.
+    pub fn take(&mut self, now: Instant) -> std::result::Result<(), Duration> {
+        self.refill(now);
+        if self.uses >= 1.0 {
+            self.uses -= 1.0;
+            Ok(())
+        } else {
+            Err(self.refill.mul_f64(1.0 - self.uses))
+        }
+    }
+}
+
+// Token buckets for every command use that is still cooling down. Full buckets are dropped whenever a use
+// is taken, so a key that isn't present has all of its uses available.
+pub struct Cooldowns {
+    buckets: Mutex<BTreeMap<CooldownKey, TokenBucket>>,
+}
+
+impl Cooldowns {
+    pub fn new() -> Self {
+        Self {
+            buckets: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    // Takes one use from the bucket for `key`, or returns how long it will be until one is available.
+    pub fn take(&self, key: CooldownKey, cooldown: Cooldown, now: Instant) -> std::result::Result<(), Duration> {
+        let mut buckets = self.buckets.lock();
+        prune(&mut buckets, now);
+        let bucket = buckets
+            .entry(key)
+            .or_insert_with(|| TokenBucket::new(cooldown.burst, cooldown.refill, now));
+        // The command may have been reloaded with a different cooldown
+        bucket.burst = cooldown.burst.max(1);
+        bucket.refill = cooldown.refill;
+        bucket.take(now)
+    }
+
+    // The buckets that aren't full, with the uses left in each and its burst size.
+    pub fn active(&self, now: Instant) -> Vec<(CooldownKey, f64, u32)> {
+        let mut buckets = self.buckets.lock();
+        prune(&mut buckets, now);
+        buckets.iter().map(|(k, b)| (k.clone(), b.uses, b.burst)).collect()
+    }
+
+    // Refills the buckets for `command`, or all buckets; returns how many there were.
+    pub fn reset(&self, command: Option<&str>) -> usize {
+        let mut buckets = self.buckets.lock();
+        let before = buckets.len();
+        buckets.retain(|k, _| command.is_some_and(|c| k.command != c));
+        before - buckets.len()
+    }
+}
+
+fn prune(buckets: &mut BTreeMap<CooldownKey, TokenBucket>, now: Instant) {
+    buckets.retain(|_, b| {
+        b.refill(now);
+        !b.is_full()
+    });
+}
@@ -1,31 +1,154 @@
+use chrono::{DateTime, Utc};
 use log::Level;
 use rustbot::prelude::*;
 use std::collections::BTreeMap;
 use std::process::Command as ProcessCommand;
 use std::str;
-use std::time::Instant;
+use std::time::{Instant, SystemTime};
 
 use crate::context::Context;
 use rustbot::types::Context as TypesContext; // trait
 
-pub type CoreCommand = dyn Fn(&Context, &str) -> Result<()> + Send + Sync;
+pub type CoreCommandFn = dyn Fn(&Context, &str) -> Result<()> + Send + Sync;
 
-pub fn get_commands() -> BTreeMap<String, (Perms, Box<CoreCommand>)> {
-    let mut cmds: BTreeMap<_, (_, Box<CoreCommand>)> = BTreeMap::new();
+pub struct CoreCommand {
+    pub req_perms: Perms,
+    pub function: Box<CoreCommandFn>,
+    pub description: &'static str,
+    pub usage: &'static str,
+}
+
+fn cmd<F: 'static + Fn(&Context, &str) -> Result<()> + Send + Sync>(
+    req_perms: Perms,
+    description: &'static str,
+    usage: &'static str,
+    f: F,
+) -> CoreCommand {
+    CoreCommand {
+        req_perms,
+        function: Box::new(f),
+        description,
+        usage,
+    }
+}
+
+pub fn get_commands() -> BTreeMap<String, CoreCommand> {
+    let mut cmds = BTreeMap::new();
 
-    cmds.insert("drop".to_string(), (Perms::Modules, Box::new(drop)));
-    cmds.insert("load".to_string(), (Perms::Modules, Box::new(load)));
-    cmds.insert("reload".to_string(), (Perms::Modules, Box::new(reload)));
-    cmds.insert("recompile".to_string(), (Perms::Modules, Box::new(recompile)));
-    cmds.insert("log".to_string(), (Perms::Modules, Box::new(log)));
-    cmds.insert("suppress".to_string(), (Perms::Modules, Box::new(suppress)));
+    cmds.insert(
+        "drop".to_string(),
+        cmd(Perms::Modules, "unload modules", "<module> [<module>...]", drop),
+    );
+    cmds.insert(
+        "load".to_string(),
+        cmd(Perms::Modules, "load modules", "<module> [<module>...]", load),
+    );
+    cmds.insert(
+        "reload".to_string(),
+        cmd(
+            Perms::Modules,
+            "unload and reload modules",
+            "<module> [<module>...]",
+            reload,
+        ),
+    );
+    cmds.insert(
+        "recompile".to_string(),
+        cmd(
+            Perms::Modules,
+            "rebuild the bot, then reload modules",
+            "<module> [<module>...]",
+            recompile,
+        ),
+    );
+    cmds.insert(
+        "rehash".to_string(),
+        cmd(
+            Perms::Admin,
+            "re-read Rustbot.toml, reconnecting platforms and reloading modules whose settings changed",
+            "",
+            rehash,
+        ),
+    );
+    cmds.insert(
+        "log".to_string(),
+        cmd(
+            Perms::Modules,
+            "set the global or a module's log level",
+            "[<module>] <error|warn|info|debug|trace|none>",
+            log,
+        ),
+    );
+    cmds.insert(
+        "suppress".to_string(),
+        cmd(
+            Perms::Modules,
+            "suppress errors from a module for a while",
+            "<module> <duration>",
+            suppress,
+        ),
+    );
     cmds.insert(
         "enable".to_string(),
-        (Perms::Modules, Box::new(move |ctx, args| set_enabled(ctx, args, true))),
+        cmd(
+            Perms::Modules,
+            "enable modules for a config",
+            "<config_id> <module> [<module>...]",
+            |ctx, args| set_enabled(ctx, args, true),
+        ),
     );
     cmds.insert(
         "disable".to_string(),
-        (Perms::Modules, Box::new(move |ctx, args| set_enabled(ctx, args, false))),
+        cmd(
+            Perms::Modules,
+            "disable modules for a config",
+            "<config_id> <module> [<module>...]",
+            |ctx, args| set_enabled(ctx, args, false),
+        ),
+    );
+    cmds.insert(
+        "schema".to_string(),
+        cmd(
+            Perms::Modules,
+            "show which schema versions of modules are applied, or undo a dropped module's schema",
+            "[drop <module>]",
+            schema,
+        ),
+    );
+    cmds.insert(
+        "cooldowns".to_string(),
+        cmd(
+            Perms::Admin,
+            "list the commands that are cooling down, or reset their cooldowns",
+            "[reset] [<command>]",
+            cooldowns,
+        ),
+    );
+    cmds.insert(
+        "ps".to_string(),
+        cmd(
+            Perms::Admin,
+            "list the module commands and jobs that are running, or stop waiting for one that is stuck",
+            "[abandon <id>]",
+            ps,
+        ),
+    );
+    cmds.insert(
+        "audit".to_string(),
+        cmd(
+            Perms::Admin,
+            "show recent uses of core and privileged commands, by a user (% matches anything) or of a command",
+            "[<user>|<command>] [<since>]",
+            audit,
+        ),
+    );
+    cmds.insert(
+        "more".to_string(),
+        cmd(Perms::None, "continue a long message that was cut short", "", more),
+    );
+    cmds.insert(
+        "help".to_string(),
+        cmd(Perms::None, "list commands, or describe one", "[<command>]", help),
     );
 
     cmds
@@ -90,6 +213,18 @@ fn recompile(ctx: &Context, args: &str) -> Result<()> {
     }
 }
 
+fn rehash(ctx: &Context, _args: &str) -> Result<()> {
+    let changes = ctx.bot.rehash()?;
+    if changes.is_empty() {
+        return ctx.reply(Message::Simple("Rustbot.toml is unchanged".to_string()));
+    }
+    ctx.reply(Message::List {
+        prefix: "rehashed: ".into(),
+        sep: "; ".into(),
+        items: changes.into_iter().map(Into::into).collect(),
+    })
+}
+
 fn parse_log_level(s: &str) -> Result<Option<Level>> {
     Ok(Some(match s {
         "err" | "error" => Level::Error,
@@ -121,7 +256,7 @@ fn log(ctx: &Context, args: &str) -> Result<()> {
 fn suppress(ctx: &Context, args: &str) -> Result<()> {
     let a = args.split(' ').collect::<Vec<&str>>();
     if a.len() != 2 {
-        bail_user!("Usage: suppress <module> <seconds>");
+        bail_user!("Usage: suppress <module> <duration>");
     }
 
     let module = a[0].to_string();
@@ -158,3 +293,240 @@ fn set_enabled(ctx: &Context, args: &str, target: bool) -> Result<()> {
 
     ctx.reply(Message::Simple("Done".to_string()))
 }
+
+fn schema(ctx: &Context, args: &str) -> Result<()> {
+    match args.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [] => {}
+        ["drop", module] => {
+            let n = ctx.bot.drop_module_schema(module)?;
+            return ctx.reply(Message::Simple(format!("undid {n} migrations of {module}")));
+        }
+        _ => bail_user!("Usage: schema [drop <module>]"),
+    }
+
+    let rows = ctx.bot().sql().lock().query(
+        "SELECT module, max(version), count(*) FROM module_migrations GROUP BY module ORDER BY module",
+        &[],
+    )?;
+    if rows.is_empty() {
+        return ctx.reply(Message::Simple("no module has applied any migrations".to_string()));
+    }
+    ctx.reply(Message::List {
+        prefix: "module schema versions: ".into(),
+        sep: ", ".into(),
+        items: rows
+            .iter()
+            .map(|row| {
+                let (module, version, count): (String, i64, i64) = (row.get(0), row.get(1), row.get(2));
+                format!("{module} v{version} ({count} migrations)").into()
+            })
+            .collect(),
+    })
+}
+
+fn cooldowns(ctx: &Context, args: &str) -> Result<()> {
+    let a = args.split_whitespace().collect::<Vec<_>>();
+    let (reset, command) = match a.as_slice() {
+        [] => (false, None),
+        ["reset"] => (true, None),
+        ["reset", command] => (true, Some(*command)),
+        [command] => (false, Some(*command)),
+        _ => bail_user!("Usage: cooldowns [reset] [<command>]"),
+    };
+
+    if reset {
+        let n = ctx.bot.cooldowns.reset(command);
+        return ctx.reply(Message::Simple(format!("reset {n} cooldowns")));
+    }
+
+    let items: Vec<_> = ctx
+        .bot
+        .cooldowns
+        .active(Instant::now())
+        .into_iter()
+        .filter(|(key, _, _)| command.is_none_or(|c| key.command == c))
+        .map(|(key, uses, burst)| format!("{key}: {uses:.1}/{burst} uses left").into())
+        .collect();
+    if items.is_empty() {
+        return ctx.reply(Message::Simple("nothing is cooling down".to_string()));
+    }
+    ctx.reply(Message::List {
+        prefix: "cooling down: ".into(),
+        sep: ", ".into(),
+        items,
+    })
+}
+
+fn ps(ctx: &Context, args: &str) -> Result<()> {
+    match args.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [] => {}
+        ["abandon", id] => {
+            let id = id.parse().map_err(|_| UserError::new(format!("invalid id {id:?}")))?;
+            let invocation = ctx.bot.watchdog.abandon(id)?;
+            return ctx.reply(Message::Simple(format!(
+                "abandoned {} ({} of {})",
+                id, invocation.running.command, invocation.running.module
+            )));
+        }
+        _ => bail_user!("Usage: ps [abandon <id>]"),
+    }
+
+    let now = Instant::now();
+    let items: Vec<_> = ctx
+        .bot
+        .watchdog
+        .running()
+        .iter()
+        .map(|i| {
+            let r = &i.running;
+            let place = if r.config.is_empty() {
+                String::new()
+            } else {
+                format!(" in {}:{}", r.config, r.channel)
+            };
+            format!(
+                "{}: {} of {} for {}{}, {}s/{}s{}",
+                i.id,
+                r.command,
+                r.module,
+                r.user,
+                place,
+                now.saturating_duration_since(i.started).as_secs(),
+                r.timeout.as_secs(),
+                if i.is_abandoned() { " (abandoned)" } else { "" }
+            )
+            .into()
+        })
+        .collect();
+    if items.is_empty() {
+        return ctx.reply(Message::Simple("no module commands or jobs are running".to_string()));
+    }
+    ctx.reply(Message::List {
+        prefix: "running: ".into(),
+        sep: ", ".into(),
+        items,
+    })
+}
+
+// How many entries `audit` shows.
+const AUDIT_ENTRIES: i64 = 10;
+
+fn audit(ctx: &Context, args: &str) -> Result<()> {
+    let mut words = args.split_whitespace().collect::<Vec<_>>();
+    let since = match words.last().map(|w| parse_duration(w)) {
+        Some(Ok(d)) => {
+            words.pop();
+            Some(SystemTime::now() - d)
+        }
+        _ => None,
+    };
+    let (user, command) = match words.as_slice() {
+        [] => (None, None),
+        [w] if ctx.bot.is_command(w) => (None, Some(*w)),
+        [w] => (Some(*w), None),
+        _ => bail_user!("Usage: audit [<user>|<command>] [<since>]"),
+    };
+
+    let rows = ctx.bot().sql().lock().query(
+        "SELECT at, user_string, channel_string, command, args, outcome FROM audit_log
+        WHERE ($1::TEXT IS NULL OR user_string LIKE $1)
+            AND ($2::TEXT IS NULL OR command = $2)
+            AND ($3::TIMESTAMPTZ IS NULL OR at >= $3)
+        ORDER BY at DESC, id DESC
+        LIMIT $4",
+        &[&user, &command, &since, &AUDIT_ENTRIES],
+    )?;
+    if rows.is_empty() {
+        return ctx.reply(Message::Simple("no matching audit log entries".to_string()));
+    }
+    ctx.reply(Message::List {
+        prefix: "audit log, latest first: ".into(),
+        sep: "; ".into(),
+        items: rows
+            .iter()
+            .map(|row| {
+                let at = DateTime::<Utc>::from(row.get::<_, SystemTime>(0));
+                let (user, channel, command, args, outcome): (String, String, String, String, String) =
+                    (row.get(1), row.get(2), row.get(3), row.get(4), row.get(5));
+                let invocation = if args.is_empty() {
+                    command
+                } else {
+                    format!("{command} {args}")
+                };
+                format!(
+                    "{} {} in {}: {} ({})",
+                    at.format("%Y-%m-%d %H:%M:%S"),
+                    user,
+                    channel,
+                    invocation,
+                    outcome
+                )
+                .into()
+            })
+            .collect(),
+    })
+}
+
+// What `help` shows for a command.
+pub struct Help {
+    pub module: Option<String>,
+    pub description: Option<String>,
+    pub usage: Option<String>,
+    pub example: Option<String>,
+}
+
+fn more(ctx: &Context, _args: &str) -> Result<()> {
+    if !ctx.more()? {
+        bail_user!("there is nothing more to show");
+    }
+    Ok(())
+}
+
+fn help(ctx: &Context, args: &str) -> Result<()> {
+    let commands = ctx.bot.available_commands(ctx)?;
+
+    let name = args.trim();
+    if name.is_empty() {
+        return ctx.reply(Message::List {
+            prefix: "available commands: ".into(),
+            sep: ", ".into(),
+            items: commands.into_keys().map(Into::into).collect(),
+        });
+    }
+
+    let (target, _) = ctx.bot.resolve_alias(name, "")?;
+    let help = match commands.get(&target) {
+        Some(h) => h,
+        None => bail_user!("no such command {:?}", name),
+    };
+
+    let mut lines = vec![];
+    let source = help
+        .module
+        .as_ref()
+        .map_or_else(String::new, |m| format!(" (from {m})"));
+    if target == name {
+        lines.push(format!(
+            "{}{}: {}",
+            name,
+            source,
+            help.description.as_deref().unwrap_or("no description")
+        ));
+    } else {
+        lines.push(format!(
+            "{} is an alias for {}{}: {}",
+            name,
+            target,
+            source,
+            help.description.as_deref().unwrap_or("no description")
+        ));
+    }
+    if let Some(usage) = &help.usage {
+        lines.push(format!("usage: {target} {usage}"));
+    }
+    if let Some(example) = &help.example {
+        lines.push(format!("example: {target} {example}"));
+    }
+
+    ctx.reply(Message::Simple(lines.join("\n")))
+}
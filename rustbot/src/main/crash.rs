@@ -0,0 +1,88 @@
+use parking_lot::Mutex;
+use std::any::Any;
+use std::collections::{BTreeMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+
+// A module that panics this many times within CRASH_WINDOW is unloaded.
+pub const CRASH_LIMIT: usize = 3;
+pub const CRASH_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+// The error a command, handler or job returns in place of panicking.
+#[derive(Debug)]
+pub struct Crash {
+    pub module: String,
+    pub what: String, // e.g. "command \"weather\""
+    pub message: String,
+    pub disable: bool, // whether the module has now crashed too often to keep
+}
+
+impl std::fmt::Display for Crash {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "{} of module {:?} panicked: {}",
+            self.what, self.module, self.message
+        )
+    }
+}
+
+impl std::error::Error for Crash {}
+
+// Runs `f`, returning the panic message if it panics. Modules don't share state with the bot that a
+// panic could leave broken (parking_lot locks aren't poisoned), so unwinding out of one is safe.
+pub fn catch<T>(f: impl FnOnce() -> T) -> std::result::Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| panic_message(&*payload))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        (*s).to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "(non-string panic payload)".to_string()
+    }
+}
+
+// When each module last panicked, within the window.
+pub struct CrashTracker {
+    limit: usize,
+    window: Duration,
+    crashes: Mutex<BTreeMap<String, VecDeque<Instant>>>,
+}
+
+impl CrashTracker {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            crashes: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    // Counts a panic of `module`, returning true if it has now panicked `limit` times within the window.
+    // Its count starts again from zero afterwards.
+    pub fn record(&self, module: &str, now: Instant) -> bool {
+        let mut crashes = self.crashes.lock();
+        let times = crashes.entry(module.to_string()).or_default();
+        while times
+            .front()
+            .is_some_and(|t| now.saturating_duration_since(*t) >= self.window)
+        {
+            times.pop_front();
+        }
+        times.push_back(now);
+
+        if times.len() >= self.limit {
+            crashes.remove(module);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn forget(&self, module: &str) {
+        self.crashes.lock().remove(module);
+    }
+}
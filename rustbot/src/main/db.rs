@@ -1,6 +1,7 @@
 use crate::config;
 use migrant_lib::config::PostgresSettingsBuilder;
 use postgres::{Client, NoTls};
+use std::collections::{BTreeMap, BTreeSet};
 
 use rustbot::prelude::*;
 
@@ -39,6 +40,61 @@ fn migrate(pc: &config::Postgres) -> Result<String> {
     config.connect_string().map_err(from_migrant)
 }
 
+// A change to a module's own tables, from Meta::migration.
+pub struct Migration {
+    pub up: String,
+    pub down: String,
+}
+
+// Applies the migrations of `module` that no earlier load has, in order of version, recording each in
+// module_migrations along with how to undo it. Returns the versions applied.
+pub fn migrate_module(db: &mut Client, module: &str, migrations: &BTreeMap<u32, Migration>) -> Result<Vec<u32>> {
+    let applied: BTreeSet<i64> = db
+        .query("SELECT version FROM module_migrations WHERE module = $1", &[&module])?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    let mut done = vec![];
+    for (&version, migration) in migrations {
+        if applied.contains(&i64::from(version)) {
+            continue;
+        }
+        let mut tx = db.transaction()?;
+        tx.batch_execute(&migration.up)
+            .with_context(|| format!("migration {version} of module {module:?} failed"))?;
+        tx.execute(
+            "INSERT INTO module_migrations (module, version, down) VALUES ($1, $2, $3)",
+            &[&module, &i64::from(version), &migration.down],
+        )?;
+        tx.commit()?;
+        info!("applied migration {} of module {}", version, module);
+        done.push(version);
+    }
+    Ok(done)
+}
+
+// Undoes every applied migration of `module`, newest first, returning how many there were.
+pub fn unmigrate_module(db: &mut Client, module: &str) -> Result<usize> {
+    let rows = db.query(
+        "SELECT version, down FROM module_migrations WHERE module = $1 ORDER BY version DESC",
+        &[&module],
+    )?;
+    for row in &rows {
+        let version: i64 = row.get(0);
+        let mut tx = db.transaction()?;
+        tx.batch_execute(row.get(1))
+            .with_context(|| format!("undoing migration {version} of module {module:?} failed"))?;
+        tx.execute(
+            "DELETE FROM module_migrations WHERE module = $1 AND version = $2",
+            &[&module, &version],
+        )?;
+        tx.commit()?;
+        info!("undid migration {} of module {}", version, module);
+    }
+    Ok(rows.len())
+}
+
 fn from_migrant(e: migrant_lib::Error) -> Error {
     Error::msg(format!("{e}"))
 }
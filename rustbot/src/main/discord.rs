@@ -0,0 +1,451 @@
+use parking_lot::RwLock;
+use serenity::client::bridge::gateway::ShardManager;
+use serenity::model::channel;
+use serenity::model::guild;
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::model::prelude as ser;
+use serenity::prelude as dis;
+use std::any::Any;
+use std::sync::Arc;
+
+use crate::bot::Rustbot;
+use crate::config;
+use crate::message::{self, MoreBuffer};
+use crate::paste::Paster;
+use crate::platform::{query_perms, Origin, Platform};
+use rustbot::prelude::*;
+
+pub struct DiscordPlatform {
+    config: config::Discord,
+    cache_and_http: RwLock<Option<Arc<serenity::CacheAndHttp>>>,
+    shard_manager: RwLock<Option<Arc<dis::Mutex<ShardManager>>>>,
+    paster: Arc<dyn Paster>,
+    more: MoreBuffer,
+}
+
+pub struct DiscordData {
+    pub user: ser::User,
+    pub channel: ChannelId,
+    pub guild: Option<GuildId>,
+
+    pub http: Arc<serenity::http::Http>,
+}
+
+impl DiscordPlatform {
+    pub fn new(config: config::Discord, paster: Arc<dyn Paster>) -> Self {
+        Self {
+            config,
+            cache_and_http: RwLock::new(None),
+            shard_manager: RwLock::new(None),
+            paster,
+            more: MoreBuffer::default(),
+        }
+    }
+
+    fn cache_and_http(&self) -> Result<Arc<serenity::CacheAndHttp>> {
+        match &*self.cache_and_http.read() {
+            Some(c) => Ok(c.clone()),
+            None => bail!("no cache found for config {:?}", self.config.id),
+        }
+    }
+
+    fn incoming(self: &Arc<Self>, bot: &Rustbot, disctx: dis::Context, msg: channel::Message) {
+        if msg.author.id == disctx.cache.read().user.id {
+            return;
+        }
+
+        let mut typ = HandleType::None;
+
+        match msg.channel_id.to_channel(&disctx) {
+            Err(e) => {
+                warn!("failed to determine channel type for incoming message: {}", e);
+                return;
+            }
+            Ok(c) => match c {
+                channel::Channel::Private(_) => typ |= HandleType::Private,
+                channel::Channel::Group(_) => typ |= HandleType::Group,
+                channel::Channel::Guild(_) => typ |= HandleType::Public,
+                _ => return,
+            },
+        }
+
+        let origin = Origin {
+            user: format!("{:?}:{}", msg.guild_id.map(|g| *g.as_u64()), msg.author.id.as_u64()),
+            user_pretty: msg.author.name.clone(),
+            channel: format!(
+                "{}:{}",
+                msg.guild_id
+                    .map(|g| format!("{}", *g.as_u64()))
+                    .unwrap_or_else(|| "none".to_string()),
+                msg.channel_id.as_u64()
+            ),
+            data: Arc::new(DiscordData {
+                user: msg.author,
+                channel: msg.channel_id,
+                guild: msg.guild_id,
+                http: disctx.http,
+            }),
+        };
+
+        if !msg.content.is_empty() {
+            bot.incoming(
+                self.clone(),
+                origin.clone(),
+                HandleType::PlainMsg | typ,
+                msg.content.as_str(),
+            );
+        }
+        for att in msg.attachments {
+            bot.incoming(
+                self.clone(),
+                origin.clone(),
+                HandleType::Attachment | typ,
+                &att.proxy_url,
+            );
+        }
+        if msg.content.is_empty() {
+            for embed in msg.embeds {
+                if embed.title.is_none() && embed.description.is_none() {
+                    // probably just a link, skip it
+                    continue;
+                }
+
+                let mut data = vec![];
+                if let Some(author) = embed.author {
+                    if let Some(url) = author.url {
+                        data.push(format!("{} <{}>", author.name, url));
+                    } else {
+                        data.push(author.name);
+                    }
+                }
+                if let Some(title) = embed.title {
+                    if let Some(url) = embed.url {
+                        data.push(format!("{title} <{url}>"));
+                    } else {
+                        data.push(title);
+                    }
+                }
+                if let Some(description) = embed.description {
+                    data.append(&mut description.split('\n').map(str::to_string).collect());
+                }
+                for field in embed.fields {
+                    if field.inline {
+                        data.push(format!("{}: {}", field.name, field.value.replace('\n', "\t")));
+                    } else {
+                        data.push(format!("{}:", field.name));
+                        for line in field.value.split('\n') {
+                            data.push(format!("\t{line}"));
+                        }
+                    }
+                }
+
+                if data.is_empty() {
+                    continue;
+                }
+
+                let mut spans = vec![];
+
+                if data.len() == 1 {
+                    spans.push(format!("│ {}", data.remove(0)));
+                } else {
+                    spans.push(format!("╽ {}", data.remove(0)));
+                    let lastline = data.remove(data.len() - 1);
+                    for line in data {
+                        spans.push(format!("┃ {line}"));
+                    }
+                    spans.push(format!("╿ {lastline}"));
+                }
+
+                bot.incoming(self.clone(), origin.clone(), HandleType::Embed | typ, &spans.join("\n"));
+            }
+        }
+    }
+
+    pub fn unprocess_message(&self, guild: &str, message: &str) -> Result<String> {
+        let cache_and_http = self.cache_and_http()?;
+        let cache = cache_and_http.cache.read();
+
+        let mut message = message.to_string();
+
+        let guildobj = {
+            if let Ok(id) = guild.parse() {
+                cache.guilds.get(&GuildId(id))
+            } else {
+                let mut v = None;
+                for g in cache.guilds.values() {
+                    if g.read().name == guild {
+                        v = Some(g);
+                        break;
+                    }
+                }
+                v
+            }
+        }
+        .ok_or_else(|| Error::msg("guild not found"))?
+        .read();
+
+        let mut replacements = get_replacements(guildobj, true);
+
+        replacements.sort_by(|l, r| {
+            if l.1.len() != r.1.len() {
+                return l.1.len().cmp(&r.1.len()).reverse();
+            }
+
+            l.1.cmp(&r.1)
+        });
+
+        for (replace, find) in replacements {
+            message = message.replace(&find, &replace);
+        }
+
+        Ok(message)
+    }
+
+    pub fn send_message(&self, guild: &str, channel: &str, message: &str, process: bool) -> Result<()> {
+        let cache_and_http = self.cache_and_http()?;
+        let cache = cache_and_http.cache.read();
+
+        let guildobj = {
+            if let Ok(id) = guild.parse() {
+                cache.guilds.get(&GuildId(id))
+            } else {
+                let mut v = None;
+                for g in cache.guilds.values() {
+                    if g.read().name == guild {
+                        v = Some(g);
+                        break;
+                    }
+                }
+                v
+            }
+        }
+        .ok_or_else(|| Error::msg("guild not found"))?
+        .read();
+
+        let chanid = {
+            if let Ok(id) = channel.parse() {
+                if guildobj.channels.get(&ChannelId(id)).is_some() {
+                    Some(ChannelId(id))
+                } else {
+                    None
+                }
+            } else {
+                let mut v = None;
+                for (id, c) in &guildobj.channels {
+                    if c.read().name == channel {
+                        v = Some(*id);
+                        break;
+                    }
+                }
+                v
+            }
+        }
+        .ok_or_else(|| Error::msg("channel not found"))?;
+
+        if process {
+            let mut message = message.to_string();
+
+            let mut replacements = get_replacements(guildobj, false);
+
+            replacements.sort_by(|l, r| {
+                if l.0.len() != r.0.len() {
+                    return l.0.len().cmp(&r.0.len()).reverse();
+                }
+
+                l.0.cmp(&r.0)
+            });
+
+            {
+                for (find, replace) in replacements {
+                    let mut need_replace = false;
+
+                    let is_replace_before_ok = |c| {
+                        let cat = unic_ucd::GeneralCategory::of(c);
+
+                        cat.is_separator() || cat.is_punctuation()
+                    };
+
+                    // Check whether we actually need to do anything.
+                    // Most of the time, we don't, so we can avoid allocating.
+                    if message.ends_with(&find) {
+                        need_replace = true;
+                    } else {
+                        for part in message.split(&find).skip(1) {
+                            if part.starts_with(is_replace_before_ok) {
+                                need_replace = true;
+                            }
+                        }
+                    }
+
+                    if need_replace {
+                        let mut parts = message.split(&find);
+                        let mut new_parts = vec![parts.next().unwrap()];
+
+                        for part in parts {
+                            if part.is_empty() || part.starts_with(is_replace_before_ok) {
+                                new_parts.push(&replace);
+                            } else {
+                                new_parts.push(&find);
+                            }
+                            new_parts.push(part);
+                        }
+
+                        message = new_parts.join("");
+                    }
+                }
+            }
+
+            self.say(chanid, &cache_and_http.http, &message)
+        } else {
+            self.say(chanid, &cache_and_http.http, message)
+        }
+    }
+
+    // Sends text to a channel, split into as many messages as it needs, up to the channel's limit.
+    fn say(&self, channel: ChannelId, http: &serenity::http::Http, text: &str) -> Result<()> {
+        let chunks = message::split_discord(text, message::DISCORD_MAX_LEN);
+        let limit = self.config.limit(*channel.as_u64());
+        let key = channel.as_u64().to_string();
+        for chunk in message::limit_lines(chunks, text, limit, &*self.paster, &self.more, &key)? {
+            channel.say(http, chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl Platform for DiscordPlatform {
+    fn kind(&self) -> &'static str {
+        "dis"
+    }
+
+    fn config_id(&self) -> &str {
+        &self.config.id
+    }
+
+    fn connect(self: Arc<Self>, bot: Arc<Rustbot>) -> Result<()> {
+        let mut dis = dis::Client::new(
+            &self.config.token,
+            Handler {
+                platform: self.clone(),
+                bot,
+            },
+        )?;
+
+        *self.cache_and_http.write() = Some(dis.cache_and_http.clone());
+        *self.shard_manager.write() = Some(dis.shard_manager.clone());
+        info!("connect: {}", self.config.id);
+        dis.start()?;
+        Ok(())
+    }
+
+    fn disconnect(&self) -> Result<()> {
+        if let Some(manager) = &*self.shard_manager.read() {
+            manager.lock().shutdown_all();
+        }
+        Ok(())
+    }
+
+    fn send(&self, channel: &str, msg: Message) -> Result<()> {
+        match channel.split_once(':') {
+            // a DM channel, which belongs to no guild, so can only be found by id
+            Some(("none", channel)) => match channel.parse() {
+                Ok(id) => self.say(
+                    ChannelId(id),
+                    &self.cache_and_http()?.http,
+                    &message::format_discord(msg),
+                ),
+                Err(_) => bail!("invalid discord DM channel {:?}", channel),
+            },
+            Some((guild, channel)) => self.send_message(guild, channel, &message::format_discord(msg), true),
+            None => bail!("invalid discord channel {:?}", channel),
+        }
+    }
+
+    fn reply(&self, origin: &Origin, msg: Message) -> Result<()> {
+        let data: &DiscordData = origin.data()?;
+        self.say(data.channel, &data.http, &message::format_discord(msg))
+    }
+
+    fn more(&self, origin: &Origin) -> Result<bool> {
+        let data: &DiscordData = origin.data()?;
+        match self.more.next_page(&data.channel.as_u64().to_string()) {
+            Some(chunks) => {
+                for chunk in chunks {
+                    data.channel.say(&data.http, chunk)?;
+                }
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn perms(&self, bot: &Rustbot, origin: &Origin) -> Result<Perms> {
+        let data: &DiscordData = origin.data()?;
+        Ok(query_perms(
+            bot,
+            "SELECT flags FROM dis_permissions WHERE config_id = $1 AND user_id = $2",
+            &[&self.config.id, &(*data.user.id.as_u64() as i64)],
+        ))
+    }
+
+    fn parse_message(&self, origin: &Origin, msg: &str) -> Result<Option<MessageText<'static>>> {
+        let data: &DiscordData = origin.data()?;
+        let text = match data.guild {
+            Some(g) => self.unprocess_message(&format!("{g}"), msg)?,
+            None => msg.to_string(),
+        };
+        Ok(Some(MessageText::Plain(spans![text])))
+    }
+
+    fn discord_params(&self, origin: &Origin) -> Option<(Option<u64>, u64, u64)> {
+        let data: &DiscordData = origin.data().ok()?;
+        Some((
+            data.guild.map(|g| *g.as_u64()),
+            *data.channel.as_u64(),
+            *data.user.id.as_u64(),
+        ))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+fn get_replacements(guild: impl std::ops::Deref<Target = guild::Guild>, reverse: bool) -> Vec<(String, String)> {
+    let mut replacements = vec![];
+    for (id, m) in &guild.members {
+        replacements.push((format!("@{}", m.user.read().name), format!("<@{id}>")));
+        if reverse {
+            replacements.push((format!("@{}", m.user.read().name), format!("<@!{id}>")));
+        }
+    }
+
+    for (id, r) in &guild.roles {
+        replacements.push((format!("@{}", r.name), format!("<@&{id}>")));
+    }
+
+    for (id, c) in &guild.channels {
+        replacements.push((format!("#{}", c.read().name), format!("<#{id}>")));
+    }
+
+    for (id, e) in &guild.emojis {
+        replacements.push((format!(":{}:", e.name), format!("<:{}:{}>", e.name, id)));
+    }
+
+    replacements
+}
+
+struct Handler {
+    platform: Arc<DiscordPlatform>,
+    bot: Arc<Rustbot>,
+}
+
+impl dis::EventHandler for Handler {
+    fn message(&self, disctx: dis::Context, msg: channel::Message) {
+        let platform = self.platform.clone();
+        let bot = self.bot.clone();
+        rayon::spawn(move || {
+            platform.incoming(&bot, disctx, msg);
+        });
+    }
+}
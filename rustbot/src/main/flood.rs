@@ -0,0 +1,166 @@
+use parking_lot::{Condvar, Mutex};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+use crate::cooldown::TokenBucket;
+use rustbot::prelude::*;
+
+// Replies to commands are sent before anything relayed from elsewhere, so that a busy bridge doesn't
+// hold up the bot's own output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    Reply,
+    Relay,
+}
+
+// Log the queue depth whenever it reaches a multiple of this.
+const DEPTH_LOG_STEP: usize = 10;
+
+// Relayed lines queued beyond this drop the oldest ones, so that a bridge busier than flood control
+// allows doesn't grow the queue without end. Replies are never dropped.
+pub const MAX_RELAY_LINES: usize = 100;
+
+// Outgoing lines for one connection, sent by `run` no faster than its token bucket allows.
+pub struct SendQueue {
+    name: String,
+    state: Mutex<QueueState>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    reply: VecDeque<String>,
+    relay: VecDeque<String>,
+    backed_up: bool, // whether the depth has been logged since the queue was last empty
+    dropped: usize,  // relayed lines dropped since the queue was last empty
+    running: bool,   // whether `start` has started a thread running `run`
+    closed: bool,
+}
+
+impl QueueState {
+    fn depth(&self) -> usize {
+        self.reply.len() + self.relay.len()
+    }
+}
+
+impl SendQueue {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            state: Mutex::new(QueueState::default()),
+            ready: Condvar::new(),
+        }
+    }
+
+    pub fn push(&self, priority: Priority, line: String) {
+        let mut state = self.state.lock();
+        match priority {
+            Priority::Reply => state.reply.push_back(line),
+            Priority::Relay => {
+                if state.relay.len() >= MAX_RELAY_LINES {
+                    if state.dropped == 0 {
+                        warn!(
+                            "{}: over {} relayed lines queued; dropping the oldest",
+                            self.name, MAX_RELAY_LINES
+                        );
+                    }
+                    state.relay.pop_front();
+                    state.dropped += 1;
+                }
+                state.relay.push_back(line);
+            }
+        }
+
+        let depth = state.depth();
+        if depth.is_multiple_of(DEPTH_LOG_STEP) {
+            state.backed_up = true;
+            info!(
+                "{}: {} lines queued ({} replies, {} relayed)",
+                self.name,
+                depth,
+                state.reply.len(),
+                state.relay.len()
+            );
+        }
+        self.ready.notify_one();
+    }
+
+    // The next line to send, if any, replies first.
+    pub fn pop(&self) -> Option<String> {
+        let mut state = self.state.lock();
+        let line = state.reply.pop_front().or_else(|| state.relay.pop_front());
+        if line.is_some() && state.depth() == 0 && state.backed_up {
+            state.backed_up = false;
+            if state.dropped == 0 {
+                info!("{}: send queue drained", self.name);
+            } else {
+                warn!(
+                    "{}: send queue drained; {} relayed lines were dropped",
+                    self.name, state.dropped
+                );
+                state.dropped = 0;
+            }
+        }
+        line
+    }
+
+    // Makes `run` return, dropping anything still queued.
+    pub fn close(&self) {
+        self.state.lock().closed = true;
+        self.ready.notify_all();
+    }
+
+    // Runs `run` in a new thread, unless one has already been started. A connection keeps its queue when it
+    // reconnects, and a second thread would send at twice the rate that `bucket` allows.
+    pub fn start<F: 'static + Fn(&str) -> Result<()> + Send>(
+        self: &Arc<Self>,
+        bucket: TokenBucket,
+        send: F,
+    ) -> Result<()> {
+        {
+            let mut state = self.state.lock();
+            if state.running {
+                return Ok(());
+            }
+            state.running = true;
+        }
+
+        let this = self.clone();
+        let spawned = thread::Builder::new()
+            .name(format!("{} send", self.name))
+            .spawn(move || this.run(bucket, send));
+        if let Err(e) = spawned {
+            self.state.lock().running = false;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    // Sends queued lines until the queue is closed, pacing them with `bucket`.
+    pub fn run(&self, mut bucket: TokenBucket, send: impl Fn(&str) -> Result<()>) {
+        loop {
+            {
+                let mut state = self.state.lock();
+                while state.depth() == 0 && !state.closed {
+                    self.ready.wait(&mut state);
+                }
+                if state.closed {
+                    return;
+                }
+            }
+
+            // Wait for the bucket before choosing the line, so that a reply queued meanwhile goes first
+            while let Err(wait) = bucket.take(Instant::now()) {
+                thread::sleep(wait);
+            }
+
+            if let Some(line) = self.pop() {
+                if let Err(e) = send(&line) {
+                    warn!("{}: failed to send line: {}", self.name, e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,588 @@
+use ::irc::client::ext::ClientExt;
+use ::irc::client::prelude as irc;
+use ::irc::client::prelude::Client;
+use parking_lot::RwLock;
+use std::any::Any;
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::bot::Rustbot;
+use crate::config;
+use crate::cooldown::TokenBucket;
+use crate::flood::{Priority, SendQueue};
+use crate::message::{self, MoreBuffer};
+use crate::paste::Paster;
+use crate::platform::{query_perms, Origin, Platform};
+use rustbot::prelude::*;
+
+// The fewest bytes of text a PRIVMSG line is split into, however little room the target leaves.
+const MIN_PRIVMSG_BUDGET: usize = 64;
+
+pub struct IrcPlatform {
+    config: config::Irc,
+    client: RwLock<Option<Arc<irc::IrcClient>>>,
+    queue: Arc<SendQueue>,
+    // Our own nick!user@host, as seen by others; learnt from messages the server echoes back to us.
+    own_prefix: RwLock<Option<String>>,
+    paster: Arc<dyn Paster>,
+    more: MoreBuffer,
+}
+
+// Origin data for IRC messages; `channel` is None for private messages.
+pub struct IrcData {
+    pub prefix: Option<Prefix>,
+    pub channel: Option<String>,
+}
+
+impl IrcPlatform {
+    pub fn new(config: config::Irc, paster: Arc<dyn Paster>) -> Self {
+        let queue = Arc::new(SendQueue::new(&format!("irc {}", config.id)));
+        Self {
+            config,
+            client: RwLock::new(None),
+            queue,
+            own_prefix: RwLock::new(None),
+            paster,
+            more: MoreBuffer::default(),
+        }
+    }
+
+    fn client(&self) -> Result<Arc<irc::IrcClient>> {
+        match &*self.client.read() {
+            Some(c) => Ok(c.clone()),
+            None => bail!("IRC connection for {} is not up", self.config.id),
+        }
+    }
+
+    // Renders a message for `target`, a channel or nick, in lines of at most `budget` bytes, cut to
+    // the target's line limit.
+    fn format(&self, target: &str, msg: Message, budget: usize) -> Result<Vec<String>> {
+        let (limit, list_width) = self.config.limits(target);
+        let (lines, text) = message::format_irc(msg, budget, list_width);
+        message::limit_lines(lines, &text, limit, &*self.paster, &self.more, target)
+    }
+
+    // Sends lines in reply to `nick`: privately, or addressed to them in `channel`.
+    fn reply_lines(&self, nick: &str, channel: Option<&str>, lines: Vec<String>) -> Result<()> {
+        for line in lines {
+            match channel {
+                None => self.send_privmsg(nick, &line, Priority::Reply)?,
+                Some(ch) => self.send_privmsg(ch, &format!("{nick}: {line}"), Priority::Reply)?,
+            }
+        }
+        Ok(())
+    }
+
+    // The number of bytes of message text that fit in a PRIVMSG to `target`, once the server has added
+    // our prefix: `:nick!user@host PRIVMSG target :text\r\n` is at most 512 bytes. Until we know our
+    // prefix, assume the longest host a server will normally show. A target or prefix too long to leave
+    // room for text still gets MIN_PRIVMSG_BUDGET, for the server to truncate.
+    pub fn privmsg_budget(&self, target: &str) -> usize {
+        let prefix_len = match &*self.own_prefix.read() {
+            Some(prefix) => prefix.len(),
+            None => self.config.nick.len() + 1 + 1 + self.config.user.len() + 1 + 63, // nick!~user@host
+        };
+        (512usize - 2)
+            .saturating_sub(format!(": PRIVMSG {target} :").len() + prefix_len)
+            .max(MIN_PRIVMSG_BUDGET)
+    }
+
+    // Lines are queued and sent as flood control allows; see SendQueue. Messages too long for one line are
+    // split, and each part sent separately.
+    pub fn send_privmsg(&self, channel: &str, message: &str, priority: Priority) -> Result<()> {
+        for line in split_irc_line(message, self.privmsg_budget(channel)) {
+            self.queue.push(priority, format!("PRIVMSG {channel} :{line}"));
+        }
+        Ok(())
+    }
+
+    pub fn send_raw(&self, line: &str) -> Result<()> {
+        let truncated = str_max_bytes(line, 510);
+        if truncated.len() < line.len() {
+            warn!("irc {}: raw line truncated to 510 bytes: {:?}", self.config.id, line);
+        }
+        self.queue.push(Priority::Reply, truncated.to_string());
+        Ok(())
+    }
+
+    // Called on every connect; only the first starts a thread, which sends on whichever client is current.
+    fn start_sender(self: &Arc<Self>) -> Result<()> {
+        let bucket = TokenBucket::new(
+            self.config.flood_burst,
+            Duration::from_millis(self.config.flood_delay_ms),
+            Instant::now(),
+        );
+        let this = Arc::downgrade(self);
+        self.queue.start(bucket, move |line| match this.upgrade() {
+            Some(this) => this.client()?.send(line).map_err(from_irc),
+            None => Ok(()),
+        })
+    }
+
+    fn incoming(self: &Arc<Self>, bot: &Rustbot, bot_name: &str, irc_msg: irc::Message) {
+        if let Some(prefix) = &irc_msg.prefix {
+            if prefix.split('!').next() == Some(bot_name) && prefix.contains('@') {
+                let mut own = self.own_prefix.write();
+                if own.as_deref() != Some(prefix) {
+                    debug!("irc {}: own prefix is {}", self.config.id, prefix);
+                    *own = Some(prefix.clone());
+                }
+            }
+        }
+
+        if let irc::Command::PRIVMSG(channel, message) = irc_msg.command {
+            let mut typ = HandleType::PlainMsg;
+
+            if channel == bot_name {
+                typ |= HandleType::Private;
+            } else {
+                typ |= HandleType::Public;
+            }
+
+            let prefix = irc_parse_prefix(irc_msg.prefix);
+            let channel = if channel == bot_name { None } else { Some(channel) };
+
+            let origin = Origin {
+                user: match &prefix {
+                    Some(prefix) => format!("{prefix}"),
+                    None => "none".to_string(),
+                },
+                user_pretty: match &prefix {
+                    Some(Prefix::User { nick, .. }) => nick.clone(),
+                    Some(Prefix::Server(s)) => s.clone(),
+                    None => "???".to_string(),
+                },
+                channel: channel.clone().unwrap_or_else(|| "query".to_string()),
+                data: Arc::new(IrcData { prefix, channel }),
+            };
+
+            bot.incoming(self.clone(), origin, typ, message.as_str());
+        }
+    }
+}
+
+impl Platform for IrcPlatform {
+    fn kind(&self) -> &'static str {
+        "irc"
+    }
+
+    fn config_id(&self) -> &str {
+        &self.config.id
+    }
+
+    fn describe(&self) -> String {
+        format!("{} ({}:{})", self.config.id, self.config.server, self.config.port)
+    }
+
+    fn connect(self: Arc<Self>, bot: Arc<Rustbot>) -> Result<()> {
+        let c = &self.config;
+        let channels: Vec<String> = bot
+            .sql()
+            .lock()
+            .query("SELECT channel FROM irc_channels WHERE config_id = $1", &[&c.id])?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        let client = Arc::new(
+            irc::IrcClient::from_config(irc::Config {
+                nickname: Some(c.nick.clone()),
+                username: Some(c.user.clone()),
+                realname: Some(c.real.clone()),
+                server: Some(c.server.clone()),
+                port: Some(c.port),
+                use_ssl: Some(c.ssl),
+                channels: Some(channels),
+                password: c.pass.clone(),
+                ..Default::default()
+            })
+            .map_err(from_irc)?,
+        );
+        client.send_cap_req(&[irc::Capability::MultiPrefix]).map_err(from_irc)?;
+        client.identify().map_err(from_irc)?;
+        *self.client.write() = Some(client.clone());
+        self.start_sender()?;
+        info!("connect: {}", self.describe());
+        client
+            .for_each_incoming(|irc_msg| {
+                let bot = bot.clone();
+                let this = self.clone();
+                let client = client.clone();
+                rayon::spawn(move || {
+                    this.incoming(&bot, client.current_nickname(), irc_msg);
+                });
+            })
+            .map_err(from_irc)?;
+        Ok(())
+    }
+
+    fn disconnect(&self) -> Result<()> {
+        self.queue.close();
+        if let Some(client) = &*self.client.read() {
+            client.send_quit("").map_err(from_irc)?;
+        }
+        Ok(())
+    }
+
+    fn send(&self, channel: &str, msg: Message) -> Result<()> {
+        for line in self.format(channel, msg, self.privmsg_budget(channel))? {
+            self.send_privmsg(channel, &line, Priority::Relay)?;
+        }
+        Ok(())
+    }
+
+    fn reply(&self, origin: &Origin, msg: Message) -> Result<()> {
+        let data: &IrcData = origin.data()?;
+        if let Some(Prefix::User { nick, .. }) = &data.prefix {
+            let lines = match &data.channel {
+                None => self.format(nick, msg, self.privmsg_budget(nick))?,
+                Some(ch) => self.format(ch, msg, self.privmsg_budget(ch).saturating_sub(nick.len() + 2))?,
+            };
+            self.reply_lines(nick, data.channel.as_deref(), lines)?;
+        }
+        Ok(())
+    }
+
+    fn more(&self, origin: &Origin) -> Result<bool> {
+        let data: &IrcData = origin.data()?;
+        if let Some(Prefix::User { nick, .. }) = &data.prefix {
+            if let Some(lines) = self.more.next_page(data.channel.as_deref().unwrap_or(nick)) {
+                self.reply_lines(nick, data.channel.as_deref(), lines)?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn perms(&self, bot: &Rustbot, origin: &Origin) -> Result<Perms> {
+        let data: &IrcData = origin.data()?;
+        let (nick, user, host) = match &data.prefix {
+            Some(Prefix::User { nick, user, host }) => (nick, user, host),
+            _ => return Ok(Perms::None),
+        };
+
+        Ok(query_perms(
+            bot,
+            "SELECT flags FROM irc_permissions WHERE config_id = $1 AND nick = $2 AND username = $3 AND host = $4",
+            &[&self.config.id, &nick, &user, &host],
+        ))
+    }
+
+    fn parse_message(&self, _origin: &Origin, msg: &str) -> Result<Option<MessageText<'static>>> {
+        if msg.starts_with(1 as char) && msg.ends_with(1 as char) && msg.len() >= 2 {
+            let ctcp = &msg[1..msg.len() - 1];
+            let parts = ctcp.splitn(2, ' ').collect::<Vec<_>>();
+            match parts[0] {
+                "ACTION" => Ok(Some(MessageText::Action(irc_parse(parts.get(1).unwrap_or(&""))))),
+                _ => {
+                    warn!("unexpected CTCP message {:?}", ctcp);
+                    Ok(None)
+                }
+            }
+        } else {
+            Ok(Some(MessageText::Plain(irc_parse(msg))))
+        }
+    }
+
+    // Inserts a zero-width no-break space after the first character of each word, so that IRC
+    // clients don't highlight the nick.
+    fn quiet_name<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        let mut out = String::with_capacity(name.len() + 6);
+        let mut prev_word = false;
+        for c in name.chars() {
+            out.push(c);
+            if c.is_ascii_alphanumeric() && !prev_word {
+                out.push('\u{feff}');
+            }
+            prev_word = c.is_alphanumeric() || c == '_';
+        }
+        out.into()
+    }
+
+    fn irc_params(&self, origin: &Origin) -> Option<(Option<String>, String)> {
+        let data: &IrcData = origin.data().ok()?;
+        match &data.prefix {
+            Some(Prefix::User { nick, .. }) => Some((data.channel.clone(), nick.clone())),
+            Some(Prefix::Server(s)) => Some((data.channel.clone(), s.clone())),
+            None => None,
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Prefix {
+    Server(String),
+    User { nick: String, user: String, host: String },
+}
+
+impl std::fmt::Display for Prefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            Self::Server(s) => write!(f, "{s}"),
+            Self::User { nick, user, host } => write!(f, "{nick}!{user}@{host}"),
+        }
+    }
+}
+
+fn irc_parse_prefix(prefix: Option<String>) -> Option<Prefix> {
+    match prefix {
+        None => None,
+        Some(s) => {
+            if !s.contains('!') {
+                Some(Prefix::Server(s))
+            } else {
+                let ss = s.clone();
+                let nr: Vec<&str> = ss.splitn(2, '!').collect();
+                if !nr[1].contains('@') {
+                    Some(Prefix::Server(s))
+                } else {
+                    let uh: Vec<&str> = nr[1].splitn(2, '@').collect();
+                    Some(Prefix::User {
+                        nick: nr[0].to_string(),
+                        user: uh[0].to_string(),
+                        host: uh[1].to_string(),
+                    })
+                }
+            }
+        }
+    }
+}
+
+fn str_max_bytes(s: &str, n: usize) -> &str {
+    if s.len() <= n {
+        return s;
+    }
+
+    let (last_char_inside, _) = s.char_indices().take_while(|(i, _)| *i <= n).last().unwrap();
+    &s[..last_char_inside]
+}
+
+const IRC_COLOR: char = 0x03 as char;
+const IRC_RESET: char = 0x0f as char;
+const IRC_BOLD: char = 0x02 as char;
+const IRC_UNDERLINE: char = 0x1f as char;
+const IRC_ITALIC: char = 0x1d as char;
+
+// Parses IRC formatting codes into spans.
+pub fn irc_parse(s: &str) -> Vec<Span<'static>> {
+    let c: Vec<char> = s.chars().collect();
+    let mut i = 0;
+    let mut spans = vec![];
+    let mut current = vec![];
+
+    let mut format = Format::None;
+    let mut fg = Color::None;
+    let mut bg = Color::None;
+
+    while c.len() > i {
+        match c[i] {
+            IRC_COLOR | IRC_RESET | IRC_BOLD | IRC_UNDERLINE | IRC_ITALIC => {
+                if !current.is_empty() {
+                    spans.push(Span::Text {
+                        text: current.iter().collect::<String>().into(),
+                        format,
+                        color: fg,
+                        bg,
+                    });
+                    current.clear();
+                }
+
+                match c[i] {
+                    IRC_COLOR => match parse_color_code(&c[i + 1..]) {
+                        None => {
+                            fg = Color::None;
+                            bg = Color::None;
+                        }
+                        Some((len, f, b)) => {
+                            i += len;
+                            fg = f.into();
+                            bg = b.map_or(Color::None, Into::into);
+                        }
+                    },
+                    IRC_RESET => {
+                        format = Format::None;
+                        fg = Color::None;
+                        bg = Color::None;
+                    }
+                    IRC_BOLD => format ^= Format::Bold,
+                    IRC_UNDERLINE => format ^= Format::Underline,
+                    IRC_ITALIC => format ^= Format::Italic,
+                    _ => unreachable!(),
+                }
+            }
+            t => current.push(t),
+        }
+        i += 1;
+    }
+
+    if !current.is_empty() {
+        spans.push(Span::Text {
+            text: current.iter().collect::<String>().into(),
+            format,
+            color: fg,
+            bg,
+        });
+    }
+
+    spans
+}
+
+// Parses the digits following a colour code, `NN` or `NN,MM` with one or two digits each. Returns the
+// number of characters consumed and the foreground and optional background colour numbers.
+fn parse_color_code(c: &[char]) -> Option<(usize, u8, Option<u8>)> {
+    fn digits(c: &[char]) -> Option<(usize, u8)> {
+        let n = c.iter().take(2).take_while(|c| c.is_ascii_digit()).count();
+        if n == 0 {
+            return None;
+        }
+        Some((n, c[..n].iter().collect::<String>().parse().unwrap()))
+    }
+
+    let (n, fg) = digits(c)?;
+    if c.get(n) == Some(&',') {
+        if let Some((m, bg)) = digits(&c[n + 1..]) {
+            return Some((n + 1 + m, fg, Some(bg)));
+        }
+    }
+    Some((n, fg, None))
+}
+
+fn from_irc(e: ::irc::error::IrcError) -> Error {
+    Error::msg(format!("{e}"))
+}
+
+// The formatting in effect at some point in a line of IRC-formatted text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct IrcFormat {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    fg: Option<u8>,
+    bg: Option<u8>,
+}
+
+impl IrcFormat {
+    // Updates the state for one unit of text from irc_units.
+    fn apply(&mut self, unit: &[char]) {
+        match unit[0] {
+            IRC_COLOR => match parse_color_code(&unit[1..]) {
+                None => {
+                    self.fg = None;
+                    self.bg = None;
+                }
+                Some((_, fg, bg)) => {
+                    self.fg = Some(fg);
+                    self.bg = bg.or(self.bg);
+                }
+            },
+            IRC_RESET => *self = Self::default(),
+            IRC_BOLD => self.bold = !self.bold,
+            IRC_UNDERLINE => self.underline = !self.underline,
+            IRC_ITALIC => self.italic = !self.italic,
+            _ => {}
+        }
+    }
+
+    // The codes that restore this state at the start of a line whose text is `next`.
+    fn codes(&self, next: &str) -> String {
+        let mut s = String::new();
+        if let Some(fg) = self.fg {
+            s += &format!("{IRC_COLOR}{fg:02}");
+            if let Some(bg) = self.bg {
+                s += &format!(",{bg:02}");
+            }
+        }
+        if self.bold {
+            s.push(IRC_BOLD);
+        }
+        if self.italic {
+            s.push(IRC_ITALIC);
+        }
+        if self.underline {
+            s.push(IRC_UNDERLINE);
+        }
+        if s.ends_with(|c: char| c.is_ascii_digit()) && next.starts_with(',') {
+            // Keep the comma from being read as part of the colour code
+            s.push(IRC_BOLD);
+            s.push(IRC_BOLD);
+        }
+        s
+    }
+}
+
+// Splits text into units that a line may be broken between: single characters, and whole colour codes.
+// Returns each unit's range of char indices.
+fn irc_units(c: &[char]) -> Vec<(usize, usize)> {
+    let mut units = vec![];
+    let mut i = 0;
+    while i < c.len() {
+        let len = match c[i] {
+            IRC_COLOR => 1 + parse_color_code(&c[i + 1..]).map_or(0, |(len, _, _)| len),
+            _ => 1,
+        };
+        units.push((i, i + len));
+        i += len;
+    }
+    units
+}
+
+// Splits a line of IRC-formatted text into lines of at most `max_bytes` bytes, breaking at the last space
+// that fits where there is one, and starting each continuation line with the formatting codes that were
+// in effect where the previous line ended.
+pub fn split_irc_line(line: &str, max_bytes: usize) -> Vec<String> {
+    if line.len() <= max_bytes {
+        return vec![line.to_string()];
+    }
+
+    let c: Vec<char> = line.chars().collect();
+    let units = irc_units(&c);
+    let len = |range: &[char]| range.iter().map(|c| c.len_utf8()).sum::<usize>();
+
+    let mut lines = vec![];
+    let mut format = IrcFormat::default();
+    let mut u = 0; // the first unit of the current line
+    while u < units.len() {
+        let start = units[u].0;
+        let rest: String = c[start..].iter().collect();
+        let restore = format.codes(&rest);
+        let budget = max_bytes.saturating_sub(restore.len());
+        if rest.len() <= budget {
+            lines.push(restore + &rest);
+            break;
+        }
+
+        // The units that fit, and the last space among them other than a leading one
+        let mut fit = u;
+        let mut space = None;
+        let mut used = 0;
+        while fit < units.len() {
+            let (s, e) = units[fit];
+            used += len(&c[s..e]);
+            if used > budget {
+                break;
+            }
+            if c[s] == ' ' && fit > u {
+                space = Some(fit);
+            }
+            fit += 1;
+        }
+        // Always make progress, even if one unit doesn't fit
+        let (end, next) = match space {
+            Some(space) => (space, space + 1),
+            None => (fit.max(u + 1), fit.max(u + 1)),
+        };
+
+        let end_char = units.get(end).map_or(c.len(), |&(s, _)| s);
+        lines.push(restore + &c[start..end_char].iter().collect::<String>());
+        for &(s, e) in &units[u..end] {
+            format.apply(&c[s..e]);
+        }
+        u = next;
+    }
+    lines
+}
@@ -1,15 +1,19 @@
-use format;
+use crate::config;
+use crate::irc::{irc_parse, IrcPlatform};
+use crate::paste::ScriptPaster;
+use crate::platform::{parse_channel_string, Platform};
 use rustbot::prelude::*;
+use std::sync::Arc;
 
 #[test]
 fn test_irc_parse() {
     // empty
-    assert_eq!(format::irc_parse(""), vec![]);
+    assert_eq!(irc_parse(""), vec![]);
 
     // basic text
     assert_eq!(
-        format::irc_parse("foo"),
-        vec![Span {
+        irc_parse("foo"),
+        vec![Span::Text {
             text: "foo".into(),
             format: Format::None,
             color: Color::None,
@@ -19,8 +23,8 @@ fn test_irc_parse() {
 
     // colored text
     assert_eq!(
-        format::irc_parse("\x032,1foo"),
-        vec![Span {
+        irc_parse("\x032,1foo"),
+        vec![Span::Text {
             text: "foo".into(),
             format: Format::None,
             color: Color::Blue,
@@ -28,8 +32,8 @@ fn test_irc_parse() {
         }]
     );
     assert_eq!(
-        format::irc_parse("\x0302,01foo"),
-        vec![Span {
+        irc_parse("\x0302,01foo"),
+        vec![Span::Text {
             text: "foo".into(),
             format: Format::None,
             color: Color::Blue,
@@ -37,15 +41,15 @@ fn test_irc_parse() {
         }]
     );
     assert_eq!(
-        format::irc_parse("\x0302,01foo\x03bar"),
+        irc_parse("\x0302,01foo\x03bar"),
         vec![
-            Span {
+            Span::Text {
                 text: "foo".into(),
                 format: Format::None,
                 color: Color::Blue,
                 bg: Color::Black
             },
-            Span {
+            Span::Text {
                 text: "bar".into(),
                 format: Format::None,
                 color: Color::None,
@@ -54,15 +58,15 @@ fn test_irc_parse() {
         ]
     );
     assert_eq!(
-        format::irc_parse("\x0302,01foo\x03,bar"),
+        irc_parse("\x0302,01foo\x03,bar"),
         vec![
-            Span {
+            Span::Text {
                 text: "foo".into(),
                 format: Format::None,
                 color: Color::Blue,
                 bg: Color::Black
             },
-            Span {
+            Span::Text {
                 text: ",bar".into(),
                 format: Format::None,
                 color: Color::None,
@@ -71,15 +75,15 @@ fn test_irc_parse() {
         ]
     );
     assert_eq!(
-        format::irc_parse("\x0302,01foo\x0301bar"),
+        irc_parse("\x0302,01foo\x0301bar"),
         vec![
-            Span {
+            Span::Text {
                 text: "foo".into(),
                 format: Format::None,
                 color: Color::Blue,
                 bg: Color::Black
             },
-            Span {
+            Span::Text {
                 text: "bar".into(),
                 format: Format::None,
                 color: Color::Black,
@@ -88,15 +92,15 @@ fn test_irc_parse() {
         ]
     );
     assert_eq!(
-        format::irc_parse("\x0302,01foo\x03,02bar"),
+        irc_parse("\x0302,01foo\x03,02bar"),
         vec![
-            Span {
+            Span::Text {
                 text: "foo".into(),
                 format: Format::None,
                 color: Color::Blue,
                 bg: Color::Black
             },
-            Span {
+            Span::Text {
                 text: ",02bar".into(),
                 format: Format::None,
                 color: Color::None,
@@ -107,21 +111,21 @@ fn test_irc_parse() {
 
     // bold text
     assert_eq!(
-        format::irc_parse("\x02foo\x02bar\x02baz"),
+        irc_parse("\x02foo\x02bar\x02baz"),
         vec![
-            Span {
+            Span::Text {
                 text: "foo".into(),
                 format: Format::Bold,
                 color: Color::None,
                 bg: Color::None
             },
-            Span {
+            Span::Text {
                 text: "bar".into(),
                 format: Format::None,
                 color: Color::None,
                 bg: Color::None
             },
-            Span {
+            Span::Text {
                 text: "baz".into(),
                 format: Format::Bold,
                 color: Color::None,
@@ -132,21 +136,21 @@ fn test_irc_parse() {
 
     // italic text
     assert_eq!(
-        format::irc_parse("\x1dfoo\x1dbar\x1dbaz"),
+        irc_parse("\x1dfoo\x1dbar\x1dbaz"),
         vec![
-            Span {
+            Span::Text {
                 text: "foo".into(),
                 format: Format::Italic,
                 color: Color::None,
                 bg: Color::None
             },
-            Span {
+            Span::Text {
                 text: "bar".into(),
                 format: Format::None,
                 color: Color::None,
                 bg: Color::None
             },
-            Span {
+            Span::Text {
                 text: "baz".into(),
                 format: Format::Italic,
                 color: Color::None,
@@ -157,21 +161,21 @@ fn test_irc_parse() {
 
     // underlined text
     assert_eq!(
-        format::irc_parse("\x1ffoo\x1fbar\x1fbaz"),
+        irc_parse("\x1ffoo\x1fbar\x1fbaz"),
         vec![
-            Span {
+            Span::Text {
                 text: "foo".into(),
                 format: Format::Underline,
                 color: Color::None,
                 bg: Color::None
             },
-            Span {
+            Span::Text {
                 text: "bar".into(),
                 format: Format::None,
                 color: Color::None,
                 bg: Color::None
             },
-            Span {
+            Span::Text {
                 text: "baz".into(),
                 format: Format::Underline,
                 color: Color::None,
@@ -182,21 +186,21 @@ fn test_irc_parse() {
 
     // multiple formats, reset
     assert_eq!(
-        format::irc_parse("\x02\x1d\x1ffoo\x034,14bar\x0fbaz"),
+        irc_parse("\x02\x1d\x1ffoo\x034,14bar\x0fbaz"),
         vec![
-            Span {
+            Span::Text {
                 text: "foo".into(),
                 format: Format::Bold | Format::Underline | Format::Italic,
                 color: Color::None,
                 bg: Color::None
             },
-            Span {
+            Span::Text {
                 text: "bar".into(),
                 format: Format::Bold | Format::Underline | Format::Italic,
                 color: Color::BrightRed,
                 bg: Color::BrightBlack,
             },
-            Span {
+            Span::Text {
                 text: "baz".into(),
                 format: Format::None,
                 color: Color::None,
@@ -207,8 +211,8 @@ fn test_irc_parse() {
 
     // UTF-8
     assert_eq!(
-        format::irc_parse("ΨΩΔ"),
-        vec![Span {
+        irc_parse("ΨΩΔ"),
+        vec![Span::Text {
             text: "ΨΩΔ".into(),
             format: Format::None,
             color: Color::None,
@@ -216,3 +220,98 @@ fn test_irc_parse() {
         }]
     );
 }
+
+#[test]
+fn test_irc_quiet_name() {
+    let p = IrcPlatform::new(
+        config::Irc {
+            id: "test".to_string(),
+            nick: "bot".to_string(),
+            user: "bot".to_string(),
+            real: "bot".to_string(),
+            server: "irc.invalid".to_string(),
+            port: 6667,
+            ssl: false,
+            pass: None,
+            flood_burst: 4,
+            flood_delay_ms: 2000,
+            max_lines: 3,
+            list_width: 300,
+            overflow: config::Overflow::Paste,
+            channels: Default::default(),
+        },
+        Arc::new(ScriptPaster::new("./external/paste")),
+    );
+
+    assert_eq!(p.quiet_name("nick"), "n\u{feff}ick");
+    assert_eq!(p.quiet_name("two words"), "t\u{feff}wo w\u{feff}ords");
+    assert_eq!(p.quiet_name("[m]nick"), "[m\u{feff}]n\u{feff}ick");
+    assert_eq!(p.quiet_name("Ψnick"), "Ψnick");
+}
+
+#[test]
+fn test_privmsg_budget() {
+    let p = IrcPlatform::new(
+        config::Irc {
+            id: "test".to_string(),
+            nick: "bot".to_string(),
+            user: "bot".to_string(),
+            real: "bot".to_string(),
+            server: "irc.invalid".to_string(),
+            port: 6667,
+            ssl: false,
+            pass: None,
+            flood_burst: 4,
+            flood_delay_ms: 2000,
+            max_lines: 3,
+            list_width: 300,
+            overflow: config::Overflow::Paste,
+            channels: Default::default(),
+        },
+        Arc::new(ScriptPaster::new("./external/paste")),
+    );
+
+    // `:bot!~bot@<63-byte host> PRIVMSG #chan :` and \r\n
+    assert_eq!(p.privmsg_budget("#chan"), 512 - 2 - 72 - 17);
+    // A target too long to leave room for any text doesn't underflow
+    assert_eq!(p.privmsg_budget(&"#".repeat(600)), 64);
+}
+
+#[test]
+fn test_parse_channel_string() {
+    assert_eq!(parse_channel_string("irc:#chan").unwrap(), ("irc", "#chan"));
+    assert_eq!(parse_channel_string("dis:123:456").unwrap(), ("dis", "123:456"));
+    assert!(parse_channel_string("irc:").is_err());
+    assert!(parse_channel_string("nochannel").is_err());
+}
+
+#[test]
+fn test_split_irc_line() {
+    use crate::irc::split_irc_line;
+
+    assert_eq!(split_irc_line("short", 10), vec!["short"]);
+    assert_eq!(split_irc_line("", 10), vec![""]);
+
+    // Word-wrapped where possible, hard-wrapped on character boundaries where not
+    assert_eq!(split_irc_line("aaa bbb ccc", 8), vec!["aaa bbb", "ccc"]);
+    assert_eq!(split_irc_line("aaaaaaaaaa", 4), vec!["aaaa", "aaaa", "aa"]);
+    assert_eq!(split_irc_line("ééééé", 4), vec!["éé", "éé", "é"]);
+
+    // Formatting is carried over to the next line
+    assert_eq!(
+        split_irc_line("\x02bold text\x02 plain", 10),
+        vec!["\x02bold", "\x02text\x02", "plain"]
+    );
+    assert_eq!(
+        split_irc_line("\x034,5red words here", 12),
+        vec!["\x034,5red", "\x0304,05words", "\x0304,05here"]
+    );
+    assert_eq!(
+        split_irc_line("\x0312abcdefgh", 6),
+        vec!["\x0312abc", "\x0312def", "\x0312gh"]
+    );
+    assert_eq!(split_irc_line("\x034xxx ,y", 7), vec!["\x034xxx", "\x0304\x02\x02,y"]);
+
+    // A colour code is never split
+    assert_eq!(split_irc_line("ab\x0312cd", 4), vec!["ab", "\x0312c", "\x0312d"]);
+}
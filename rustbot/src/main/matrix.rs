@@ -0,0 +1,355 @@
+// A minimal Matrix client-server API client: just enough to log in, long-poll /sync for room
+// messages and invites, join rooms, and send messages.
+
+use parking_lot::RwLock;
+use reqwest::blocking::Client as HttpClient;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::json;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::bot::Rustbot;
+use crate::config;
+use crate::message;
+use crate::platform::{query_perms, Origin, Platform};
+use rustbot::prelude::*;
+
+pub struct Client {
+    homeserver: Url,
+    user_id: String,
+    access_token: String,
+    http: HttpClient,
+
+    txn_prefix: u128,
+    txn: AtomicU64,
+}
+
+#[derive(Deserialize)]
+pub struct SyncResponse {
+    pub next_batch: String,
+    #[serde(default)]
+    pub rooms: Rooms,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Rooms {
+    #[serde(default)]
+    pub join: BTreeMap<String, JoinedRoom>,
+    #[serde(default)]
+    pub invite: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct JoinedRoom {
+    #[serde(default)]
+    pub summary: Summary,
+    #[serde(default)]
+    pub timeline: Timeline,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Summary {
+    #[serde(rename = "m.joined_member_count")]
+    pub joined_member_count: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Timeline {
+    #[serde(default)]
+    pub events: Vec<Event>,
+}
+
+#[derive(Deserialize)]
+pub struct Event {
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub sender: String,
+    #[serde(default)]
+    pub content: serde_json::Value,
+}
+
+impl Event {
+    // The text of a message event that the bot should react to, if this is one. Notices are
+    // skipped, as those are what bots (including this one) send.
+    pub fn message_body(&self) -> Option<&str> {
+        if self.typ != "m.room.message" {
+            return None;
+        }
+        match self.content.get("msgtype")?.as_str()? {
+            "m.text" | "m.emote" => self.content.get("body")?.as_str(),
+            _ => None,
+        }
+    }
+}
+
+// "@someone:example.org" => "someone"
+pub fn localpart(user_id: &str) -> &str {
+    let s = user_id.strip_prefix('@').unwrap_or(user_id);
+    s.split(':').next().unwrap_or(s)
+}
+
+#[derive(Deserialize)]
+struct WhoamiResponse {
+    user_id: String,
+}
+
+// Whether `user_id` is the configured `user`, which may be a full user id or just its localpart.
+pub fn is_configured_user(user: &str, user_id: &str) -> bool {
+    if user.starts_with('@') {
+        user == user_id
+    } else {
+        localpart(user_id) == user
+    }
+}
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    user_id: String,
+    access_token: String,
+}
+
+impl Client {
+    pub fn connect(c: &config::Matrix) -> Result<Self> {
+        let homeserver = Url::parse(&c.homeserver)?;
+        let http = HttpClient::builder().timeout(Duration::from_secs(90)).build()?;
+
+        let (user_id, access_token) = match (&c.access_token, &c.password) {
+            (Some(token), _) => {
+                let resp = http
+                    .get(endpoint(&homeserver, &["account", "whoami"])?)
+                    .bearer_auth(token)
+                    .send()?;
+                let resp: WhoamiResponse = parse_response(resp)?;
+                (resp.user_id, token.clone())
+            }
+            (None, Some(password)) => {
+                let resp = http
+                    .post(endpoint(&homeserver, &["login"])?)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(
+                        json!({
+                            "type": "m.login.password",
+                            "identifier": {"type": "m.id.user", "user": c.user},
+                            "password": password,
+                            "initial_device_display_name": "rustbot",
+                        })
+                        .to_string(),
+                    )
+                    .send()?;
+                let resp: LoginResponse = parse_response(resp)?;
+                (resp.user_id, resp.access_token)
+            }
+            (None, None) => bail!("matrix config {:?} needs either access_token or password", c.id),
+        };
+        if !is_configured_user(&c.user, &user_id) {
+            bail!(
+                "matrix config {:?} is for {:?}, but its credentials are for {:?}",
+                c.id,
+                c.user,
+                user_id
+            );
+        }
+
+        Ok(Self {
+            homeserver,
+            user_id,
+            access_token,
+            http,
+            txn_prefix: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+            txn: AtomicU64::new(0),
+        })
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    fn url(&self, segments: &[&str]) -> Result<Url> {
+        endpoint(&self.homeserver, segments)
+    }
+
+    // Without `since`, only fetches the current state and no timeline events, so that messages
+    // sent while the bot was away are not replayed.
+    pub fn sync(&self, since: Option<&str>) -> Result<SyncResponse> {
+        let mut req = self.http.get(self.url(&["sync"])?).bearer_auth(&self.access_token);
+        req = match since {
+            Some(since) => req.query(&[("since", since), ("timeout", "30000")]),
+            None => req.query(&[("filter", r#"{"room":{"timeline":{"limit":0}}}"#)]),
+        };
+        parse_response(req.send()?)
+    }
+
+    pub fn join(&self, room: &str) -> Result<()> {
+        let resp = self
+            .http
+            .post(self.url(&["join", room])?)
+            .bearer_auth(&self.access_token)
+            .header(CONTENT_TYPE, "application/json")
+            .body("{}")
+            .send()?;
+        parse_response::<serde_json::Value>(resp).map(|_| ())
+    }
+
+    pub fn send(&self, room: &str, body: &str, html: Option<&str>) -> Result<()> {
+        let txn = format!("{}.{}", self.txn_prefix, self.txn.fetch_add(1, Ordering::Relaxed));
+        let content = match html {
+            Some(html) => json!({
+                "msgtype": "m.notice",
+                "body": body,
+                "format": "org.matrix.custom.html",
+                "formatted_body": html,
+            }),
+            None => json!({"msgtype": "m.notice", "body": body}),
+        };
+
+        let resp = self
+            .http
+            .put(self.url(&["rooms", room, "send", "m.room.message", &txn])?)
+            .bearer_auth(&self.access_token)
+            .header(CONTENT_TYPE, "application/json")
+            .body(content.to_string())
+            .send()?;
+        parse_response::<serde_json::Value>(resp).map(|_| ())
+    }
+}
+
+fn endpoint(homeserver: &Url, segments: &[&str]) -> Result<Url> {
+    let mut url = homeserver.clone();
+    url.path_segments_mut()
+        .map_err(|()| Error::msg("homeserver URL cannot be a base"))?
+        .pop_if_empty()
+        .extend(["_matrix", "client", "v3"].iter().chain(segments));
+    Ok(url)
+}
+
+fn parse_response<T: serde::de::DeserializeOwned>(resp: reqwest::blocking::Response) -> Result<T> {
+    let status = resp.status();
+    let text = resp.text()?;
+    if !status.is_success() {
+        bail!("matrix request failed with {}: {}", status, text);
+    }
+    serde_json::from_str(&text).with_context(|| format!("failed to unmarshal matrix response: {text}"))
+}
+
+pub struct MatrixPlatform {
+    config: config::Matrix,
+    client: RwLock<Option<Arc<Client>>>,
+    stopped: AtomicBool, // set by disconnect; checked between syncs
+}
+
+impl MatrixPlatform {
+    pub fn new(config: config::Matrix) -> Self {
+        Self {
+            config,
+            client: RwLock::new(None),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    fn client(&self) -> Result<Arc<Client>> {
+        match &*self.client.read() {
+            Some(c) => Ok(c.clone()),
+            None => bail!("no matrix client found for config {:?}", self.config.id),
+        }
+    }
+
+    fn incoming(self: &Arc<Self>, bot: &Rustbot, client: &Client, room: String, direct: bool, event: Event) {
+        if event.sender == client.user_id() {
+            return;
+        }
+        let message = match event.message_body() {
+            Some(m) => m.to_string(),
+            None => return,
+        };
+
+        let typ = if direct {
+            HandleType::PlainMsg | HandleType::Private
+        } else {
+            HandleType::PlainMsg | HandleType::Public
+        };
+
+        let origin = Origin {
+            user_pretty: localpart(&event.sender).to_string(),
+            user: event.sender,
+            channel: room,
+            data: Arc::new(()),
+        };
+        bot.incoming(self.clone(), origin, typ, &message);
+    }
+}
+
+impl Platform for MatrixPlatform {
+    fn kind(&self) -> &'static str {
+        "mx"
+    }
+
+    fn config_id(&self) -> &str {
+        &self.config.id
+    }
+
+    fn connect(self: Arc<Self>, bot: Arc<Rustbot>) -> Result<()> {
+        let c = &self.config;
+        let client = Arc::new(Client::connect(c)?);
+        let mut since = client.sync(None)?.next_batch;
+        *self.client.write() = Some(client.clone());
+        info!("connect: {} ({} on {})", c.id, client.user_id(), c.homeserver);
+
+        let mut member_counts = BTreeMap::new();
+        while !self.stopped.load(Ordering::Relaxed) {
+            let resp = client.sync(Some(&since))?;
+            since = resp.next_batch;
+
+            for room in resp.rooms.invite.keys() {
+                info!("matrix {}: joining {} on invite", c.id, room);
+                if let Err(e) = client.join(room) {
+                    warn!("matrix {}: failed to join {}: {}", c.id, room, e);
+                }
+            }
+
+            for (room, data) in resp.rooms.join {
+                if let Some(n) = data.summary.joined_member_count {
+                    member_counts.insert(room.clone(), n);
+                }
+                let direct = member_counts.get(&room) == Some(&2);
+
+                for event in data.timeline.events {
+                    let bot = bot.clone();
+                    let this = self.clone();
+                    let client = client.clone();
+                    let room = room.clone();
+                    rayon::spawn(move || {
+                        this.incoming(&bot, &client, room, direct, event);
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn disconnect(&self) -> Result<()> {
+        self.stopped.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn send(&self, room: &str, msg: Message) -> Result<()> {
+        let (body, html) = message::format_matrix(msg);
+        self.client()?.send(room, &body, html.as_deref())
+    }
+
+    fn perms(&self, bot: &Rustbot, origin: &Origin) -> Result<Perms> {
+        Ok(query_perms(
+            bot,
+            "SELECT flags FROM mx_permissions WHERE config_id = $1 AND user_id = $2",
+            &[&self.config.id, &origin.user],
+        ))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
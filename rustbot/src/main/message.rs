@@ -1,39 +1,79 @@
-use rustbot::prelude::*;
+use parking_lot::Mutex;
 use std::borrow::Cow;
-use std::io::{Read, Write};
-use std::process::{Command, Stdio};
+use std::collections::{BTreeMap, VecDeque};
 
-fn paste(text: &str) -> Result<String> {
-    let mut cmd = Command::new("./external/paste")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?;
+use crate::config::Overflow;
+use crate::irc;
+use crate::paste::Paster;
+use rustbot::prelude::*;
 
-    {
-        let stdin = cmd.stdin.take();
-        write!(stdin.unwrap(), "{text}")?;
-    }
+// How much of a long message to send: at most `max` lines (or messages), the last of them pointing to
+// the rest as `overflow` says.
+#[derive(Clone, Copy, Debug)]
+pub struct Limit {
+    pub max: usize,
+    pub overflow: Overflow,
+}
 
-    cmd.wait().expect("failed to wait for paste");
+// The rest of long messages cut short by Overflow::More, by channel, with the page size for each.
+#[derive(Default)]
+pub struct MoreBuffer {
+    pending: Mutex<BTreeMap<String, (VecDeque<String>, usize)>>,
+}
 
-    let url = {
-        let stdout = cmd.stdout.take();
-        let mut url = String::new();
-        stdout.unwrap().read_to_string(&mut url)?;
-        url
-    };
+impl MoreBuffer {
+    // The next page of what is held back for `key`, followed by a note of how much is left, if any.
+    pub fn next_page(&self, key: &str) -> Option<Vec<String>> {
+        let mut pending = self.pending.lock();
+        let (lines, page) = pending.get_mut(key)?;
+        let n = (*page).min(lines.len());
+        let mut out: Vec<String> = lines.drain(..n).collect();
+        if lines.is_empty() {
+            pending.remove(key);
+        } else {
+            out.push(more_note(lines.len()));
+        }
+        Some(out)
+    }
+}
 
-    Ok(format!("[full message: {}]", url.trim()))
+fn more_note(n: usize) -> String {
+    format!("[{n} more; use the more command to continue]")
 }
 
-fn paste_max_lines(input: &str, max_lines: usize) -> Result<(Vec<String>, Option<String>)> {
-    let lines: Vec<String> = input.split('\n').map(std::string::ToString::to_string).collect();
-    if lines.len() > max_lines {
-        let v = lines[0..max_lines - 1].to_vec();
-        Ok((v, Some(paste(input)?)))
-    } else {
-        Ok((lines, None))
+// Applies `limit` to the lines a message was rendered as. If there are too many, the first
+// `limit.max - 1` are kept and followed by either a link to a paste of `text`, or a note that the
+// rest is in `more` under `key` (also used for Overflow::Paste when `paster` can't make public links);
+// that replaces anything else held back for `key`.
+pub fn limit_lines(
+    mut lines: Vec<String>,
+    text: &str,
+    limit: Limit,
+    paster: &dyn Paster,
+    more: &MoreBuffer,
+    key: &str,
+) -> Result<Vec<String>> {
+    let max = limit.max.max(1);
+    if lines.len() <= max {
+        return Ok(lines);
+    }
+
+    let rest = lines.split_off(max - 1);
+    // A link that only works on the bot's own host is no use to anyone reading it
+    let overflow = match limit.overflow {
+        Overflow::Paste if !paster.public() => Overflow::More,
+        overflow => overflow,
+    };
+    match overflow {
+        Overflow::Paste => lines.push(format!("[full message: {}]", paster.paste(text)?)),
+        Overflow::More => {
+            lines.push(more_note(rest.len()));
+            more.pending
+                .lock()
+                .insert(key.to_string(), (rest.into(), (max - 1).max(1)));
+        }
     }
+    Ok(lines)
 }
 
 fn render_irc(spans: &[Span]) -> String {
@@ -110,7 +150,15 @@ fn render_irc(spans: &[Span]) -> String {
     st
 }
 
-pub fn format_irc(m: Message) -> Result<Vec<String>> {
+// Renders the message as lines of at most `max_bytes` bytes each, wrapping lists at `list_width`.
+// Also returns the text to paste if there are too many lines for limit_lines.
+pub fn format_irc(m: Message, max_bytes: usize, list_width: usize) -> (Vec<String>, String) {
+    let split = |text: &str, max_bytes: usize| -> Vec<String> {
+        text.split('\n')
+            .flat_map(|line| irc::split_irc_line(line, max_bytes))
+            .collect()
+    };
+
     let msg = match m {
         Message::Simple(s) | Message::Code(s) => s,
         Message::Spans(s) => render_irc(&s),
@@ -118,17 +166,10 @@ pub fn format_irc(m: Message) -> Result<Vec<String>> {
             let p = render_irc(&p);
             let s = render_irc(&s);
 
-            let (vec, link) = paste_max_lines(&s, 3)?;
-
-            let mut vec = vec.iter().map(|line| p.clone() + line).collect::<Vec<_>>();
-            if let Some(link) = link {
-                vec.push(link);
-            }
-            return Ok(vec);
+            let lines = split(&s, max_bytes.saturating_sub(p.len()));
+            return (lines.iter().map(|line| p.clone() + line).collect(), s);
         }
         Message::List { prefix, sep, items } => {
-            let max_line_len = 300;
-
             let mut lines = vec![];
             let mut items: &[_] = &items;
 
@@ -137,7 +178,7 @@ pub fn format_irc(m: Message) -> Result<Vec<String>> {
                 let mut current_line: Vec<&str> = vec![&prefix, &items[0]];
                 items = &items[1..];
 
-                while !items.is_empty() && current_length + sep.len() + items[0].len() <= max_line_len {
+                while !items.is_empty() && current_length + sep.len() + items[0].len() <= list_width {
                     current_line.push(&sep);
                     current_line.push(&items[0]);
                     current_length += sep.len() + items[0].len();
@@ -151,13 +192,7 @@ pub fn format_irc(m: Message) -> Result<Vec<String>> {
         }
     };
 
-    match paste_max_lines(&msg, 3)? {
-        (vec, None) => Ok(vec),
-        (mut vec, Some(link)) => {
-            vec.push(link);
-            Ok(vec)
-        }
-    }
+    (split(&msg, max_bytes), msg)
 }
 
 fn render_dis<'a>(s: &'a Span) -> Cow<'a, str> {
@@ -192,40 +227,336 @@ fn render_dis_spans(s: &[Span]) -> String {
     s.iter().map(render_dis).collect::<Vec<Cow<str>>>().join("")
 }
 
-pub fn format_discord(m: Message) -> Result<String> {
-    let (msg, code) = match m {
-        Message::Simple(s) => (s, false),
-        Message::Code(s) => (s, true),
-        Message::Spans(s) => (render_dis_spans(&s), false),
+// Discord's limit on the length of a message, counted in UTF-16 code units.
+pub const DISCORD_MAX_LEN: usize = 2000;
+
+// Renders a message as Discord markdown, ready to be split by split_discord.
+pub fn format_discord(m: Message) -> String {
+    match m {
+        Message::Simple(s) => s,
+        Message::Code(s) if !s.contains('\n') => format!("`{s}`"),
+        Message::Code(s) => format!("```{s}```"),
+        Message::Spans(s) => render_dis_spans(&s),
         Message::Prefixed(p, s) => {
             let p = render_dis_spans(&p);
             let s = render_dis_spans(&s);
+            s.split('\n')
+                .map(|line| p.clone() + line)
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        Message::List { prefix, sep, items } => format!("{}{}", prefix, items.join(&sep)),
+    }
+}
 
-            let (res, url) = paste_max_lines(&s, 11)?;
-            let mut res = res.iter().map(|line| p.clone() + line).collect::<Vec<_>>();
-            if let Some(u) = url {
-                res.push(u);
-            }
-            return Ok(res.join("\n"));
+// The markdown state at some point in a Discord message: whether we're in a ``` code block, and
+// otherwise which of ** and __ are open, innermost last.
+#[derive(Clone, Default)]
+struct DiscordFormat {
+    code: bool,
+    open: Vec<&'static str>,
+}
+
+impl DiscordFormat {
+    fn apply(&mut self, unit: &str) {
+        match unit {
+            "```" => self.code = !self.code,
+            "**" | "__" if !self.code => match self.open.iter().rposition(|m| *m == unit) {
+                Some(i) => {
+                    self.open.remove(i);
+                }
+                None => self.open.push(if unit == "**" { "**" } else { "__" }),
+            },
+            _ => {}
         }
-        Message::List { prefix, sep, items } => (format!("{}{}", prefix, items.join(&sep)), false),
-    };
+    }
 
-    if code && !msg.contains('\n') {
-        Ok(format!("`{msg}`"))
-    } else {
-        let (mut res, url) = paste_max_lines(&msg, 11)?;
-        if code {
-            if let Some(u) = url {
-                Ok(format!("```{}```\n{}", res.join("\n"), u))
-            } else {
-                Ok(format!("```{}```", res.join("\n")))
+    // What ends a chunk in this state, so that its formatting is closed
+    fn close(&self) -> String {
+        if self.code {
+            "```".to_string()
+        } else {
+            self.open.iter().rev().copied().collect()
+        }
+    }
+
+    // What starts the next chunk, to reopen the formatting that close() ended
+    fn reopen(&self) -> String {
+        if self.code {
+            "```".to_string()
+        } else {
+            self.open.concat()
+        }
+    }
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.chars().map(char::len_utf16).sum()
+}
+
+// Splits text into chunks of at most `limit` UTF-16 code units, breaking at the last newline that
+// fits, or else the last space, or else between any two characters. Code blocks and ** and __
+// formatting that are open at a break are closed at the end of the chunk and reopened in the next.
+pub fn split_discord(text: &str, limit: usize) -> Vec<String> {
+    if utf16_len(text) <= limit {
+        return vec![text.to_string()];
+    }
+
+    let mut units = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let len = ["```", "**", "__"]
+            .iter()
+            .find(|m| rest.starts_with(*m))
+            .map_or(c.len_utf8(), |m| m.len());
+        units.push(&rest[..len]);
+        rest = &rest[len..];
+    }
+
+    let mut chunks = vec![];
+    let mut format = DiscordFormat::default();
+    let mut i = 0;
+    while i < units.len() {
+        let reopen = format.reopen();
+
+        // The furthest breaks that fit, as (end of chunk, start of next chunk)
+        let (mut newline, mut space, mut any) = (None, None, None);
+        let mut end = format.clone();
+        let mut used = utf16_len(&reopen);
+        let mut j = i;
+        while j < units.len() {
+            if j > i {
+                if used + utf16_len(&end.close()) > limit {
+                    break;
+                }
+                any = Some((j, j));
+                match units[j] {
+                    "\n" => newline = Some((j, j + 1)),
+                    " " => space = Some((j, j + 1)),
+                    _ => {}
+                }
             }
+            used += utf16_len(units[j]);
+            end.apply(units[j]);
+            j += 1;
+        }
+
+        let (stop, next) = if j == units.len() && used + utf16_len(&end.close()) <= limit {
+            (j, j)
         } else {
-            if let Some(u) = url {
-                res.push(u);
+            newline.or(space).or(any).unwrap_or((i + 1, i + 1))
+        };
+
+        let mut chunk = reopen;
+        for unit in &units[i..stop] {
+            chunk.push_str(unit);
+            format.apply(unit);
+        }
+        chunk += &format.close();
+        chunks.push(chunk);
+        i = next;
+    }
+    chunks
+}
+
+fn ansi_color(c: Color) -> Option<u8> {
+    Some(match c {
+        Color::None => return None,
+        Color::BrightWhite => 97,
+        Color::Black => 30,
+        Color::Blue => 34,
+        Color::Green => 32,
+        Color::BrightRed => 91,
+        Color::Red => 31,
+        Color::Magenta => 35,
+        Color::Yellow => 33,
+        Color::BrightYellow => 93,
+        Color::BrightGreen => 92,
+        Color::Cyan => 36,
+        Color::BrightCyan => 96,
+        Color::BrightBlue => 94,
+        Color::BrightMagenta => 95,
+        Color::BrightBlack => 90,
+        Color::White => 37,
+    })
+}
+
+fn render_ansi(spans: &[Span]) -> String {
+    let mut st = String::new();
+
+    for sp in spans {
+        match sp {
+            Span::Text {
+                text,
+                format,
+                color,
+                bg,
+            } => {
+                let mut codes = vec![];
+                if format.contains(Format::Bold) {
+                    codes.push(1);
+                }
+                if format.contains(Format::Italic) {
+                    codes.push(3);
+                }
+                if format.contains(Format::Underline) {
+                    codes.push(4);
+                }
+                if let Some(c) = ansi_color(*color) {
+                    codes.push(c);
+                }
+                if let Some(c) = ansi_color(*bg) {
+                    codes.push(c + 10);
+                }
+
+                if codes.is_empty() {
+                    st.push_str(text);
+                } else {
+                    let codes = codes.iter().map(u8::to_string).collect::<Vec<_>>().join(";");
+                    st.push_str(&format!("\x1b[{codes}m{text}\x1b[0m"));
+                }
+            }
+            Span::DiscordEmoji(name, _) => {
+                st.push(':');
+                st.push_str(name);
+                st.push(':');
             }
-            Ok(res.join("\n"))
         }
     }
+
+    st
+}
+
+pub fn format_console(m: Message) -> Vec<String> {
+    match m {
+        Message::Simple(s) | Message::Code(s) => s.split('\n').map(str::to_string).collect(),
+        Message::Spans(s) => render_ansi(&s).split('\n').map(str::to_string).collect(),
+        Message::Prefixed(p, s) => {
+            let p = render_ansi(&p);
+            render_ansi(&s).split('\n').map(|line| p.clone() + line).collect()
+        }
+        Message::List { prefix, sep, items } => vec![format!("{}{}", prefix, items.join(&sep))],
+    }
+}
+
+fn matrix_color(c: Color) -> Option<&'static str> {
+    Some(match c {
+        Color::None => return None,
+        Color::BrightWhite => "#ffffff",
+        Color::Black => "#000000",
+        Color::Blue => "#00007f",
+        Color::Green => "#009300",
+        Color::BrightRed => "#ff0000",
+        Color::Red => "#7f0000",
+        Color::Magenta => "#9c009c",
+        Color::Yellow => "#fc7f00",
+        Color::BrightYellow => "#ffff00",
+        Color::BrightGreen => "#00fc00",
+        Color::Cyan => "#009393",
+        Color::BrightCyan => "#00ffff",
+        Color::BrightBlue => "#0000fc",
+        Color::BrightMagenta => "#ff00ff",
+        Color::BrightBlack => "#7f7f7f",
+        Color::White => "#d2d2d2",
+    })
+}
+
+fn html_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\n' => out.push_str("<br>"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn render_matrix(spans: &[Span]) -> String {
+    let mut st = String::new();
+
+    for sp in spans {
+        match sp {
+            Span::Text {
+                text,
+                format,
+                color,
+                bg,
+            } => {
+                let mut open = String::new();
+                let mut close = String::new();
+
+                let fg = matrix_color(*color);
+                let bg = matrix_color(*bg);
+                if fg.is_some() || bg.is_some() {
+                    open.push_str("<font");
+                    if let Some(fg) = fg {
+                        open.push_str(&format!(" data-mx-color=\"{fg}\""));
+                    }
+                    if let Some(bg) = bg {
+                        open.push_str(&format!(" data-mx-bg-color=\"{bg}\""));
+                    }
+                    open.push('>');
+                    close.insert_str(0, "</font>");
+                }
+                for (f, tag) in &[(Format::Bold, "b"), (Format::Italic, "i"), (Format::Underline, "u")] {
+                    if format.contains(*f) {
+                        open.push_str(&format!("<{tag}>"));
+                        close.insert_str(0, &format!("</{tag}>"));
+                    }
+                }
+
+                st.push_str(&open);
+                st.push_str(&html_escape(text));
+                st.push_str(&close);
+            }
+            Span::DiscordEmoji(name, _) => {
+                st.push(':');
+                st.push_str(name);
+                st.push(':');
+            }
+        }
+    }
+
+    st
+}
+
+// Returns the plain-text body and, if the message has any formatting, the HTML formatted body.
+pub fn format_matrix(m: Message) -> (String, Option<String>) {
+    match m {
+        Message::Simple(s) => (s, None),
+        Message::Code(s) => {
+            let html = if s.contains('\n') {
+                format!("<pre><code>{}</code></pre>", html_escape(&s).replace("<br>", "\n"))
+            } else {
+                format!("<code>{}</code>", html_escape(&s))
+            };
+            (s, Some(html))
+        }
+        Message::Spans(s) => {
+            let html = render_matrix(&s);
+            (spans_to_raw_string(s), Some(html))
+        }
+        Message::Prefixed(p, s) => {
+            let html_p = render_matrix(&p);
+            let html = render_matrix(&s)
+                .split("<br>")
+                .map(|line| html_p.clone() + line)
+                .collect::<Vec<_>>()
+                .join("<br>");
+
+            let p = spans_to_raw_string(p);
+            let body = spans_to_raw_string(s)
+                .split('\n')
+                .map(|line| p.clone() + line)
+                .collect::<Vec<_>>()
+                .join("\n");
+            (body, Some(html))
+        }
+        Message::List { prefix, sep, items } => (format!("{}{}", prefix, items.join(&sep)), None),
+    }
 }
@@ -0,0 +1,284 @@
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::config;
+use crate::paste::Paster;
+use rustbot::prelude::*;
+
+// Upper bounds of histogram buckets, in seconds.
+const BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+// A count for each set of label values.
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, u64>>,
+}
+
+impl Counter {
+    fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self {
+            name,
+            help,
+            labels,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    // `values` are in the same order as the counter's labels.
+    pub fn inc(&self, values: &[&str]) {
+        debug_assert_eq!(values.len(), self.labels.len(), "{}", self.name);
+        *self.values.lock().entry(owned(values)).or_default() += 1;
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "counter");
+        for (values, n) in self.values.lock().iter() {
+            writeln!(out, "{}{} {}", self.name, labels(self.labels, values, None), n).unwrap();
+        }
+    }
+}
+
+#[derive(Default)]
+struct Observations {
+    buckets: Vec<u64>, // not cumulative; one for each of BUCKETS
+    sum: f64,
+    count: u64,
+}
+
+// How many observations fell in each of BUCKETS, for each set of label values.
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, Observations>>,
+}
+
+impl Histogram {
+    fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self {
+            name,
+            help,
+            labels,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn observe(&self, values: &[&str], d: Duration) {
+        debug_assert_eq!(values.len(), self.labels.len(), "{}", self.name);
+        let secs = d.as_secs_f64();
+        let mut all = self.values.lock();
+        let o = all.entry(owned(values)).or_default();
+        o.buckets.resize(BUCKETS.len(), 0);
+        if let Some(i) = BUCKETS.iter().position(|b| secs <= *b) {
+            o.buckets[i] += 1;
+        }
+        o.sum += secs;
+        o.count += 1;
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "histogram");
+        for (values, o) in self.values.lock().iter() {
+            let mut cumulative = 0;
+            for (bucket, n) in BUCKETS.iter().zip(&o.buckets) {
+                cumulative += n;
+                let le = bucket.to_string();
+                let l = labels(self.labels, values, Some(&le));
+                writeln!(out, "{}_bucket{} {}", self.name, l, cumulative).unwrap();
+            }
+            let l = labels(self.labels, values, Some("+Inf"));
+            writeln!(out, "{}_bucket{} {}", self.name, l, o.count).unwrap();
+            let l = labels(self.labels, values, None);
+            writeln!(out, "{}_sum{} {}", self.name, l, o.sum).unwrap();
+            writeln!(out, "{}_count{} {}", self.name, l, o.count).unwrap();
+        }
+    }
+}
+
+fn owned(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+fn header(out: &mut String, name: &str, help: &str, typ: &str) {
+    writeln!(out, "# HELP {name} {help}").unwrap();
+    writeln!(out, "# TYPE {name} {typ}").unwrap();
+}
+
+// `{name="value",...}`, or nothing if there are no labels.
+fn labels(names: &[&str], values: &[String], le: Option<&str>) -> String {
+    let mut pairs: Vec<_> = names
+        .iter()
+        .zip(values)
+        .map(|(n, v)| format!("{n}=\"{}\"", escape(v)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{le}\""));
+    }
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// Everything the bot counts, served in the Prometheus text format by the [metrics] server.
+pub struct Metrics {
+    pub messages_received: Counter,
+    pub messages_sent: Counter,
+    pub commands: Histogram,
+    pub command_errors: Counter,
+    pub reconnects: Counter,
+    pub pastes: Counter,
+    pub sql: Histogram,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            messages_received: Counter::new(
+                "rustbot_messages_received_total",
+                "Messages, attachments and embeds received from platforms.",
+                &["config", "channel_type"],
+            ),
+            messages_sent: Counter::new(
+                "rustbot_messages_sent_total",
+                "Replies and messages sent by commands and modules.",
+                &["config"],
+            ),
+            commands: Histogram::new(
+                "rustbot_command_duration_seconds",
+                "Time taken by commands, whether or not they succeeded.",
+                &["module", "command"],
+            ),
+            command_errors: Counter::new(
+                "rustbot_command_errors_total",
+                "Commands that failed: with an error for the user, a backend error, or a crash.",
+                &["module", "command", "kind"],
+            ),
+            reconnects: Counter::new(
+                "rustbot_reconnects_total",
+                "Times a platform connection was retried after it ended.",
+                &["config"],
+            ),
+            pastes: Counter::new(
+                "rustbot_paste_uploads_total",
+                "Long messages pasted, by whether pasting them succeeded.",
+                &["result"],
+            ),
+            sql: Histogram::new(
+                "rustbot_db_lock_held_seconds",
+                "Time the shared database connection was held for queries.",
+                &[],
+            ),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.messages_received.render(&mut out);
+        self.messages_sent.render(&mut out);
+        self.commands.render(&mut out);
+        self.command_errors.render(&mut out);
+        self.reconnects.render(&mut out);
+        self.pastes.render(&mut out);
+        self.sql.render(&mut out);
+        out
+    }
+}
+
+// A Paster that counts the pastes made through it.
+pub struct CountingPaster {
+    pub paster: Arc<dyn Paster>,
+    pub metrics: Arc<Metrics>,
+}
+
+impl Paster for CountingPaster {
+    fn paste(&self, text: &str) -> Result<String> {
+        let res = self.paster.paste(text);
+        self.metrics.pastes.inc(&[if res.is_ok() { "ok" } else { "error" }]);
+        res
+    }
+
+    fn public(&self) -> bool {
+        self.paster.public()
+    }
+}
+
+// Serves `metrics` at /metrics over HTTP on the configured address, in a new thread.
+pub fn serve(metrics: Arc<Metrics>, config: &config::Metrics) -> Result<()> {
+    let addr: SocketAddr = config
+        .listen
+        .parse()
+        .with_context(|| format!("invalid metrics listen address {:?}", config.listen))?;
+
+    thread::Builder::new()
+        .name("metrics server".to_string())
+        .spawn(move || {
+            let rt = match tokio::runtime::Runtime::new() {
+                Ok(rt) => rt,
+                Err(e) => {
+                    error!("metrics server: failed to start runtime: {}", e);
+                    return;
+                }
+            };
+            rt.block_on(async {
+                let make_svc = make_service_fn(move |_conn| {
+                    let metrics = metrics.clone();
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                            let metrics = metrics.clone();
+                            async move { Ok::<_, Infallible>(handle(&metrics, &req)) }
+                        }))
+                    }
+                });
+
+                info!("metrics server: listening on {}", addr);
+                if let Err(e) = Server::bind(&addr).serve(make_svc).await {
+                    error!("metrics server: {}", e);
+                }
+            });
+        })?;
+    Ok(())
+}
+
+fn handle(metrics: &Metrics, req: &Request<Body>) -> Response<Body> {
+    let (status, content_type, body) = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => (
+            StatusCode::OK,
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics.render(),
+        ),
+        (&Method::GET, _) => (
+            StatusCode::NOT_FOUND,
+            "text/plain; charset=utf-8",
+            "not found\n".to_string(),
+        ),
+        _ => (
+            StatusCode::METHOD_NOT_ALLOWED,
+            "text/plain; charset=utf-8",
+            "method not allowed\n".to_string(),
+        ),
+    };
+
+    let mut res = Response::new(Body::from(body));
+    *res.status_mut() = status;
+    res.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static(content_type),
+    );
+    res
+}
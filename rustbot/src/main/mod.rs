@@ -1,10 +1,24 @@
 mod bot;
 mod config;
+mod console;
 mod context;
+mod cooldown;
 mod core;
+mod crash;
 mod db;
+mod discord;
+mod flood;
+mod irc;
+mod matrix;
 mod message;
+mod metrics;
+mod paste;
+mod platform;
+mod scheduler;
+mod watchdog;
 
+#[cfg(test)]
+mod irc_test;
 #[cfg(test)]
 mod test;
 
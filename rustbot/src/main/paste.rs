@@ -0,0 +1,293 @@
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::fs;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::config;
+use rustbot::prelude::*;
+
+// Somewhere to put text that is too long to send as messages.
+pub trait Paster: Send + Sync {
+    // Stores `text`, returning a URL it can be read from.
+    fn paste(&self, text: &str) -> Result<String>;
+
+    // Whether the URLs `paste` returns can be opened by others, rather than only on the bot's host.
+    fn public(&self) -> bool {
+        true
+    }
+}
+
+// Pastes by running an external command with the text on stdin; it prints the URL on stdout.
+pub struct ScriptPaster {
+    command: String,
+}
+
+impl ScriptPaster {
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+        }
+    }
+}
+
+impl Paster for ScriptPaster {
+    fn paste(&self, text: &str) -> Result<String> {
+        let mut cmd = Command::new(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to run paste command {:?}", self.command))?;
+
+        if let Some(mut stdin) = cmd.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+
+        let out = cmd.wait_with_output()?;
+        if !out.status.success() {
+            bail!("paste command {:?} failed: {}", self.command, out.status);
+        }
+        let url = String::from_utf8(out.stdout)?;
+        if url.trim().is_empty() {
+            bail!("paste command {:?} printed no URL", self.command);
+        }
+        Ok(url.trim().to_string())
+    }
+}
+
+// Length of a paste id, in hex digits of the SHA-256 of its content.
+const PASTE_ID_LEN: usize = 16;
+
+// Pastes stored as files in `dir`, named by a hash of their content, and served by `serve`. Pastes
+// older than `expiry` are no longer served, and are removed by `expire`.
+pub struct PasteStore {
+    dir: PathBuf,
+    url: String,
+    expiry: Option<Duration>,
+    public: bool,
+}
+
+impl PasteStore {
+    pub fn new(dir: &str, url: &str, expiry: Option<Duration>) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create paste directory {dir:?}"))?;
+        Ok(Self {
+            dir: PathBuf::from(dir),
+            url: url.trim_end_matches('/').to_string(),
+            expiry,
+            public: true,
+        })
+    }
+
+    fn id(text: &str) -> String {
+        let hash = Sha256::digest(text.as_bytes());
+        let hex: String = hash.iter().map(|b| format!("{b:02x}")).collect();
+        hex[..PASTE_ID_LEN].to_string()
+    }
+
+    // The file for a paste id, or None if it isn't one that we could have generated.
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        if id.len() == PASTE_ID_LEN && id.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)) {
+            Some(self.dir.join(id))
+        } else {
+            None
+        }
+    }
+
+    fn expired(&self, modified: SystemTime, now: SystemTime) -> bool {
+        match (self.expiry, now.duration_since(modified)) {
+            (Some(expiry), Ok(age)) => age > expiry,
+            _ => false,
+        }
+    }
+
+    // The content of the paste with this id, if it exists and hasn't expired.
+    pub fn get(&self, id: &str, now: SystemTime) -> Option<String> {
+        let path = self.path(id)?;
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+        if self.expired(modified, now) {
+            return None;
+        }
+        fs::read_to_string(path).ok()
+    }
+
+    // Removes expired pastes, returning how many there were.
+    pub fn expire(&self, now: SystemTime) -> Result<usize> {
+        let mut removed = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            if name.to_str().and_then(|n| self.path(n)).is_none() {
+                continue;
+            }
+            if self.expired(entry.metadata()?.modified()?, now) {
+                fs::remove_file(entry.path())?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    // The response to a GET for `path`: the paste as HTML at /<id>, or as plain text at /<id>.txt.
+    pub fn respond(&self, path: &str, now: SystemTime) -> (StatusCode, &'static str, String) {
+        let path = path.trim_start_matches('/');
+        let (id, raw) = match path.strip_suffix(".txt") {
+            Some(id) => (id, true),
+            None => (path, false),
+        };
+
+        match self.get(id, now) {
+            None => (
+                StatusCode::NOT_FOUND,
+                "text/plain; charset=utf-8",
+                "paste not found\n".to_string(),
+            ),
+            Some(text) if raw => (StatusCode::OK, "text/plain; charset=utf-8", text),
+            Some(text) => (
+                StatusCode::OK,
+                "text/html; charset=utf-8",
+                format!(
+                    "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>paste {id}</title></head>\n\
+                     <body><p><a href=\"{id}.txt\">raw</a></p><pre>{}</pre></body></html>\n",
+                    html_escape(&text)
+                ),
+            ),
+        }
+    }
+}
+
+impl Paster for PasteStore {
+    fn paste(&self, text: &str) -> Result<String> {
+        let id = Self::id(text);
+        // Rewritten even if it already exists, so that its expiry starts again
+        fs::write(self.dir.join(&id), text)?;
+        Ok(format!("{}/{}", self.url, id))
+    }
+
+    fn public(&self) -> bool {
+        self.public
+    }
+}
+
+fn html_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// How often the server removes expired pastes.
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Serves pastes from `store` over HTTP on `addr`, in a new thread.
+fn serve(store: Arc<PasteStore>, addr: SocketAddr) -> Result<()> {
+    thread::Builder::new().name("paste server".to_string()).spawn(move || {
+        let rt = match tokio::runtime::Runtime::new() {
+            Ok(rt) => rt,
+            Err(e) => {
+                error!("paste server: failed to start runtime: {}", e);
+                return;
+            }
+        };
+        rt.block_on(async {
+            let expire_store = store.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(EXPIRE_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    match expire_store.expire(SystemTime::now()) {
+                        Ok(0) => {}
+                        Ok(n) => info!("paste server: removed {} expired pastes", n),
+                        Err(e) => warn!("paste server: failed to remove expired pastes: {}", e),
+                    }
+                }
+            });
+
+            let make_svc = make_service_fn(move |_conn| {
+                let store = store.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                        let store = store.clone();
+                        async move { Ok::<_, Infallible>(handle(&store, &req)) }
+                    }))
+                }
+            });
+
+            info!("paste server: listening on {}", addr);
+            if let Err(e) = Server::bind(&addr).serve(make_svc).await {
+                error!("paste server: {}", e);
+            }
+        });
+    })?;
+    Ok(())
+}
+
+fn handle(store: &PasteStore, req: &Request<Body>) -> Response<Body> {
+    let (status, content_type, body) = if req.method() == Method::GET {
+        store.respond(req.uri().path(), SystemTime::now())
+    } else {
+        (
+            StatusCode::METHOD_NOT_ALLOWED,
+            "text/plain; charset=utf-8",
+            "method not allowed\n".to_string(),
+        )
+    };
+
+    let mut res = Response::new(Body::from(body));
+    *res.status_mut() = status;
+    res.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static(content_type),
+    );
+    res
+}
+
+// The paster for the configured backend, starting the built-in server if that's the one in use.
+pub fn from_config(config: &config::Paste) -> Result<Arc<dyn Paster>> {
+    match config {
+        config::Paste::Builtin {
+            dir,
+            listen,
+            url,
+            expiry_days,
+        } => {
+            let addr: SocketAddr = listen
+                .parse()
+                .with_context(|| format!("invalid paste listen address {listen:?}"))?;
+            // Links to a server only reachable from this host are no use to anyone else
+            let public = url.is_some() || !addr.ip().is_loopback();
+            let url = url.clone().unwrap_or_else(|| format!("http://{addr}"));
+            let expiry = match expiry_days {
+                0 => None,
+                days => Some(Duration::from_secs(days * 24 * 60 * 60)),
+            };
+
+            let mut store = PasteStore::new(dir, &url, expiry)?;
+            if !public {
+                warn!(
+                    "paste: the builtin server listens on {} and no url is set, so its links would only work on \
+                     this host; long messages are kept for the more command instead",
+                    addr
+                );
+                store.public = false;
+            }
+            let store = Arc::new(store);
+            serve(store.clone(), addr)?;
+            Ok(store)
+        }
+        config::Paste::Script { command } => Ok(Arc::new(ScriptPaster::new(command))),
+    }
+}
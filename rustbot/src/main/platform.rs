@@ -0,0 +1,123 @@
+use postgres::types::ToSql;
+use std::any::Any;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use crate::bot::Rustbot;
+use crate::config;
+use crate::console::ConsolePlatform;
+use crate::discord::DiscordPlatform;
+use crate::irc::IrcPlatform;
+use crate::matrix::MatrixPlatform;
+use crate::paste::Paster;
+use rustbot::prelude::*;
+
+// A chat network connection, one per config id. Everything network-specific lives behind this trait:
+// connecting and turning incoming traffic into Rustbot::incoming calls, rendering and sending
+// messages, resolving permissions, and the network's half of channel strings ("<kind>:<channel>").
+pub trait Platform: Send + Sync {
+    // The channel string prefix for this network, e.g. "irc" for "irc:#channel".
+    fn kind(&self) -> &'static str;
+    fn config_id(&self) -> &str;
+    // Used in logs and thread names.
+    fn describe(&self) -> String {
+        self.config_id().to_string()
+    }
+
+    // Connect, and process incoming messages until the connection is lost.
+    fn connect(self: Arc<Self>, bot: Arc<Rustbot>) -> Result<()>;
+
+    // Close the connection for good, making `connect` return; used when the platform's config entry is
+    // changed or removed. Platforms that can't do this are only replaced on restart.
+    fn disconnect(&self) -> Result<()> {
+        bail!("{} connections can't be closed without a restart", self.kind())
+    }
+
+    // Render and send a message to a channel, given without the "<kind>:" prefix.
+    fn send(&self, channel: &str, msg: Message) -> Result<()>;
+
+    // Reply to an incoming message; by default, sends to the channel it came from.
+    fn reply(&self, origin: &Origin, msg: Message) -> Result<()> {
+        self.send(&origin.channel, msg)
+    }
+
+    // Send the next part of a long message that was cut short for the `more` command, to wherever
+    // `origin` came from. Ok(false) means there was nothing left to send.
+    fn more(&self, _origin: &Origin) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn perms(&self, bot: &Rustbot, origin: &Origin) -> Result<Perms>;
+
+    // Decode the network's formatting in an incoming message. Ok(None) means the message should not
+    // be treated as chat text at all (for example, an unknown IRC CTCP request).
+    fn parse_message(&self, _origin: &Origin, msg: &str) -> Result<Option<MessageText<'static>>> {
+        Ok(Some(MessageText::Plain(spans![msg.to_string()])))
+    }
+
+    // Alter a name so that including it in a message does not notify the user it belongs to.
+    fn quiet_name<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        name.into()
+    }
+
+    fn discord_params(&self, _origin: &Origin) -> Option<(Option<u64>, u64, u64)> {
+        None
+    }
+    fn irc_params(&self, _origin: &Origin) -> Option<(Option<String>, String)> {
+        None
+    }
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+// Who sent an incoming message, and where. `data` carries whatever else the platform needs to reply
+// to or identify the sender, and is only ever downcast by the platform that created it.
+#[derive(Clone)]
+pub struct Origin {
+    pub user: String,
+    pub user_pretty: String,
+    pub channel: String,
+
+    pub data: Arc<dyn Any + Send + Sync>,
+}
+
+impl Origin {
+    pub fn data<T: 'static>(&self) -> Result<&T> {
+        self.data
+            .downcast_ref()
+            .ok_or_else(|| Error::msg("message origin does not belong to this platform"))
+    }
+}
+
+// Splits "kind:channel" into its two halves.
+pub fn parse_channel_string(s: &str) -> Result<(&str, &str)> {
+    match s.split_once(':') {
+        Some((kind, channel)) if !kind.is_empty() && !channel.is_empty() => Ok((kind, channel)),
+        _ => bail!("invalid channel string {:?}", s),
+    }
+}
+
+pub fn channel_string(platform: &dyn Platform, channel: &str) -> String {
+    format!("{}:{}", platform.kind(), channel)
+}
+
+// Looks up a user's flags with a query returning at most one `flags` row; errors are logged and treated
+// as no permissions.
+pub fn query_perms(bot: &Rustbot, query: &str, params: &[&(dyn ToSql + Sync)]) -> Perms {
+    match bot.sql().lock().query(query, params) {
+        Err(e) => {
+            error!("error fetching perms: {}", e);
+            Perms::None
+        }
+        Ok(v) => v.first().map_or(Perms::None, |row| row.get(0)),
+    }
+}
+
+pub fn new(config: config::PlatformConfig, paster: &Arc<dyn Paster>) -> Arc<dyn Platform> {
+    match config {
+        config::PlatformConfig::Irc(c) => Arc::new(IrcPlatform::new(c, paster.clone())),
+        config::PlatformConfig::Discord(c) => Arc::new(DiscordPlatform::new(c, paster.clone())),
+        config::PlatformConfig::Matrix(c) => Arc::new(MatrixPlatform::new(c)),
+        config::PlatformConfig::Console(c) => Arc::new(ConsolePlatform::new(c)),
+    }
+}
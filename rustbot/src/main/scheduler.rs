@@ -0,0 +1,168 @@
+use chrono::{DateTime, Utc};
+use parking_lot::{Condvar, Mutex};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+use rustbot::prelude::*;
+
+// A stored job that fails is run at most this many times in all, waiting twice as long before each retry.
+pub const JOB_ATTEMPTS: i32 = 8;
+const JOB_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+// How long to wait before running a stored job again after its `attempts`th run failed, or None if that
+// was its last.
+pub fn retry_delay(attempts: i32) -> Option<Duration> {
+    if attempts >= JOB_ATTEMPTS {
+        return None;
+    }
+    Some(JOB_RETRY_DELAY * 2u32.pow(attempts.saturating_sub(1).max(0) as u32))
+}
+
+// A job for the timer thread to run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JobRef {
+    // The `index`th job that a module added with Meta::schedule.
+    Module {
+        module: String,
+        index: usize,
+    },
+    // A row of scheduled_jobs, added with Bot::schedule_job.
+    Stored {
+        id: i64,
+        module: String,
+        name: String,
+        payload: String,
+    },
+}
+
+impl JobRef {
+    fn module(&self) -> &str {
+        match self {
+            JobRef::Module { module, .. } | JobRef::Stored { module, .. } => module,
+        }
+    }
+}
+
+struct Entry {
+    job: JobRef,
+    schedule: Schedule,
+    due: SystemTime,
+}
+
+// The first time a job on `schedule` is due after `now`, or None if it never is again.
+fn next_due(schedule: &Schedule, now: SystemTime) -> Option<SystemTime> {
+    match schedule {
+        Schedule::Every(interval) => Some(now + (*interval).max(Duration::from_secs(1))),
+        Schedule::Cron(cron) => cron.next_after(DateTime::<Utc>::from(now)).map(SystemTime::from),
+        Schedule::At(_) => None,
+    }
+}
+
+// When every job is next due. The timer thread sleeps in `run` until the earliest of them.
+pub struct Scheduler {
+    entries: Mutex<Entries>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct Entries {
+    by_key: BTreeMap<u64, Entry>,
+    next_key: u64,
+}
+
+impl Entries {
+    fn next_due(&self) -> Option<SystemTime> {
+        self.by_key.values().map(|e| e.due).min()
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(Entries::default()),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub fn add(&self, job: JobRef, schedule: Schedule, now: SystemTime) {
+        let due = match &schedule {
+            Schedule::At(at) => Some(*at),
+            s => next_due(s, now),
+        };
+        let due = match due {
+            Some(due) => due,
+            None => {
+                warn!("scheduler: {:?} never runs, ignoring it", schedule);
+                return;
+            }
+        };
+
+        let mut entries = self.entries.lock();
+        entries.next_key += 1;
+        let key = entries.next_key;
+        entries.by_key.insert(key, Entry { job, schedule, due });
+        self.changed.notify_one();
+    }
+
+    // Forgets the jobs of a module that is being dropped; returns how many there were.
+    pub fn remove_module(&self, module: &str) -> usize {
+        let by_key = &mut self.entries.lock().by_key;
+        let before = by_key.len();
+        by_key.retain(|_, e| e.job.module() != module);
+        before - by_key.len()
+    }
+
+    // Forgets a stored job that was cancelled; returns whether it was pending.
+    pub fn remove_stored(&self, id: i64) -> bool {
+        let by_key = &mut self.entries.lock().by_key;
+        let before = by_key.len();
+        by_key.retain(|_, e| !matches!(e.job, JobRef::Stored { id: i, .. } if i == id));
+        before != by_key.len()
+    }
+
+    // Takes the jobs that are due at `now`, in the order they were due, and moves recurring ones on to
+    // their next time.
+    pub fn take_due(&self, now: SystemTime) -> Vec<JobRef> {
+        let by_key = &mut self.entries.lock().by_key;
+
+        let mut due: Vec<_> = by_key
+            .iter()
+            .filter(|(_, e)| e.due <= now)
+            .map(|(k, e)| (e.due, *k))
+            .collect();
+        due.sort();
+
+        let mut jobs = vec![];
+        for (_, key) in due {
+            let entry = by_key.get_mut(&key).unwrap();
+            jobs.push(entry.job.clone());
+            match next_due(&entry.schedule, now) {
+                Some(next) => entry.due = next,
+                None => {
+                    by_key.remove(&key);
+                }
+            }
+        }
+        jobs
+    }
+
+    // Runs jobs as they become due, forever. `run_job` should hand the job off rather than wait for it,
+    // so that a slow job doesn't hold up the others.
+    pub fn run(&self, run_job: impl Fn(JobRef)) {
+        loop {
+            for job in self.take_due(SystemTime::now()) {
+                run_job(job);
+            }
+
+            let mut entries = self.entries.lock();
+            match entries.next_due() {
+                None => self.changed.wait(&mut entries),
+                Some(next) => {
+                    if let Ok(wait) = next.duration_since(SystemTime::now()) {
+                        self.changed.wait_for(&mut entries, wait);
+                    }
+                }
+            }
+        }
+    }
+}
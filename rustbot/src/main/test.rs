@@ -1,4 +1,17 @@
 use crate::bot;
+use crate::config::{self, Overflow};
+use crate::console;
+use crate::cooldown::{CooldownKey, Cooldowns, TokenBucket};
+use crate::crash::{self, CrashTracker};
+use crate::flood::{self, Priority, SendQueue};
+use crate::matrix;
+use crate::message::{self, Limit, MoreBuffer};
+use crate::metrics::Metrics;
+use crate::paste::{PasteStore, Paster};
+use crate::scheduler::{self, JobRef, Scheduler};
+use crate::watchdog::{Outcome, Running, Watchdog};
+use rustbot::prelude::*;
+use std::time::{Duration, Instant, SystemTime};
 
 #[test]
 fn test_truncate_module_path() {
@@ -33,3 +46,569 @@ fn test_truncate_module_path() {
         assert_eq!(bot::truncate_module_path(test_path, i), expected[i]);
     }
 }
+
+#[test]
+fn test_format_console() {
+    assert_eq!(
+        message::format_console(Message::Spans(spans![
+            "plain ",
+            span!(Format::Bold; "bold"),
+            " ",
+            span!(Color::Red + Format::Underline; "red"),
+        ])),
+        vec!["plain \x1b[1mbold\x1b[0m \x1b[4;31mred\x1b[0m"]
+    );
+
+    assert_eq!(
+        message::format_console(Message::Prefixed(spans!["<a> "], spans!["one\ntwo"])),
+        vec!["<a> one", "<a> two"]
+    );
+
+    assert_eq!(
+        message::format_console(Message::List {
+            prefix: "Items: ".into(),
+            sep: ", ".into(),
+            items: vec!["a".into(), "b".into()],
+        }),
+        vec!["Items: a, b"]
+    );
+}
+
+#[test]
+fn test_format_matrix() {
+    assert_eq!(
+        message::format_matrix(Message::Simple("a <b> & c".to_string())),
+        ("a <b> & c".to_string(), None)
+    );
+
+    assert_eq!(
+        message::format_matrix(Message::Spans(spans![
+            "x < y ",
+            span!(Format::Bold + Format::Italic; "both"),
+            " ",
+            span!(Color::Red; "red"),
+        ])),
+        (
+            "x < y both red".to_string(),
+            Some("x &lt; y <b><i>both</i></b> <font data-mx-color=\"#7f0000\">red</font>".to_string())
+        )
+    );
+
+    assert_eq!(
+        message::format_matrix(Message::Prefixed(
+            spans![span!(Format::Bold; "<a>"), " "],
+            spans!["1\n2"]
+        )),
+        (
+            "<a> 1\n<a> 2".to_string(),
+            Some("<b>&lt;a&gt;</b> 1<br><b>&lt;a&gt;</b> 2".to_string())
+        )
+    );
+
+    assert_eq!(
+        message::format_matrix(Message::Code("a\n<b>".to_string())),
+        (
+            "a\n<b>".to_string(),
+            Some("<pre><code>a\n&lt;b&gt;</code></pre>".to_string())
+        )
+    );
+}
+
+#[test]
+fn test_matrix_sync_parse() {
+    let resp: matrix::SyncResponse = serde_json::from_str(
+        r#"{
+            "next_batch": "s2",
+            "rooms": {
+                "join": {
+                    "!room:example.org": {
+                        "summary": {"m.joined_member_count": 2},
+                        "timeline": {"events": [
+                            {"type": "m.room.message", "sender": "@a:example.org", "content": {"msgtype": "m.text", "body": "!dice 1d6"}},
+                            {"type": "m.room.message", "sender": "@bot:example.org", "content": {"msgtype": "m.notice", "body": "4"}},
+                            {"type": "m.room.member", "sender": "@c:example.org", "content": {"membership": "join"}}
+                        ]}
+                    }
+                },
+                "invite": {"!other:example.org": {}}
+            }
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(resp.next_batch, "s2");
+    assert!(resp.rooms.invite.contains_key("!other:example.org"));
+
+    let room = &resp.rooms.join["!room:example.org"];
+    assert_eq!(room.summary.joined_member_count, Some(2));
+    let bodies: Vec<_> = room.timeline.events.iter().map(matrix::Event::message_body).collect();
+    assert_eq!(bodies, vec![Some("!dice 1d6"), None, None]);
+
+    assert_eq!(matrix::localpart("@someone:example.org"), "someone");
+
+    assert!(matrix::is_configured_user("@bot:example.org", "@bot:example.org"));
+    assert!(matrix::is_configured_user("bot", "@bot:example.org"));
+    assert!(!matrix::is_configured_user("@bot:example.org", "@bot:example.com"));
+    assert!(!matrix::is_configured_user("bot", "@other:example.org"));
+}
+
+#[test]
+fn test_cooldowns() {
+    let cooldowns = Cooldowns::new();
+    let cooldown = Cooldown {
+        scope: CooldownScope::User,
+        burst: 2,
+        refill: Duration::from_secs(10),
+    };
+    let key = |command: &str, target: &str| CooldownKey {
+        config: "irc".to_string(),
+        command: command.to_string(),
+        scope: CooldownScope::User,
+        target: target.to_string(),
+    };
+
+    let start = Instant::now();
+    assert_eq!(cooldowns.take(key("dice", "a"), cooldown, start), Ok(()));
+    assert_eq!(cooldowns.take(key("dice", "a"), cooldown, start), Ok(()));
+    assert_eq!(
+        cooldowns.take(key("dice", "a"), cooldown, start + Duration::from_secs(4)),
+        Err(Duration::from_secs(6))
+    );
+    // Other users and commands have their own buckets
+    assert_eq!(cooldowns.take(key("dice", "b"), cooldown, start), Ok(()));
+    assert_eq!(cooldowns.take(key("roll", "a"), cooldown, start), Ok(()));
+
+    // One use comes back every 10s
+    let later = start + Duration::from_secs(10);
+    assert_eq!(cooldowns.take(key("dice", "a"), cooldown, later), Ok(()));
+    assert!(cooldowns.take(key("dice", "a"), cooldown, later).is_err());
+
+    let active = cooldowns.active(later);
+    assert_eq!(active.len(), 1);
+    assert_eq!(active[0].0.to_string(), "dice (user a on irc)");
+    assert_eq!((active[0].1, active[0].2), (0.0, 2));
+
+    assert_eq!(cooldowns.reset(Some("dice")), 1);
+    assert_eq!(cooldowns.take(key("dice", "a"), cooldown, later), Ok(()));
+    assert_eq!(cooldowns.reset(None), 1);
+
+    // Buckets that have refilled are dropped by the next use of any command
+    for user in ["a", "b", "c"] {
+        assert_eq!(cooldowns.take(key("dice", user), cooldown, later), Ok(()));
+    }
+    let refilled = later + Duration::from_secs(10);
+    assert_eq!(cooldowns.take(key("roll", "a"), cooldown, refilled), Ok(()));
+    assert_eq!(cooldowns.reset(None), 1);
+}
+
+#[test]
+fn test_send_queue() {
+    let queue = SendQueue::new("test");
+    queue.push(Priority::Relay, "relay 1".to_string());
+    queue.push(Priority::Relay, "relay 2".to_string());
+    queue.push(Priority::Reply, "reply 1".to_string());
+
+    assert_eq!(queue.pop().as_deref(), Some("reply 1"));
+    assert_eq!(queue.pop().as_deref(), Some("relay 1"));
+    queue.push(Priority::Reply, "reply 2".to_string());
+    assert_eq!(queue.pop().as_deref(), Some("reply 2"));
+    assert_eq!(queue.pop().as_deref(), Some("relay 2"));
+    assert_eq!(queue.pop(), None);
+
+    let start = Instant::now();
+    let mut bucket = TokenBucket::new(2, Duration::from_secs(2), start);
+    assert_eq!(bucket.take(start), Ok(()));
+    assert_eq!(bucket.take(start), Ok(()));
+    assert_eq!(bucket.take(start), Err(Duration::from_secs(2)));
+    assert_eq!(bucket.take(start + Duration::from_secs(3)), Ok(()));
+    assert_eq!(bucket.take(start + Duration::from_secs(3)), Err(Duration::from_secs(1)));
+
+    let mut unlimited = TokenBucket::new(1, Duration::ZERO, start);
+    assert!((0..10).all(|_| unlimited.take(start).is_ok()));
+
+    queue.push(Priority::Reply, "never sent".to_string());
+    queue.close();
+    queue.run(unlimited, |line| panic!("sent {:?} after close", line));
+}
+
+#[test]
+fn test_send_queue_full() {
+    // Past the cap, the oldest relayed lines are dropped, but never replies
+    let queue = SendQueue::new("test");
+    for i in 0..flood::MAX_RELAY_LINES + 5 {
+        queue.push(Priority::Relay, format!("relay {i}"));
+    }
+    for i in 0..flood::MAX_RELAY_LINES + 5 {
+        queue.push(Priority::Reply, format!("reply {i}"));
+    }
+
+    let lines: Vec<String> = std::iter::from_fn(|| queue.pop()).collect();
+    assert_eq!(lines.len(), 2 * flood::MAX_RELAY_LINES + 5);
+    assert_eq!(lines[0], "reply 0");
+    assert_eq!(
+        lines[flood::MAX_RELAY_LINES + 4],
+        format!("reply {}", flood::MAX_RELAY_LINES + 4)
+    );
+    assert_eq!(lines[flood::MAX_RELAY_LINES + 5], "relay 5");
+    assert_eq!(lines.last().unwrap(), &format!("relay {}", flood::MAX_RELAY_LINES + 4));
+}
+
+#[test]
+fn test_send_queue_restarted() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // Starting the sender again, as reconnecting does, mustn't add to the rate it sends at
+    let queue = Arc::new(SendQueue::new("test"));
+    let sent = Arc::new(AtomicUsize::new(0));
+    for _ in 0..3 {
+        let bucket = TokenBucket::new(1, Duration::from_secs(60), Instant::now());
+        let sent = sent.clone();
+        queue
+            .start(bucket, move |_| {
+                sent.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .unwrap();
+    }
+    for i in 0..3 {
+        queue.push(Priority::Reply, format!("line {i}"));
+    }
+
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(sent.load(Ordering::SeqCst), 1);
+    queue.close();
+}
+
+#[test]
+fn test_split_discord() {
+    use message::split_discord;
+
+    assert_eq!(split_discord("short", 10), vec!["short"]);
+
+    // Newlines are preferred to spaces, and spaces to breaking a word
+    assert_eq!(split_discord("aaa bbb\nccc", 10), vec!["aaa bbb", "ccc"]);
+    assert_eq!(split_discord("aaa bbb ccc", 10), vec!["aaa bbb", "ccc"]);
+    assert_eq!(split_discord("aaaaaaaaaaaa", 5), vec!["aaaaa", "aaaaa", "aa"]);
+
+    // Lengths are in UTF-16 units, and surrogate pairs are never split
+    assert_eq!(split_discord("😀😀😀", 4), vec!["😀😀", "😀"]);
+    assert_eq!(split_discord("😀😀😀", 3), vec!["😀", "😀", "😀"]);
+
+    // Formatting and code blocks are closed and reopened
+    assert_eq!(split_discord("**bold words**", 10), vec!["**bold**", "**words**"]);
+    assert_eq!(split_discord("__**a b**__ c", 10), vec!["__**a**__", "__**b**__", "c"]);
+    assert_eq!(
+        split_discord("```\nline 1\nline 2\n```", 14),
+        vec!["```\nline 1```", "```line 2\n```"]
+    );
+    // but not inside code blocks
+    assert_eq!(split_discord("```**a\nb**```", 10), vec!["```**a```", "```b**```"]);
+}
+
+#[test]
+fn test_paste_store() {
+    let dir = std::env::temp_dir().join(format!("rustbot-paste-test-{}", std::process::id()));
+    let dir = dir.to_str().unwrap();
+    let store = PasteStore::new(dir, "http://paste.test/", Some(Duration::from_secs(60))).unwrap();
+    let now = SystemTime::now();
+
+    let url = store.paste("<one>\ntwo").unwrap();
+    let id = url.strip_prefix("http://paste.test/").unwrap();
+    assert_eq!(id.len(), 16);
+    assert_eq!(store.paste("<one>\ntwo").unwrap(), url);
+    assert_ne!(store.paste("other").unwrap(), url);
+
+    assert_eq!(store.get(id, now).as_deref(), Some("<one>\ntwo"));
+    let (status, _, body) = store.respond(&format!("/{id}.txt"), now);
+    assert_eq!((status.as_u16(), body.as_str()), (200, "<one>\ntwo"));
+    let (status, content_type, body) = store.respond(&format!("/{id}"), now);
+    assert_eq!((status.as_u16(), content_type), (200, "text/html; charset=utf-8"));
+    assert!(body.contains("<pre>&lt;one&gt;\ntwo</pre>"));
+
+    // Only ids that we could have generated are looked up
+    assert_eq!(store.respond("/../../etc/passwd", now).0.as_u16(), 404);
+    assert_eq!(store.respond(&format!("/{}", id.to_uppercase()), now).0.as_u16(), 404);
+
+    let later = now + Duration::from_secs(120);
+    assert_eq!(store.get(id, later), None);
+    assert_eq!(store.expire(now).unwrap(), 0);
+    assert_eq!(store.expire(later).unwrap(), 2);
+    assert_eq!(store.get(id, now), None);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_console_socket_dir() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("rustbot-console-test-{}", std::process::id()));
+    let sock = dir.join("run/rustbot.sock");
+    let sock = sock.to_str().unwrap();
+
+    // A missing directory is created closed to others
+    console::check_socket_dir(sock).unwrap();
+    let run = dir.join("run");
+    assert_eq!(std::fs::metadata(&run).unwrap().permissions().mode() & 0o777, 0o700);
+
+    // One that others can enter is refused
+    std::fs::set_permissions(&run, std::fs::Permissions::from_mode(0o755)).unwrap();
+    assert!(console::check_socket_dir(sock).is_err());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+struct TestPaster;
+
+impl Paster for TestPaster {
+    fn paste(&self, text: &str) -> Result<String> {
+        Ok(format!("paste of {} lines", text.split('\n').count()))
+    }
+}
+
+struct LocalPaster;
+
+impl Paster for LocalPaster {
+    fn paste(&self, _text: &str) -> Result<String> {
+        Ok("http://127.0.0.1:8090/0123456789abcdef".to_string())
+    }
+
+    fn public(&self) -> bool {
+        false
+    }
+}
+
+#[test]
+fn test_limit_lines() {
+    let text = "1\n2\n3\n4\n5\n6\n7";
+    let lines = || text.split('\n').map(String::from).collect::<Vec<_>>();
+    let more = MoreBuffer::default();
+    let limit = |max, overflow| Limit { max, overflow };
+
+    let short = message::limit_lines(lines(), text, limit(7, Overflow::More), &TestPaster, &more, "#a").unwrap();
+    assert_eq!(short, lines());
+    assert_eq!(more.next_page("#a"), None);
+
+    assert_eq!(
+        message::limit_lines(lines(), text, limit(3, Overflow::Paste), &TestPaster, &more, "#a").unwrap(),
+        vec!["1", "2", "[full message: paste of 7 lines]"]
+    );
+    assert_eq!(more.next_page("#a"), None);
+
+    assert_eq!(
+        message::limit_lines(lines(), text, limit(3, Overflow::More), &TestPaster, &more, "#a").unwrap(),
+        vec!["1", "2", "[5 more; use the more command to continue]"]
+    );
+    assert_eq!(more.next_page("#b"), None);
+    assert_eq!(
+        more.next_page("#a").unwrap(),
+        vec!["3", "4", "[3 more; use the more command to continue]"]
+    );
+    assert_eq!(
+        more.next_page("#a").unwrap(),
+        vec!["5", "6", "[1 more; use the more command to continue]"]
+    );
+    assert_eq!(more.next_page("#a").unwrap(), vec!["7"]);
+    assert_eq!(more.next_page("#a"), None);
+
+    // A new long message replaces what was left of the last one
+    message::limit_lines(lines(), text, limit(3, Overflow::More), &TestPaster, &more, "#a").unwrap();
+    message::limit_lines(lines(), text, limit(6, Overflow::More), &TestPaster, &more, "#a").unwrap();
+    assert_eq!(more.next_page("#a").unwrap(), vec!["6", "7"]);
+
+    // Links only the bot's host could open aren't sent; the rest is kept for `more` instead
+    assert_eq!(
+        message::limit_lines(lines(), text, limit(3, Overflow::Paste), &LocalPaster, &more, "#a").unwrap(),
+        vec!["1", "2", "[5 more; use the more command to continue]"]
+    );
+    assert_eq!(
+        more.next_page("#a").unwrap(),
+        vec!["3", "4", "[3 more; use the more command to continue]"]
+    );
+}
+
+#[test]
+fn test_scheduler() {
+    let scheduler = Scheduler::new();
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000 * 60);
+    let job = |module: &str, index| JobRef::Module {
+        module: module.to_string(),
+        index,
+    };
+    let stored = |id| JobRef::Stored {
+        id,
+        module: "b".to_string(),
+        name: "job".to_string(),
+        payload: String::new(),
+    };
+    let mins = |n: u64| Duration::from_secs(n * 60);
+
+    scheduler.add(job("a", 0), Schedule::Every(mins(2)), start);
+    scheduler.add(job("a", 1), Schedule::Cron(Cron::parse("*/5 * * * *").unwrap()), start);
+    scheduler.add(stored(1), Schedule::At(start + mins(3)), start);
+    scheduler.add(stored(2), Schedule::At(start + mins(4)), start);
+    scheduler.add(job("c", 0), Schedule::At(start - mins(1)), start);
+
+    assert_eq!(scheduler.take_due(start), vec![job("c", 0)]);
+    assert_eq!(scheduler.take_due(start + mins(1)), vec![]);
+    assert_eq!(scheduler.take_due(start + mins(2)), vec![job("a", 0)]);
+    assert!(scheduler.remove_stored(2));
+    assert!(!scheduler.remove_stored(2));
+    assert_eq!(
+        scheduler.take_due(start + mins(5)),
+        vec![stored(1), job("a", 0), job("a", 1)]
+    );
+    assert_eq!(scheduler.take_due(start + mins(5)), vec![]);
+
+    assert_eq!(scheduler.remove_module("a"), 2);
+    assert_eq!(scheduler.take_due(start + mins(60)), vec![]);
+}
+
+#[test]
+fn test_job_retry_delay() {
+    assert_eq!(scheduler::retry_delay(1), Some(Duration::from_secs(30)));
+    assert_eq!(scheduler::retry_delay(2), Some(Duration::from_secs(60)));
+    assert_eq!(scheduler::retry_delay(3), Some(Duration::from_secs(120)));
+    assert!(scheduler::retry_delay(scheduler::JOB_ATTEMPTS - 1).is_some());
+    assert_eq!(scheduler::retry_delay(scheduler::JOB_ATTEMPTS), None);
+}
+
+#[test]
+fn test_config() {
+    let example = include_str!("../../../Rustbot.toml.example");
+    let config = config::parse(example).unwrap();
+    assert_eq!(
+        config.platforms().keys().collect::<Vec<_>>(),
+        vec!["console", "discord", "irc", "matrix"]
+    );
+
+    let mut changed = config::parse(example).unwrap();
+    assert!(config.platforms() == changed.platforms());
+    changed.irc[0].nick = "otherbot".to_string();
+    let (old, new) = (config.platforms(), changed.platforms());
+    assert!(old["irc"] != new["irc"]);
+    assert!(old["discord"] == new["discord"]);
+
+    let duplicate = example.replace("id = \"console\"", "id = \"irc\"");
+    assert_eq!(
+        config::parse(&duplicate).err().unwrap().to_string(),
+        "config id \"irc\" is used by both a [[irc]] and a [[console]] entry"
+    );
+}
+
+#[test]
+fn test_crash() {
+    assert_eq!(crash::catch(|| 1), Ok(1));
+    assert_eq!(crash::catch(|| -> () { panic!("boom") }), Err("boom".to_string()));
+    assert_eq!(crash::catch(|| -> () { panic!("{}", 42) }), Err("42".to_string()));
+    assert_eq!(
+        crash::catch(|| std::panic::panic_any(42)),
+        Err("(non-string panic payload)".to_string())
+    );
+
+    let crashes = CrashTracker::new(3, Duration::from_secs(60));
+    let start = Instant::now();
+    assert!(!crashes.record("weather", start));
+    assert!(!crashes.record("weather", start + Duration::from_secs(10)));
+    // Other modules are counted separately
+    assert!(!crashes.record("admin", start + Duration::from_secs(20)));
+    // The first crash has left the window by now
+    assert!(!crashes.record("weather", start + Duration::from_secs(60)));
+    assert!(crashes.record("weather", start + Duration::from_secs(65)));
+    // and counting starts again once it has been reported
+    assert!(!crashes.record("weather", start + Duration::from_secs(66)));
+
+    crashes.forget("admin");
+    assert!(!crashes.record("admin", start + Duration::from_secs(30)));
+    assert!(!crashes.record("admin", start + Duration::from_secs(40)));
+    assert!(crashes.record("admin", start + Duration::from_secs(50)));
+}
+
+#[test]
+fn test_watchdog() {
+    let watchdog = Watchdog::new();
+    let running = |command: &str| Running {
+        config: "irc".to_string(),
+        module: "utils".to_string(),
+        command: command.to_string(),
+        user: "nick".to_string(),
+        channel: "#chan".to_string(),
+        timeout: Duration::from_secs(30),
+    };
+
+    let (units, units_outcome) = watchdog.start(running("units"));
+    let (bash, bash_outcome) = watchdog.start(running("bash"));
+    assert_ne!(units.id, bash.id);
+    assert_eq!(
+        watchdog.running().iter().map(|i| i.id).collect::<Vec<_>>(),
+        vec![units.id, bash.id]
+    );
+
+    // A command that finishes in time hands its result to the waiting caller
+    assert!(watchdog.finish(&units, Ok(())).is_none());
+    assert!(matches!(units_outcome.try_recv(), Ok(Outcome::Finished(Ok(())))));
+
+    // An abandoned one keeps showing until it returns, and then its result is given back
+    assert_eq!(watchdog.abandon(bash.id).unwrap().id, bash.id);
+    assert!(matches!(bash_outcome.try_recv(), Ok(Outcome::Abandoned)));
+    assert_eq!(
+        watchdog.abandon(bash.id).map(|_| ()).unwrap_err().to_string(),
+        format!("command {} was already abandoned", bash.id)
+    );
+    assert!(watchdog.running()[0].is_abandoned());
+    assert_eq!(
+        watchdog
+            .finish(&bash, Err(anyhow!("late")))
+            .unwrap()
+            .unwrap_err()
+            .to_string(),
+        "late"
+    );
+    assert!(watchdog.running().is_empty());
+    assert_eq!(
+        watchdog.abandon(bash.id).map(|_| ()).unwrap_err().to_string(),
+        format!("no command {} is running", bash.id)
+    );
+
+    // One whose thread couldn't be started is forgotten without an outcome
+    let (dice, dice_outcome) = watchdog.start(running("dice"));
+    watchdog.forget(&dice);
+    assert!(watchdog.running().is_empty());
+    assert!(dice_outcome.try_recv().is_err());
+}
+
+#[test]
+fn test_metrics() {
+    let metrics = Metrics::new();
+    metrics.messages_received.inc(&["irc", "public"]);
+    metrics.messages_received.inc(&["irc", "public"]);
+    metrics.messages_received.inc(&["dis\"cord", "private"]);
+    metrics.commands.observe(&["dice", "roll"], Duration::from_millis(20));
+    metrics.commands.observe(&["dice", "roll"], Duration::from_millis(300));
+    metrics.commands.observe(&["dice", "roll"], Duration::from_secs(60));
+    metrics.sql.observe(&[], Duration::from_millis(1));
+
+    let out = metrics.render();
+    let lines: Vec<_> = out.lines().collect();
+    for expected in [
+        "# TYPE rustbot_messages_received_total counter",
+        r#"rustbot_messages_received_total{config="dis\"cord",channel_type="private"} 1"#,
+        r#"rustbot_messages_received_total{config="irc",channel_type="public"} 2"#,
+        "# TYPE rustbot_command_duration_seconds histogram",
+        r#"rustbot_command_duration_seconds_bucket{module="dice",command="roll",le="0.01"} 0"#,
+        r#"rustbot_command_duration_seconds_bucket{module="dice",command="roll",le="0.025"} 1"#,
+        r#"rustbot_command_duration_seconds_bucket{module="dice",command="roll",le="0.5"} 2"#,
+        r#"rustbot_command_duration_seconds_bucket{module="dice",command="roll",le="30"} 2"#,
+        r#"rustbot_command_duration_seconds_bucket{module="dice",command="roll",le="+Inf"} 3"#,
+        r#"rustbot_command_duration_seconds_sum{module="dice",command="roll"} 60.32"#,
+        r#"rustbot_command_duration_seconds_count{module="dice",command="roll"} 3"#,
+        r#"rustbot_db_lock_held_seconds_bucket{le="0.005"} 1"#,
+        "rustbot_db_lock_held_seconds_count 1",
+        // Metrics with nothing counted yet still have their header
+        "# TYPE rustbot_reconnects_total counter",
+    ] {
+        assert!(lines.contains(&expected), "{:?} not in\n{}", expected, out);
+    }
+    assert!(!out.contains("rustbot_reconnects_total{"));
+}
@@ -0,0 +1,115 @@
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rustbot::prelude::*;
+
+// How long a module command may run before the bot stops waiting for it, unless it sets its own timeout.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// What a running command is, for `ps`. Scheduled jobs run the same way, for the user "scheduler" and with
+// an empty config and channel.
+pub struct Running {
+    pub config: String,
+    pub module: String,
+    pub command: String,
+    pub user: String,
+    pub channel: String,
+    pub timeout: Duration,
+}
+
+pub enum Outcome {
+    Finished(Result<()>),
+    Abandoned, // by an admin, with `ps abandon`
+}
+
+// A module command that is running on its own thread, with the bot waiting for its outcome. Threads can't
+// be stopped from outside, so abandoning one only stops the bot from waiting: its replies are dropped, and
+// it keeps running (and keeps showing in `ps`, and keeps its module from being unloaded) until it returns
+// by itself.
+pub struct Invocation {
+    pub id: u64,
+    pub started: Instant,
+    pub running: Running,
+    abandoned: AtomicBool,
+    done: Sender<Outcome>,
+}
+
+impl Invocation {
+    pub fn is_abandoned(&self) -> bool {
+        self.abandoned.load(Ordering::SeqCst)
+    }
+
+    // Returns false if it had already been abandoned.
+    pub fn abandon(&self) -> bool {
+        !self.abandoned.swap(true, Ordering::SeqCst)
+    }
+}
+
+// Every module command that is running.
+pub struct Watchdog {
+    next_id: AtomicU64,
+    invocations: Mutex<BTreeMap<u64, Arc<Invocation>>>,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            invocations: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    // Tracks a command that is about to start; its outcome arrives on the receiver.
+    pub fn start(&self, running: Running) -> (Arc<Invocation>, Receiver<Outcome>) {
+        let (done, outcome) = mpsc::channel();
+        let invocation = Arc::new(Invocation {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            started: Instant::now(),
+            running,
+            abandoned: AtomicBool::new(false),
+            done,
+        });
+        self.invocations.lock().insert(invocation.id, invocation.clone());
+        (invocation, outcome)
+    }
+
+    // Stops tracking a command that has returned, and hands its result to whoever is waiting for it. If
+    // it was abandoned meanwhile, nobody is, so the result is given back.
+    pub fn finish(&self, invocation: &Invocation, res: Result<()>) -> Option<Result<()>> {
+        self.forget(invocation);
+        if invocation.is_abandoned() {
+            return Some(res);
+        }
+        match invocation.done.send(Outcome::Finished(res)) {
+            Ok(()) => None,
+            Err(mpsc::SendError(Outcome::Finished(res))) => Some(res),
+            Err(_) => None,
+        }
+    }
+
+    // Stops tracking a command that never started.
+    pub fn forget(&self, invocation: &Invocation) {
+        self.invocations.lock().remove(&invocation.id);
+    }
+
+    pub fn abandon(&self, id: u64) -> Result<Arc<Invocation>> {
+        let invocation = match self.invocations.lock().get(&id) {
+            Some(i) => i.clone(),
+            None => bail_user!("no command {} is running", id),
+        };
+        if !invocation.abandon() {
+            bail_user!("command {} was already abandoned", id);
+        }
+        invocation.done.send(Outcome::Abandoned).unwrap_or(()); // Err() means the bot already gave up on it
+        Ok(invocation)
+    }
+
+    // Oldest first.
+    pub fn running(&self) -> Vec<Arc<Invocation>> {
+        self.invocations.lock().values().cloned().collect()
+    }
+}
@@ -0,0 +1,262 @@
+// #[derive(Arg)], re-exported from rustbot::prelude.
+//
+// Structs parse their fields in order, like a tuple of the field types would. Field attributes:
+//   #[arg(optional)]  the field is an Option<T>, and is None if there is no input left for it
+//   #[arg(rest)]      the field takes all of the remaining input; its type must implement From<&str>
+// The two can be combined for an Option of a rest-of-input type; `rest` must be on the last field.
+//
+// Enums parse a keyword naming the variant (the variant name in kebab-case, or #[arg(name = "...")]),
+// followed by the variant's fields as for a struct.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, GenericParam, Lifetime, LitStr, Result};
+
+#[proc_macro_derive(Arg, attributes(arg))]
+pub fn derive_arg(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match derive(input) {
+        Ok(ts) => ts.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn derive(input: DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+
+    // The trait's lifetime: the type's own lifetime parameter if it has one, otherwise a fresh one
+    let lifetimes: Vec<_> = input.generics.lifetimes().collect();
+    let (lt, extra_lt) = match lifetimes.as_slice() {
+        [] => (Lifetime::new("'__arg", Span::call_site()), true),
+        [l] => (l.lifetime.clone(), false),
+        _ => {
+            return Err(Error::new_spanned(
+                &input.generics,
+                "#[derive(Arg)] supports at most one lifetime parameter",
+            ))
+        }
+    };
+
+    let mut generics = input.generics.clone();
+    for param in &mut generics.params {
+        if let GenericParam::Type(t) = param {
+            t.bounds.push(syn::parse_quote!(::rustbot::args::Arg<#lt>));
+        }
+    }
+    let (_, ty_generics, where_clause) = input.generics.split_for_impl();
+    if extra_lt {
+        generics.params.insert(0, syn::parse_quote!(#lt));
+    }
+    let (impl_generics, _, _) = generics.split_for_impl();
+
+    let (parse, describe) = match &input.data {
+        Data::Struct(s) => {
+            let fields = parse_fields(&s.fields)?;
+            let body = parse_body(&fields, &lt, quote!(Self), &s.fields);
+            let describe = describe_fields(&fields);
+            (
+                quote! {
+                    let rest: ::std::option::Option<&'s str> = ::std::option::Option::Some(input);
+                    #body
+                },
+                describe,
+            )
+        }
+        Data::Enum(e) => {
+            let mut arms = vec![];
+            let mut keywords = vec![];
+            let mut descriptions = vec![];
+            for v in &e.variants {
+                let keyword = variant_keyword(v)?;
+                let ident = &v.ident;
+                let fields = parse_fields(&v.fields)?;
+                let body = parse_body(&fields, &lt, quote!(Self::#ident), &v.fields);
+                arms.push(quote! {
+                    #keyword => { #body }
+                });
+                let fields_description = describe_fields(&fields);
+                descriptions.push(if fields.is_empty() {
+                    quote!(::std::string::String::from(#keyword))
+                } else {
+                    quote!(::std::format!("{} {}", #keyword, #fields_description))
+                });
+                keywords.push(keyword);
+            }
+            let expected = keywords.join(", ");
+            (
+                quote! {
+                    if input.is_empty() {
+                        ::rustbot::bail_user!("missing argument")
+                    }
+                    let (keyword, rest) = match input.split_once(char::is_whitespace) {
+                        ::std::option::Option::Some((keyword, rest)) => (keyword, ::std::option::Option::Some(rest)),
+                        ::std::option::Option::None => (input, ::std::option::Option::None),
+                    };
+                    match keyword {
+                        #(#arms)*
+                        _ => ::rustbot::bail_user!("expected one of {}, got {:?}", #expected, keyword),
+                    }
+                },
+                quote! {
+                    ::std::borrow::Cow::<'static, str>::Owned([#(#descriptions),*].join(" | "))
+                },
+            )
+        }
+        Data::Union(_) => return Err(Error::new_spanned(&input, "#[derive(Arg)] does not support unions")),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::rustbot::args::Arg<#lt> for #name #ty_generics #where_clause {
+            fn parse_from<'s: #lt>(input: &'s str) -> ::rustbot::error::Result<(Self, ::std::option::Option<&'s str>)> {
+                #parse
+            }
+
+            fn describe_expected() -> ::std::borrow::Cow<'static, str> {
+                #describe
+            }
+        }
+    })
+}
+
+struct Field<'a> {
+    ident: syn::Ident, // the binding used in parse_from; the field name for named fields
+    named: bool,
+    ty: &'a syn::Type,
+    optional: bool,
+    rest: bool,
+}
+
+fn parse_fields(fields: &Fields) -> Result<Vec<Field<'_>>> {
+    let mut out = vec![];
+    for (i, f) in fields.iter().enumerate() {
+        let mut field = Field {
+            ident: f.ident.clone().unwrap_or_else(|| format_ident!("field{}", i)),
+            named: f.ident.is_some(),
+            ty: &f.ty,
+            optional: false,
+            rest: false,
+        };
+        for attr in &f.attrs {
+            if !attr.path().is_ident("arg") {
+                continue;
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("optional") {
+                    field.optional = true;
+                    Ok(())
+                } else if meta.path.is_ident("rest") {
+                    field.rest = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `optional` or `rest`"))
+                }
+            })?;
+        }
+        if field.rest && i + 1 != fields.len() {
+            return Err(Error::new_spanned(f, "#[arg(rest)] must be on the last field"));
+        }
+        out.push(field);
+    }
+    Ok(out)
+}
+
+// Parses `fields` from `rest`, an Option<&str> in scope, then returns `constructor` built from them.
+fn parse_body(fields: &[Field], lt: &Lifetime, constructor: TokenStream2, shape: &Fields) -> TokenStream2 {
+    let mut stmts = vec![];
+    for f in fields {
+        let ident = &f.ident;
+        let ty = f.ty;
+        stmts.push(match (f.rest, f.optional) {
+            (false, false) => quote! {
+                let (#ident, rest) = match rest {
+                    ::std::option::Option::Some(input) => <#ty as ::rustbot::args::Arg<#lt>>::parse_from(input)?,
+                    ::std::option::Option::None => ::rustbot::bail_user!("missing argument"),
+                };
+            },
+            (false, true) => quote! {
+                let (#ident, rest) = match rest {
+                    ::std::option::Option::None | ::std::option::Option::Some("") => (::std::option::Option::None, ::std::option::Option::None),
+                    ::std::option::Option::Some(input) => <#ty as ::rustbot::args::Arg<#lt>>::parse_from(input)?,
+                };
+            },
+            (true, false) => quote! {
+                let (#ident, rest): (#ty, ::std::option::Option<&'s str>) = match rest {
+                    ::std::option::Option::Some(input) if !input.is_empty() => (::std::convert::From::from(input), ::std::option::Option::None),
+                    _ => ::rustbot::bail_user!("missing argument"),
+                };
+            },
+            (true, true) => quote! {
+                let (#ident, rest): (#ty, ::std::option::Option<&'s str>) = (
+                    rest.filter(|input| !input.is_empty()).map(::std::convert::From::from),
+                    ::std::option::Option::None,
+                );
+            },
+        });
+    }
+
+    let idents = fields.iter().map(|f| &f.ident);
+    let value = match shape {
+        Fields::Named(_) => quote!(#constructor { #(#idents),* }),
+        Fields::Unnamed(_) => quote!(#constructor ( #(#idents),* )),
+        Fields::Unit => constructor,
+    };
+    quote! {
+        #(#stmts)*
+        ::std::result::Result::Ok((#value, rest))
+    }
+}
+
+// "(name: description, ...)", or "(description, ...)" for tuple fields
+fn describe_fields(fields: &[Field]) -> TokenStream2 {
+    let parts = fields.iter().map(|f| {
+        let ty = f.ty;
+        let description = match f.rest {
+            false => quote!(<#ty as ::rustbot::args::Arg>::describe_expected()),
+            true if f.optional => quote!(::std::borrow::Cow::<'static, str>::Borrowed("optional rest-of-input")),
+            true => quote!(::std::borrow::Cow::<'static, str>::Borrowed("rest-of-input")),
+        };
+        if f.named {
+            let name = f.ident.to_string();
+            quote!(::std::format!("{}: {}", #name, #description))
+        } else {
+            quote!(::std::string::String::from(#description))
+        }
+    });
+    quote! {
+        {
+            let fields: &[::std::string::String] = &[#(#parts),*];
+            ::std::borrow::Cow::<'static, str>::Owned(::std::format!("({})", fields.join(", ")))
+        }
+    }
+}
+
+fn variant_keyword(v: &syn::Variant) -> Result<String> {
+    let mut keyword = None;
+    for attr in &v.attrs {
+        if !attr.path().is_ident("arg") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                keyword = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("expected `name = \"...\"`"))
+            }
+        })?;
+    }
+    Ok(keyword.unwrap_or_else(|| kebab_case(&v.ident.to_string())))
+}
+
+// "ListAll" => "list-all"
+fn kebab_case(s: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in s.chars().enumerate() {
+        if c.is_uppercase() && i != 0 {
+            out.push('-');
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
+}